@@ -2,18 +2,22 @@ mod power_sink;
 
 use clap::Parser;
 use dsp::num_complex::Complex32;
-use futuresdr::anyhow::Result;
+use futuresdr::anyhow::{bail, Result};
 use futuresdr::blocks::seify::SourceBuilder;
 use futuresdr::blocks::Apply;
 use futuresdr::blocks::Fft;
 use futuresdr::macros::connect;
+use futuresdr::runtime::Block;
 use futuresdr::runtime::Flowgraph;
 use futuresdr::runtime::Runtime;
+use sdr_common::file_source::{self, FileSource};
+use sdr_common::iq_file::FileArgs;
 
 use crate::power_sink::PowerSink;
 
 
 const SAMPLE_RATE: f64 = 2_000_000.;
+const DEFAULT_FREQUENCY: f64 = 100.0;
 const FFT_SIZE: usize = 4096;
 
 #[derive(Parser, Debug)]
@@ -23,21 +27,35 @@ struct Args {
     #[clap(short, long, default_value_t = 50.0)]
     gain: f64,
 
-    /// Measure frequency in MHz
-    #[clap(short, long, default_value_t = 100.0)]
-    frequency: f64,
+    /// Measure frequency in MHz, 100 MHz by default. The file has its own frequency.
+    #[clap(short, long)]
+    frequency: Option<f64>,
+
+    /// Device args, or the IQ file to measure, e.g. "file=capture.cu8,rate=2000000,loop=false"
+    #[clap(short, long, default_value = "")]
+    args: String,
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
-    println!("Freq: {}", args.frequency);
     let mut fg = Flowgraph::new();
-    let src_block = SourceBuilder::new()
-        .frequency(args.frequency * 1e6)
-        .sample_rate(SAMPLE_RATE)
-        .gain(args.gain)
-        .build()?;
+    let src_block = match FileArgs::parse(&args.args) {
+        Some(_) if args.frequency.is_some() => {
+            bail!("--frequency can't be used with a file, set frequency= in the args instead");
+        }
+        Some(file_args) => play_file(&file_args?)?,
+        None => {
+            let frequency = args.frequency.unwrap_or(DEFAULT_FREQUENCY);
+            println!("Freq: {frequency} MHz, rate: {SAMPLE_RATE} S/s");
+            SourceBuilder::new()
+                .args(args.args.as_str())?
+                .frequency(frequency * 1e6)
+                .sample_rate(SAMPLE_RATE)
+                .gain(args.gain)
+                .build()?
+        }
+    };
     let fft = Fft::with_options(
         FFT_SIZE,
         futuresdr::blocks::FftDirection::Forward,
@@ -54,4 +72,15 @@ fn main() -> Result<()> {
     Runtime::new().run(fg)?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+// Source which plays the IQ file in place of the device. Frequency and rate come from the file.
+fn play_file(args: &FileArgs) -> Result<Block> {
+    let (reader, throttle) = file_source::open(args)?;
+    let (frequency, rate) = {
+        let reader = reader.lock().unwrap();
+        (reader.info().frequency, reader.info().sample_rate)
+    };
+    println!("Freq: {} MHz, rate: {} S/s", frequency / 1e6, rate);
+    Ok(FileSource::new(reader, throttle))
+}
@@ -1,17 +1,28 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
 mod radio;
+mod rds;
+mod stereo;
 
 use std::fs;
+use std::path::Path;
+use std::time::Duration;
 
 use eframe::{egui, CreationContext};
 use radio::FMRadio;
+use rds::pty_name;
+use sdr_common::sigmf::SampleFormat;
+use sdr_common::stereo::Region;
 use serde::Deserialize;
 
 
 #[derive(Deserialize)]
 struct Config {
    source: Source,
+   #[serde(default)]
+   audio: Audio,
+   #[serde(default)]
+   recording: Recording,
    bookmarks: Vec<Bookmark>,
 }
 
@@ -22,6 +33,17 @@ struct Source {
     args: String,
 }
 
+#[derive(Deserialize, Default)]
+struct Audio {
+    region: Region,
+}
+
+#[derive(Deserialize)]
+struct Recording {
+    /// Directory of the IQ recordings
+    directory: String,
+}
+
 #[derive(Deserialize)]
 struct Bookmark {
     name: String,
@@ -29,15 +51,21 @@ struct Bookmark {
 }
 
 struct YasaApp {
-    radio: FMRadio,
+    // None when the radio couldn't be started, the error says why
+    radio: Option<FMRadio>,
     current_freq: f64,
     config: Config,
+    record_format: SampleFormat,
+    // Error of the last action
+    error: Option<String>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self { 
             source: Default::default(), 
+            audio: Default::default(),
+            recording: Default::default(),
             bookmarks: Vec::default() 
         }
     }
@@ -53,6 +81,12 @@ impl Default for Source {
     }
 }
 
+impl Default for Recording {
+    fn default() -> Self {
+        Self { directory: ".".to_string() }
+    }
+}
+
 impl<'a> YasaApp {
     fn new(cc: &CreationContext<'_>, config: Config) -> Self {
         // init GUI
@@ -62,19 +96,41 @@ impl<'a> YasaApp {
             .map(|b| b.frequency)
             .unwrap_or(100_000_000.0);
         let src = &config.source;
-        let radio = FMRadio::start(current_freq, src.gain, src.rate, &src.args).unwrap();
+        let (radio, error) = match FMRadio::start(current_freq, src.gain, src.rate, &src.args, config.audio.region) {
+            Ok(radio) => (Some(radio), None),
+            Err(e) => (None, Some(format!("Radio can't be started: {e}"))),
+        };
+        // Played file decides the band
+        let current_freq = radio.as_ref().map_or(current_freq, |radio| radio.frequency());
 
         Self {
             radio,
             current_freq,
             config,
+            record_format: SampleFormat::Cf32,
+            error,
         }
     }
     
     // tune to given frequency
     fn tune_action(&mut self, new_freq: f64) {
-        self.radio.tune_to(new_freq as f64).expect("Tune error"); 
-        self.current_freq = new_freq;
+        let Some(radio) = &mut self.radio else { return };
+        match radio.tune_to(new_freq) {
+            Ok(_) => self.current_freq = new_freq,
+            Err(e) => self.error = Some(e.to_string()),
+        }
+    }
+
+    // Start or stop the IQ recording
+    fn record_action(&mut self) {
+        let Some(radio) = &mut self.radio else { return };
+        let result = if radio.recording().is_some() {
+            radio.stop_recording()
+        } else {
+            let directory = Path::new(&self.config.recording.directory);
+            radio.start_recording(directory, self.record_format).map(|_| ())
+        };
+        self.error = result.err().map(|e| e.to_string());
     }
 }
 
@@ -101,7 +157,46 @@ impl eframe::App for YasaApp {
         egui::CentralPanel::default()
             .show(ctx, |ui| {
                 ui.label(format!("f =  {}", self.current_freq));
+                if let Some(error) = &self.error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+                let Some(radio) = &self.radio else { return };
+                let (recording, is_stereo, rds) = (radio.recording(), radio.is_stereo(), radio.rds());
+                ui.horizontal(|ui| {
+                    let record_label = if recording.is_some() { "Stop recording" } else { "Record" };
+                    if ui.button(record_label).clicked() {
+                        self.record_action();
+                    }
+                    ui.add_enabled_ui(recording.is_none(), |ui| {
+                        egui::ComboBox::from_id_source("record_format")
+                            .selected_text(self.record_format.name())
+                            .show_ui(ui, |ui| {
+                                for format in SampleFormat::ALL {
+                                    ui.selectable_value(&mut self.record_format, format, format.name());
+                                }
+                            });
+                    });
+                    if let Some((path, seconds)) = recording {
+                        ui.label(format!("{} {seconds:.1} s", path.display()));
+                    }
+                });
+                ui.label(if is_stereo { "Stereo" } else { "Mono" });
+
+                ui.heading(&rds.ps);
+                ui.label(&rds.radiotext);
+                if let Some(pi) = rds.pi {
+                    ui.label(format!("PI: {pi:04X}"));
+                }
+                if let Some(pty) = rds.pty {
+                    ui.label(format!("PTY: {}", pty_name(pty)));
+                }
+                if let Some(time) = rds.time {
+                    ui.label(format!("Time: {time}"));
+                }
             });
+
+        // Keep the stereo indicator and RDS up to date
+        ctx.request_repaint_after(Duration::from_millis(250));
     }
 }
 
@@ -1,24 +1,31 @@
 //! A simple FM receiver that you can tune to nearby radio stations
 //!
 //! When you run the example, it will build a flowgraph consisting of the following blocks:
-//! * SeifySource: Gets data from your SDR
+//! * SeifySource: Gets data from your SDR, or FileSource plays the IQ file in its place
+//! * Shift: Moves the station to zero frequency
 //! * Demodulator: Demodulates the FM signal
+//! * StereoBlock: Decodes left and right channel when the pilot is received
+//! * RdsBlock: Decodes the station name and radiotext
 //! * AudioSink: Plays the demodulated signal on your device
+//! * IqRecorder: Records the received band in the SigMF format
 //!
-//! After giving it some time to start up the SDR, it enters a loop where you will
-//! be periodically asked to enter a new frequency that the SDR will be tuned to.
-//! **Watch out** though: Some frequencies (very high or very low) might be unsupported
-//! by your SDR and may cause a crash.
+//! The SDR is tuned next to the station, so the DC spike is out of the channel.
+//! Played file can't be retuned, the station can be chosen only inside its band.
 
 
-use dsp::core::{freq_shift::FrequencyShift, fm::QuadratureDetector};
-use futuresdr::anyhow::Result;
+use std::f32::consts::PI;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use dsp::core::fm::QuadratureDetector;
+use futuresdr::anyhow::{bail, Result};
 use futuresdr::async_io;
 use futuresdr::blocks::audio::AudioSink;
 use futuresdr::blocks::seify::SourceBuilder;
 use futuresdr::blocks::Apply;
 use futuresdr::blocks::FirBuilder;
-use futuresdr::futuredsp::firdes;
 use futuresdr::log;
 use futuresdr::macros::connect;
 use futuresdr::num_complex::Complex32;
@@ -26,19 +33,53 @@ use futuresdr::num_integer::gcd;
 use futuresdr::runtime::Block;
 use futuresdr::runtime::Pmt;
 use futuresdr::runtime::{Flowgraph, FlowgraphHandle, Runtime};
+use sdr_common::file_source::{self, FileSource, SharedReader};
+use sdr_common::iq_file::FileArgs;
+use sdr_common::iq_recorder::{IqRecorder, SharedRecorder};
+use sdr_common::shift::{SharedOffset, Shift};
+use sdr_common::sigmf::{self, Recording, SampleFormat, SigmfWriter};
+use sdr_common::stereo::Region;
+
+use crate::rds::{RdsBlock, RdsInfo};
+use crate::stereo::StereoBlock;
+
+// Maximum frequency deviation of the broadcast FM
+const MAX_DEVIATION: f32 = 75_000.0;
+// Part of the sample rate passed by the anti-aliasing filter of the SDR
+const USABLE_BAND: f64 = 0.9;
 
 
 pub struct FMRadio {
     handle: FlowgraphHandle,
     source: SourceBlock,
+    is_stereo: Arc<AtomicBool>,
+    rds: Arc<Mutex<RdsInfo>>,
+    recorder: SharedRecorder,
+    // Offset of the station from the center
+    offset: SharedOffset,
+    // Hardware frequency or the frequency of the file
+    center: f64,
+    frequency: f64,
+    // Metadata of the IQ recordings
+    rate: f64,
+    gain: f64,
+    args: String,
 }
 
 enum SourceBlock {
     Seify { id: usize, freq_offset: f64, freq_port_id: usize },
+    // File can't be retuned, the station moves only inside its band
+    File { max_offset: f64 },
 }
 
 impl FMRadio {
-    pub fn start(frequency: f64, gain: f64, rate: f64, args: &str) -> Result<Self> {
+    pub fn start(frequency: f64, gain: f64, rate: f64, args: &str, region: Region) -> Result<Self> {
+        // File replaces the device and decides the sample rate and frequency
+        let file = match FileArgs::parse(args) {
+            Some(file_args) => Some(file_source::open(&file_args?)?),
+            None => None,
+        };
+        let rate = file.as_ref().map_or(rate, |(reader, _)| reader.lock().unwrap().info().sample_rate);
         let freq_offset = rate / 4.0;
 
         let mut audio_rates = AudioSink::supported_sample_rates();
@@ -47,9 +88,6 @@ impl FMRadio {
         let audio_rate = audio_rates[0];
         log::info!("Selected Audio Rate {audio_rate:?} from supported {audio_rates:?}");
 
-        // Create a new Seify SDR block with the given parameters
-        let src = FMRadio::seify(frequency + freq_offset, gain, rate, args).expect("Can't init Seify");
-
         // Downsample before demodulation
         // why do we need this?
         let mut audio_mult = 5;
@@ -58,35 +96,70 @@ impl FMRadio {
         }
         log::info!("Audio Mult {audio_mult:?}");
 
-        let shift = FMRadio::shift(freq_offset as f32, rate as usize);
+        // Create a new Seify SDR block with the given parameters
+        let max_offset = FMRadio::max_offset(rate, (audio_rate * audio_mult) as f64);
+        let (src, center, frequency) = match &file {
+            Some((reader, throttle)) => {
+                // Station stays in the band, below the center when it isn't
+                let center = reader.lock().unwrap().info().frequency;
+                let frequency = if (frequency - center).abs() <= max_offset { frequency } else { center - freq_offset };
+                (FileSource::new(reader.clone(), *throttle), center, frequency)
+            }
+            None => (FMRadio::seify(frequency + freq_offset, gain, rate, args)?, frequency + freq_offset, frequency),
+        };
+
+        let offset = Arc::new(Mutex::new(frequency - center));
+        let shift = Shift::new(offset.clone(), rate);
 
         let interp = (audio_rate * audio_mult) as usize;
         let decim = rate as usize;
         log::info!("interp {interp}   decim {decim}");
         let resamp1 = FirBuilder::new_resampling::<Complex32, Complex32>(interp, decim);
 
-        let demod = FMRadio::fm_demodulation();
+        let demod = FMRadio::fm_demodulation(interp as f32);
 
-        let audio_filter = FMRadio::audio_filter(audio_rate + audio_mult, audio_mult as usize);
+        let is_stereo = Arc::new(AtomicBool::new(false));
+        let stereo = StereoBlock::new(interp as f32, audio_mult as usize, region.deemphasis(), is_stereo.clone());
 
-        // Single-channel `AudioSink` with the downsampled rate (sample_rate / (8*5) = 48_000)
-        let snk = AudioSink::new(audio_rate, 1);
+        let rds = Arc::new(Mutex::new(RdsInfo::default()));
+        let rds_decoder = RdsBlock::new(interp as f32, rds.clone());
+
+        // Stereo `AudioSink` with the downsampled rate (sample_rate / (8*5) = 48_000)
+        let snk = AudioSink::new(audio_rate, 2);
                 
         // Save ports for connectiong to the blocks
-        let freq_port_id = src.message_input_name_to_id("freq").unwrap();
+        let freq_port_id = src.message_input_name_to_id("freq");
 
         // Create the `Flowgraph` and add `Block`s
         let runtime = Runtime::new();
         let mut fg = Flowgraph::new();
-        connect!(fg, src > shift > resamp1 > demod > audio_filter > snk.in;);
+        // Raw IQ is written only while there is a recording
+        let recorder = Arc::new(Mutex::new(None));
+        let iq_recorder = IqRecorder::new(recorder.clone());
+
+        connect!(fg, src > shift > resamp1 > demod > stereo > snk.in;
+            demod > rds_decoder;
+            src > iq_recorder;);
 
         // Start the flowgraph and save the handle
         let (_res, handle) = runtime.start_sync(fg);
-        let source_block = SourceBlock::Seify { id: src, freq_offset, freq_port_id };
+        let source_block = match freq_port_id {
+            Some(freq_port_id) => SourceBlock::Seify { id: src, freq_offset, freq_port_id },
+            None => SourceBlock::File { max_offset },
+        };
         
         Ok(Self { 
             handle, 
             source: source_block,
+            is_stereo,
+            rds,
+            recorder,
+            offset,
+            center,
+            frequency,
+            rate,
+            gain,
+            args: args.to_string(),
         })
 
     }
@@ -100,11 +173,71 @@ impl FMRadio {
                     freq_port_id,
                     Pmt::F64(new_freq + freq_offset),
                 ))?;
+                if let Some(writer) = self.recorder.lock().unwrap().as_mut() {
+                    writer.retune(new_freq + freq_offset)?;
+                }
+                self.center = new_freq + freq_offset;
+            }
+            SourceBlock::File { max_offset } => {
+                if (new_freq - self.center).abs() > max_offset {
+                    bail!("{} MHz is outside of the played file", new_freq / 1e6);
+                }
+                log::info!("Tune to: {}", new_freq);
+                *self.offset.lock().unwrap() = new_freq - self.center;
             }
         }
+        self.frequency = new_freq;
+        *self.rds.lock().unwrap() = RdsInfo::default();
+        Ok(())
+    }
+
+    /// Start recording the received band to a new SigMF file in the directory.
+    /// Returns the path of the recording without the extensions.
+    pub fn start_recording(&mut self, directory: &Path, format: SampleFormat) -> Result<PathBuf> {
+        self.stop_recording()?;
+        let frequency = self.center;
+        let recording = Recording {
+            sample_rate: self.rate,
+            hw: self.args.clone(),
+            description: format!("Gain {} dB", self.gain),
+            recorder: "fmradio".to_string(),
+        };
+        let path = directory.join(sigmf::file_name("fmradio", SystemTime::now(), frequency));
+        log::info!("Record IQ to {}", path.display());
+        let writer = SigmfWriter::create(&path, format, recording, frequency)?;
+        *self.recorder.lock().unwrap() = Some(writer);
+        Ok(path)
+    }
+
+    /// Finish the IQ recording, if there is one
+    pub fn stop_recording(&mut self) -> Result<()> {
+        if let Some(writer) = self.recorder.lock().unwrap().take() {
+            writer.finish()?;
+        }
         Ok(())
     }
 
+    /// Path of the IQ recording and its length in seconds, none when not recording
+    pub fn recording(&self) -> Option<(PathBuf, f64)> {
+        self.recorder.lock().unwrap().as_ref()
+            .map(|writer| (writer.path().to_path_buf(), writer.samples() as f64 / writer.sample_rate()))
+    }
+
+    /// Tuned frequency
+    pub fn frequency(&self) -> f64 {
+        self.frequency
+    }
+
+    /// True when the stereo pilot is received
+    pub fn is_stereo(&self) -> bool {
+        self.is_stereo.load(Ordering::Relaxed)
+    }
+
+    /// Data decoded from RDS
+    pub fn rds(&self) -> RdsInfo {
+        self.rds.lock().unwrap().clone()
+    }
+
     // Build Seify block.
     fn seify(frequency: f64, gain: f64, rate: f64, args: &str) -> Result<Block> {
         SourceBuilder::new()
@@ -115,41 +248,21 @@ impl FMRadio {
             .build()
     } 
 
-    // Shift signal by a given offset
-    fn shift(freq_offset: f32, rate: usize) -> Block {
-        // let mut offset = Sine::new(freq_offset, rate);
-        let mut shifter = FrequencyShift::new(freq_offset, rate);
-        let block = Apply::new(move |v: &Complex32| -> Complex32 {
-            shifter.process_sample(v)
-        });
-        
-        block
+    // Largest distance of the station from the center, so the channel stays in the band
+    fn max_offset(rate: f64, channel_rate: f64) -> f64 {
+        (USABLE_BAND * rate / 2.0 - channel_rate / 2.0).max(rate / 4.0)
     }
 
     // Demodulation block using the conjugate delay method
     // See https://en.wikipedia.org/wiki/Detector_(radio)#Quadrature_detector
-    fn fm_demodulation() -> Block {
+    // Output is scaled so the full deviation is 1.0
+    fn fm_demodulation(sample_rate: f32) -> Block {
         let mut demod = QuadratureDetector::new();
+        let gain = sample_rate / (2.0 * PI * MAX_DEVIATION);
         let demod = Apply::new(move |v: &Complex32| -> f32 {
-            demod.process_sample(v)
+            gain * demod.process_sample(v)
         });
 
         demod
     }
-
-    // Design filter for the audio and decimate by 5.
-    // Ideally, this should be a FM de-emphasis filter, but the following works.
-    fn audio_filter(sample_rate: u32, decim: usize) -> Block {
-        let cutoff = 2_000.0 / sample_rate as f64;
-        let transition = 10_000.0 / sample_rate as f64;
-        log::info!("cutoff {cutoff}   transition {transition}");
-        let audio_filter_taps = firdes::kaiser::lowpass::<f32>(cutoff, transition, 0.1);
-        let resamp = FirBuilder::new_resampling_with_taps::<f32, f32, _, _>(
-            1,
-            decim,
-            audio_filter_taps,
-        );
-
-        resamp
-    }
 }
\ No newline at end of file
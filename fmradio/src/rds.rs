@@ -0,0 +1,77 @@
+//! Block running the RDS decoder from `sdr_common` on the FM multiplex signal.
+//!
+//! The block is a sink for the multiplex signal. Decoded data is shared with the GUI.
+
+use std::sync::{Arc, Mutex};
+
+use futuresdr::anyhow::Result;
+use futuresdr::macros::async_trait;
+use futuresdr::runtime::BlockMeta;
+use futuresdr::runtime::BlockMetaBuilder;
+use futuresdr::runtime::Kernel;
+use futuresdr::runtime::MessageIo;
+use futuresdr::runtime::MessageIoBuilder;
+use futuresdr::runtime::StreamIo;
+use futuresdr::runtime::StreamIoBuilder;
+use futuresdr::runtime::WorkIo;
+use futuresdr::runtime::{Block, TypedBlock};
+use sdr_common::rds::RdsDecoder;
+
+pub use sdr_common::rds::{pty_name, RdsInfo};
+
+
+/// Decode RDS from the FM multiplex signal.
+///
+/// # Inputs
+///
+/// `in`: Output of the FM demodulator scaled so the full deviation is 1.0
+pub struct RdsBlock {
+    decoder: RdsDecoder,
+    info: Arc<Mutex<RdsInfo>>,
+}
+
+impl RdsBlock {
+    /// `rate` is the rate of the multiplex signal. Decoded data is stored in `info`.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(rate: f32, info: Arc<Mutex<RdsInfo>>) -> Block {
+        Block::from_typed(Self::new_typed(rate, info))
+    }
+
+    pub fn new_typed(rate: f32, info: Arc<Mutex<RdsInfo>>) -> TypedBlock<Self> {
+        TypedBlock::new(
+            BlockMetaBuilder::new("RdsBlock").build(),
+            StreamIoBuilder::new().add_input::<f32>("in").build(),
+            MessageIoBuilder::<Self>::new().build(),
+            RdsBlock { decoder: RdsDecoder::new(rate), info },
+        )
+    }
+}
+
+#[doc(hidden)]
+#[async_trait]
+impl Kernel for RdsBlock {
+    async fn work(
+        &mut self,
+        io: &mut WorkIo,
+        sio: &mut StreamIo,
+        _mio: &mut MessageIo<Self>,
+        _meta: &mut BlockMeta,
+    ) -> Result<()> {
+        let i = sio.input(0).slice::<f32>();
+        let mut decoded = false;
+        for &x in i {
+            decoded |= self.decoder.process_sample(x);
+        }
+        sio.input(0).consume(i.len());
+
+        if decoded {
+            *self.info.lock().unwrap() = self.decoder.info().clone();
+        }
+
+        if sio.input(0).finished() {
+            io.finished = true;
+        }
+
+        Ok(())
+    }
+}
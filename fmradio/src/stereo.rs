@@ -0,0 +1,97 @@
+//! Block running the FM stereo decoder from `sdr_common` on the multiplex signal.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use futuresdr::anyhow::Result;
+use futuresdr::macros::async_trait;
+use futuresdr::runtime::BlockMeta;
+use futuresdr::runtime::BlockMetaBuilder;
+use futuresdr::runtime::Kernel;
+use futuresdr::runtime::MessageIo;
+use futuresdr::runtime::MessageIoBuilder;
+use futuresdr::runtime::StreamIo;
+use futuresdr::runtime::StreamIoBuilder;
+use futuresdr::runtime::WorkIo;
+use futuresdr::runtime::{Block, TypedBlock};
+use sdr_common::stereo::StereoDecoder;
+
+
+/// Decode stereo from the FM multiplex signal.
+///
+/// # Inputs
+///
+/// `in`: Output of the FM demodulator scaled so the full deviation is 1.0
+///
+/// # Outputs
+///
+/// `out`: Interleaved left and right audio
+pub struct StereoBlock {
+    decoder: StereoDecoder,
+    is_stereo: Arc<AtomicBool>,
+    buffer: Vec<f32>,
+}
+
+impl StereoBlock {
+    /// `rate` is the rate of the multiplex signal. Audio is decimated by `audio_decim`.
+    /// `tau` is the de-emphasis time constant in seconds.
+    /// `is_stereo` is set when the pilot is received.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(rate: f32, audio_decim: usize, tau: f32, is_stereo: Arc<AtomicBool>) -> Block {
+        Block::from_typed(Self::new_typed(rate, audio_decim, tau, is_stereo))
+    }
+
+    pub fn new_typed(rate: f32, audio_decim: usize, tau: f32, is_stereo: Arc<AtomicBool>) -> TypedBlock<Self> {
+        TypedBlock::new(
+            BlockMetaBuilder::new("StereoBlock").build(),
+            StreamIoBuilder::new()
+                .add_input::<f32>("in")
+                .add_output::<f32>("out")
+                .build(),
+            MessageIoBuilder::<Self>::new().build(),
+            StereoBlock {
+                decoder: StereoDecoder::new(rate, audio_decim, tau),
+                is_stereo,
+                buffer: vec![],
+            },
+        )
+    }
+}
+
+#[doc(hidden)]
+#[async_trait]
+impl Kernel for StereoBlock {
+    async fn work(
+        &mut self,
+        io: &mut WorkIo,
+        sio: &mut StreamIo,
+        _mio: &mut MessageIo<Self>,
+        _meta: &mut BlockMeta,
+    ) -> Result<()> {
+        let i = sio.input(0).slice::<f32>();
+        let o = sio.output(0).slice::<f32>();
+
+        // Decode only when there is a room for the audio, so the buffer doesn't grow
+        if self.buffer.len() < o.len() {
+            for &x in i {
+                if let Some((left, right)) = self.decoder.process_sample(x) {
+                    self.buffer.push(left);
+                    self.buffer.push(right);
+                }
+            }
+            sio.input(0).consume(i.len());
+            self.is_stereo.store(self.decoder.is_stereo(), Ordering::Relaxed);
+        }
+
+        let n = self.buffer.len().min(o.len());
+        o[..n].copy_from_slice(&self.buffer[..n]);
+        self.buffer.drain(..n);
+        sio.output(0).produce(n);
+
+        if sio.input(0).finished() && self.buffer.is_empty() {
+            io.finished = true;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,48 @@
+// Compare single-threaded and parallel parsing of a large synthetic sweep file.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use power_sweep::dataframe::DataFrame;
+
+
+const SWEEPS: usize = 500;
+const STEPS: usize = 8;
+const BINS: usize = 256;
+
+// Build hackrf_sweep like output with STEPS records of BINS samples per sweep.
+fn synthetic_csv() -> String {
+    let mut csv = String::new();
+    for sweep in 0..SWEEPS {
+        let time = format!("{:02}:{:02}:{:02}", 16 + sweep / 3600, (sweep / 60) % 60, sweep % 60);
+        for step in 0..STEPS {
+            let freq_low = 430_000_000 + step * 2_500_000;
+            csv.push_str(&format!("2024-02-03, {}, {}, {}, 9765.62, 704", time, freq_low, freq_low + 2_500_000));
+            for bin in 0..BINS {
+                let v = -45.0 + ((sweep * 31 + step * 7 + bin) % 97) as f32 * 0.05;
+                csv.push_str(&format!(", {:.2}", v));
+            }
+            csv.push('\n');
+        }
+    }
+    csv
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let csv = synthetic_csv();
+    let mut group = c.benchmark_group("parse");
+    group.sample_size(10);
+    group.bench_function("sequential", |b| b.iter(|| DataFrame::from_string_sequential(&csv)));
+    group.bench_function("parallel", |b| b.iter(|| DataFrame::from_string(&csv)));
+    group.finish();
+}
+
+fn bench_bin_stats(c: &mut Criterion) {
+    let df = DataFrame::from_string(&synthetic_csv());
+    let mut group = c.benchmark_group("bin_stats");
+    group.sample_size(10);
+    group.bench_function("sequential", |b| b.iter(|| df.bin_stats_sequential()));
+    group.bench_function("parallel", |b| b.iter(|| df.bin_stats()));
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse, bench_bin_stats);
+criterion_main!(benches);
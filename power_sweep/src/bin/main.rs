@@ -1,4 +1,116 @@
+use std::fs;
+use std::path::PathBuf;
 
-fn main() {
+use chrono_tz::Tz;
+use clap::{Parser, Subcommand};
+use power_sweep::classify::Classifier;
+use power_sweep::dataframe::DataFrame;
+use power_sweep::emission::{self, EmissionOptions};
+use power_sweep::noise::{NoiseFloor, NoiseFloorOptions};
+use power_sweep::periodicity::bin_periodicity;
 
-}
\ No newline at end of file
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// CSV file created by hackrf_sweep, soapy_power or rtl_power
+    file: PathBuf,
+
+    /// Time zone of the timestamps in the file, e.g. Europe/Warsaw
+    #[clap(long, default_value = "UTC")]
+    tz: Tz,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Timeline of the noise floor steps and broadband rises
+    Events {
+        /// Width of the band segment in Hz
+        #[clap(long, default_value_t = 1_000_000.0)]
+        segment: f64,
+
+        /// Minimal change of the noise floor in dB
+        #[clap(long, default_value_t = 3.0)]
+        threshold: f32,
+
+        /// Number of sweeps averaged before and after the change
+        #[clap(long, default_value_t = 3)]
+        window: usize,
+    },
+    /// Detected emissions with their features and class
+    Emissions {
+        /// Level above the noise floor in dB
+        #[clap(long, default_value_t = 10.0)]
+        threshold: f32,
+    },
+    /// Repetition period of every frequency bin
+    Periodicity {
+        /// Level above the noise floor in dB
+        #[clap(long, default_value_t = 10.0)]
+        threshold: f32,
+
+        /// Minimal confidence of the period (0.0-1.0)
+        #[clap(long, default_value_t = 0.5)]
+        confidence: f32,
+    },
+}
+
+fn main() -> std::io::Result<()> {
+    let args = Args::parse();
+    let data = fs::read_to_string(&args.file)?;
+    let df = DataFrame::from_string(&data).with_time_zone(args.tz);
+    if df.skipped_rows() > 0 {
+        eprintln!("Skipped {} invalid rows", df.skipped_rows());
+    }
+
+    match args.command {
+        Command::Events { segment, threshold, window } => {
+            let options = NoiseFloorOptions { segment_width: segment, threshold, window, ..Default::default() };
+            let events = NoiseFloor::from_dataframe(&df, &options).events(&options);
+            for event in events {
+                println!("{}  {:<14}  {:.3}-{:.3} MHz  {:+.1} dB",
+                    event.time.to_rfc3339(),
+                    event.kind.name(),
+                    event.freq_low / 1e6,
+                    event.freq_high / 1e6,
+                    event.delta);
+            }
+        }
+        Command::Emissions { threshold } => {
+            let options = EmissionOptions { threshold, ..Default::default() };
+            let classifier = Classifier::default();
+            println!("{:>11} {:>11} {:>9} {:>7} {:>6} {:>8} {:>8} {:>5} {:>8}  class",
+                "low MHz", "high MHz", "bw kHz", "peak dB", "duty", "burst s", "period s", "conf", "drift");
+            for e in emission::detect(&df, &options) {
+                let (period, confidence) = e.periodicity
+                    .map(|p| (format!("{:.1}", p.period), format!("{:.2}", p.confidence)))
+                    .unwrap_or(("-".to_owned(), "-".to_owned()));
+                println!("{:>11.4} {:>11.4} {:>9.1} {:>7.1} {:>5.0}% {:>8.1} {:>8} {:>5} {:>8.1}  {}",
+                    e.freq_low / 1e6,
+                    e.freq_high / 1e6,
+                    e.bandwidth / 1e3,
+                    e.peak_power,
+                    e.duty_cycle * 100.0,
+                    e.burst_length,
+                    period,
+                    confidence,
+                    e.drift,
+                    classifier.classify(&e).name());
+            }
+        }
+        Command::Periodicity { threshold, confidence } => {
+            let options = EmissionOptions { threshold, ..Default::default() };
+            println!("{:>11} {:>8} {:>5}", "freq MHz", "period s", "conf");
+            for (bin, p) in bin_periodicity(&df, &options).iter().enumerate() {
+                if let Some(p) = p.filter(|p| p.confidence >= confidence) {
+                    println!("{:>11.4} {:>8.1} {:>5.2}", df.bin_frequency(bin) / 1e6, p.period, p.confidence);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
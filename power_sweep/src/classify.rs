@@ -0,0 +1,104 @@
+// Rule based classification of the detected emissions.
+//
+// Rules are checked in order and the first one which matches gives the label.
+
+use crate::emission::Emission;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmissionClass {
+    ContinuousCarrier,
+    PeriodicBeacon,
+    BurstyData,
+    PushToTalk,
+    WidebandNoise,
+}
+
+/// Thresholds used by the classification rules
+pub struct Classifier {
+    /// Emissions wider than this (in Hz) are wideband noise
+    pub wideband: f64,
+    /// Emissions present in at least this part of the sweeps are carriers
+    pub continuous_duty: f32,
+    /// Minimal confidence of the repetition period for the beacon
+    pub periodic: f32,
+    /// Bursts lasting at least this number of sweeps look like voice transmissions
+    pub voice_sweeps: f32,
+}
+
+impl Default for Classifier {
+    fn default() -> Self {
+        Self {
+            wideband: 200_000.0,
+            continuous_duty: 0.9,
+            periodic: 0.7,
+            voice_sweeps: 2.0,
+        }
+    }
+}
+
+impl EmissionClass {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EmissionClass::ContinuousCarrier => "carrier",
+            EmissionClass::PeriodicBeacon => "beacon",
+            EmissionClass::BurstyData => "packet",
+            EmissionClass::PushToTalk => "voice",
+            EmissionClass::WidebandNoise => "wideband-noise",
+        }
+    }
+}
+
+impl Classifier {
+    pub fn classify(&self, emission: &Emission) -> EmissionClass {
+        if emission.bandwidth >= self.wideband {
+            EmissionClass::WidebandNoise
+        } else if emission.duty_cycle >= self.continuous_duty {
+            EmissionClass::ContinuousCarrier
+        } else if emission.periodicity.is_some_and(|p| p.confidence >= self.periodic) {
+            EmissionClass::PeriodicBeacon
+        } else if emission.burst_sweeps >= self.voice_sweeps {
+            EmissionClass::PushToTalk
+        } else {
+            EmissionClass::BurstyData
+        }
+    }
+}
+
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::periodicity::Periodicity;
+
+    fn emission(bandwidth: f64, duty_cycle: f32, burst_sweeps: f32, confidence: f32) -> Emission {
+        Emission {
+            freq_low: 433_900_000.0,
+            freq_high: 433_900_000.0 + bandwidth,
+            first_seen: Default::default(),
+            last_seen: Default::default(),
+            peak_power: -40.0,
+            bandwidth,
+            duty_cycle,
+            burst_sweeps,
+            burst_length: burst_sweeps as f64 * 10.0,
+            periodicity: Some(Periodicity { period: 60.0, confidence }),
+            drift: 0.0,
+            activity: vec![],
+        }
+    }
+
+    #[test]
+    fn test_classify() {
+        let classifier = Classifier::default();
+
+        assert_eq!(classifier.classify(&emission(500_000.0, 0.5, 1.0, 0.0)), EmissionClass::WidebandNoise);
+        assert_eq!(classifier.classify(&emission(10_000.0, 1.0, 12.0, 0.0)), EmissionClass::ContinuousCarrier);
+        assert_eq!(classifier.classify(&emission(10_000.0, 0.1, 1.0, 0.9)), EmissionClass::PeriodicBeacon);
+        assert_eq!(classifier.classify(&emission(12_500.0, 0.3, 4.0, 0.2)), EmissionClass::PushToTalk);
+        assert_eq!(classifier.classify(&emission(20_000.0, 0.05, 1.0, 0.1)), EmissionClass::BurstyData);
+    }
+}
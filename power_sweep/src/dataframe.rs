@@ -1,19 +1,29 @@
 // Read CSV file aith the output from the `hackrf_sweep`, `soapy_power`, or `rtl_power` output.
 
-use chrono::{NaiveDate, NaiveTime};
+use std::ops::Range;
+
+use chrono::{DateTime, Duration, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
 use csv::ReaderBuilder;
+use rayon::prelude::*;
 use serde::Deserialize;
 
 
+/// Number of frequency bins aggregated by a single task in the parallel path.
+const BINS_PER_TASK: usize = 256;
+
 pub struct DataFrame {
     records: Vec<CsvRecord>,
     freq_low: u64,
     freq_high: u64,
     freq_step: f32,
     sweep_steps: usize,
+    time_zone: Tz,
+    // Rows which couldn't be parsed or have the invalid frequency range
+    skipped: usize,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
 pub struct CsvRecord {
     #[serde(with = "custom_date")]
     pub date: NaiveDate,
@@ -26,37 +36,73 @@ pub struct CsvRecord {
     pub samples: Vec<f32>,
 }
 
+/// Power statistics of a single frequency bin collected over all sweeps.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinStats {
+    /// Lower edge of the bin in Hz
+    pub frequency: f64,
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    /// Number of samples which contributed to this bin
+    pub count: usize,
+}
+
+/// Single pass over the whole frequency range.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sweep {
+    /// Time of the first record
+    pub start: DateTime<Utc>,
+    /// Time of the last record
+    pub end: DateTime<Utc>,
+    /// Indices of the records which belong to this sweep
+    pub records: Range<usize>,
+}
+
 mod custom_date {
     use chrono::naive::NaiveDate;
-    use serde::{Deserialize, Deserializer};
+    use serde::{de::Error, Deserialize, Deserializer};
 
     pub fn deserialize<'de, D>(deserializer: D) -> Result<NaiveDate, D::Error>
     where D: Deserializer<'de> {
         let s = String::deserialize(deserializer)?;
-        Ok(NaiveDate::parse_from_str(&s, "%Y-%m-%d").unwrap())
+        NaiveDate::parse_from_str(&s, "%Y-%m-%d").map_err(D::Error::custom)
     }
 }
 
 mod custom_time {
     use chrono::naive::NaiveTime;
-    use serde::{Deserialize, Deserializer};
+    use serde::{de::Error, Deserialize, Deserializer};
 
     pub fn deserialize<'de, D>(deserializer: D) -> Result<NaiveTime, D::Error>
     where D: Deserializer<'de> {
         let s = String::deserialize(deserializer)?;
-        Ok(NaiveTime::parse_from_str(&s, "%H:%M:%S%.f").unwrap())
+        NaiveTime::parse_from_str(&s, "%H:%M:%S%.f").map_err(D::Error::custom)
     }
 }
 
 impl DataFrame {
+    /// Parse CSV data using all available cores.
+    /// The input is split at line boundaries, so the result is identical to `from_string_sequential`.
     pub fn from_string(data: &str) -> Self {
-        let mut rdr = ReaderBuilder::new()
-            .has_headers(false)
-            .trim(csv::Trim::All)
-            .from_reader(data.as_bytes());
-    
-        let records: Vec<CsvRecord> = rdr.deserialize().flatten().collect();
+        let chunks = split_lines(data, rayon::current_num_threads() * 4);
+        let mut records = vec![];
+        let mut skipped = 0;
+        for (chunk_records, chunk_skipped) in chunks.par_iter().map(|chunk| parse_records(chunk)).collect::<Vec<_>>() {
+            records.extend(chunk_records);
+            skipped += chunk_skipped;
+        }
 
+        Self { skipped, ..Self::from_records(records) }
+    }
+
+    /// Parse CSV data on the current thread.
+    pub fn from_string_sequential(data: &str) -> Self {
+        let (records, skipped) = parse_records(data);
+        Self { skipped, ..Self::from_records(records) }
+    }
+
+    pub fn from_records(records: Vec<CsvRecord>) -> Self {
         let default = CsvRecord::default();
         let first = records.first().unwrap_or(&default);
         let sweep_steps = records.iter()
@@ -74,21 +120,260 @@ impl DataFrame {
             freq_high,
             freq_step,
             sweep_steps,
+            time_zone: Tz::UTC,
+            skipped: 0,
+        }
+    }
+
+    /// Set the time zone in which the timestamps were written.
+    /// `hackrf_sweep` and `rtl_power` use the local time of the capturing machine.
+    pub fn with_time_zone(mut self, time_zone: Tz) -> Self {
+        self.time_zone = time_zone;
+        self
+    }
+
+    pub fn time_zone(&self) -> Tz {
+        self.time_zone
+    }
+
+    pub fn records(&self) -> &[CsvRecord] {
+        &self.records
+    }
+
+    /// Number of the rows which were left out, because they couldn't be parsed
+    /// or their upper frequency is below the lower one
+    pub fn skipped_rows(&self) -> usize {
+        self.skipped
+    }
+
+    pub fn freq_low(&self) -> u64 {
+        self.freq_low
+    }
+
+    pub fn freq_high(&self) -> u64 {
+        self.freq_high
+    }
+
+    pub fn freq_step(&self) -> f32 {
+        self.freq_step
+    }
+
+    /// Number of records which make a single sweep over the whole frequency range
+    pub fn sweep_steps(&self) -> usize {
+        self.sweep_steps
+    }
+
+    /// Time of every record converted to UTC.
+    /// Timestamps are made monotonic: if the time goes back across midnight without the date change,
+    /// then the next day is assumed. Local times repeated by the DST change are resolved using
+    /// the previous record.
+    pub fn timestamps(&self) -> Vec<DateTime<Utc>> {
+        let mut timestamps: Vec<DateTime<Utc>> = Vec::with_capacity(self.records.len());
+        let mut prev_local: Option<NaiveDateTime> = None;
+        for record in &self.records {
+            let mut local = record.timestamp();
+            if let Some(prev) = prev_local {
+                while local + Duration::hours(12) < prev {
+                    local += Duration::days(1);
+                }
+            }
+            let utc = self.to_utc(local, timestamps.last().copied());
+            timestamps.push(utc);
+            prev_local = Some(local);
+        }
+        timestamps
+    }
+
+    /// Split records into sweeps. New sweep starts when the frequency goes back.
+    pub fn sweeps(&self) -> Vec<Sweep> {
+        let timestamps = self.timestamps();
+        let mut sweeps: Vec<Sweep> = vec![];
+        for (i, record) in self.records.iter().enumerate() {
+            let new_sweep = i == 0 || record.freq_low <= self.records[i - 1].freq_low;
+            match sweeps.last_mut() {
+                Some(sweep) if !new_sweep => {
+                    sweep.end = timestamps[i];
+                    sweep.records.end = i + 1;
+                }
+                _ => sweeps.push(Sweep { start: timestamps[i], end: timestamps[i], records: i..i + 1 }),
+            }
+        }
+        sweeps
+    }
+
+    /// Power in every bin of the given sweep. Bins without data are NaN.
+    pub fn sweep_powers(&self, sweep: &Sweep) -> Vec<f32> {
+        let mut powers = vec![f32::NAN; self.num_bins()];
+        for record in &self.records[sweep.records.clone()] {
+            let Some(first) = self.bin_index(record) else { continue };
+            for (bin, &v) in record.bins().iter().enumerate() {
+                if let Some(p) = powers.get_mut(first + bin) {
+                    *p = v;
+                }
+            }
         }
+        powers
+    }
+
+    fn to_utc(&self, local: NaiveDateTime, prev: Option<DateTime<Utc>>) -> DateTime<Utc> {
+        match self.time_zone.from_local_datetime(&local) {
+            LocalResult::Single(t) => t.with_timezone(&Utc),
+            LocalResult::Ambiguous(early, late) => {
+                let early = early.with_timezone(&Utc);
+                match prev {
+                    Some(p) if early < p => late.with_timezone(&Utc),
+                    _ => early,
+                }
+            }
+            // Time skipped by the DST change. Assume the clock wasn't moved forward yet.
+            LocalResult::None => self.time_zone
+                .from_local_datetime(&(local + Duration::hours(1)))
+                .earliest()
+                .map(|t| t.with_timezone(&Utc) - Duration::hours(1))
+                .unwrap_or_else(|| Utc.from_utc_datetime(&local)),
+        }
+    }
+
+    /// Number of frequency bins in a single sweep
+    pub fn num_bins(&self) -> usize {
+        match self.freq_high.checked_sub(self.freq_low) {
+            Some(span) if self.freq_step > 0.0 => (span as f64 / self.freq_step as f64).round() as usize,
+            _ => 0,
+        }
+    }
+
+    /// Per bin statistics computed using all available cores.
+    /// Each task owns a disjoint range of bins and visits records in file order,
+    /// so the result is identical to `bin_stats_sequential`.
+    pub fn bin_stats(&self) -> Vec<BinStats> {
+        let num_bins = self.num_bins();
+        (0..num_bins).step_by(BINS_PER_TASK)
+            .collect::<Vec<usize>>()
+            .par_iter()
+            .map(|&start| self.aggregate(start..num_bins.min(start + BINS_PER_TASK)))
+            .collect::<Vec<Vec<BinStats>>>()
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    /// Per bin statistics computed on the current thread.
+    pub fn bin_stats_sequential(&self) -> Vec<BinStats> {
+        self.aggregate(0..self.num_bins())
+    }
+
+    /// Index of the first bin covered by the given record
+    pub fn bin_index(&self, record: &CsvRecord) -> Option<usize> {
+        if record.freq_low < self.freq_low || self.freq_step <= 0.0 {
+            return None;
+        }
+        Some(((record.freq_low - self.freq_low) as f64 / self.freq_step as f64).round() as usize)
+    }
+
+    /// Frequency of the lower edge of the given bin in Hz
+    pub fn bin_frequency(&self, bin: usize) -> f64 {
+        self.freq_low as f64 + bin as f64 * self.freq_step as f64
+    }
+
+    fn aggregate(&self, bins: Range<usize>) -> Vec<BinStats> {
+        let mut sums = vec![0.0f64; bins.len()];
+        let mut stats: Vec<BinStats> = bins.clone()
+            .map(|bin| BinStats { 
+                frequency: self.bin_frequency(bin), 
+                min: f32::INFINITY, 
+                max: f32::NEG_INFINITY, 
+                mean: 0.0, 
+                count: 0,
+            })
+            .collect();
+
+        for record in &self.records {
+            let Some(first) = self.bin_index(record) else { continue };
+            let samples = record.bins();
+            let start = bins.start.max(first);
+            let end = bins.end.min(first + samples.len());
+            for bin in start..end {
+                let v = samples[bin - first];
+                let s = &mut stats[bin - bins.start];
+                s.min = s.min.min(v);
+                s.max = s.max.max(v);
+                s.count += 1;
+                sums[bin - bins.start] += v as f64;
+            }
+        }
+
+        for (s, sum) in stats.iter_mut().zip(sums) {
+            if s.count > 0 {
+                s.mean = (sum / s.count as f64) as f32;
+            }
+        }
+        stats
+    }
+}
+
+impl CsvRecord {
+    /// Date and time as written in the file
+    pub fn timestamp(&self) -> NaiveDateTime {
+        self.date.and_time(self.time)
+    }
+
+    /// Samples which fall into the record frequency range.
+    /// Some tools emit an extra sample at `freq_high`, which belongs to the next record.
+    /// Records with the upper frequency below the lower one have no samples.
+    pub fn bins(&self) -> &[f32] {
+        let Some(span) = self.freq_high.checked_sub(self.freq_low) else {
+            return &[];
+        };
+        if self.freq_step <= 0.0 {
+            return &self.samples;
+        }
+        let n = (span as f64 / self.freq_step as f64).round() as usize;
+        &self.samples[..n.min(self.samples.len())]
     }
 }
 
-impl Default for CsvRecord {
-    fn default() -> Self {
-        Self { 
-            date: Default::default(), 
-            time: Default::default(), 
-            freq_low: Default::default(), 
-            freq_high: Default::default(), 
-            freq_step: Default::default(), 
-            num_samples: Default::default(), 
-            samples: Default::default() }
+// Valid records and the number of the skipped rows
+fn parse_records(data: &str) -> (Vec<CsvRecord>, usize) {
+    let mut rdr = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(data.as_bytes());
+
+    let mut records = vec![];
+    let mut skipped = 0;
+    for row in rdr.records() {
+        // Lines with only the white space
+        if matches!(&row, Ok(row) if row.len() == 1 && row[0].is_empty()) {
+            continue;
+        }
+        match row.and_then(|row| row.deserialize::<CsvRecord>(None)) {
+            Ok(record) if record.freq_high >= record.freq_low => records.push(record),
+            _ => skipped += 1,
+        }
     }
+    (records, skipped)
+}
+
+// Split text into about `parts` chunks. Each chunk ends at the line boundary.
+fn split_lines(data: &str, parts: usize) -> Vec<&str> {
+    let chunk_size = (data.len() / parts.max(1)).max(1);
+    let mut chunks = vec![];
+    let mut rest = data;
+    while !rest.is_empty() {
+        let split = if rest.len() <= chunk_size {
+            rest.len()
+        } else {
+            rest.as_bytes()[chunk_size..].iter()
+                .position(|&b| b == b'\n')
+                .map(|i| chunk_size + i + 1)
+                .unwrap_or(rest.len())
+        };
+        let (chunk, tail) = rest.split_at(split);
+        chunks.push(chunk);
+        rest = tail;
+    }
+    chunks
 }
 
 /// ------------------------------------------------------------------------------------------------
@@ -106,12 +391,121 @@ mod tests {
             2024-02-03, 14:12:38, 144000000, 145000000, 976.56, 2, -30.0, -31.0
             2024-02-03, 14:12:48, 145000000, 146000000, 976.56, 2, -40.0, -41.0
         ";
-        let df = DataFrame::from_string(&csv);
+        let df = DataFrame::from_string(csv);
 
         assert_eq!(df.freq_low, 144_000_000);
         assert_eq!(df.freq_high, 146_000_000);
         assert_eq!(df.freq_step, 976.56);
         assert_eq!(df.sweep_steps, 2);
         assert_eq!(df.records.len(), 4);
+        assert_eq!(df.skipped_rows(), 0);
+    }
+
+    #[test]
+    fn test_invalid_rows() {
+        let csv = "\
+            2024-02-03, 14:11:38, 144000000, 145000000, 976.56, 2, -10.0, -11.0
+            2024-02-03, 14:11:48, 146000000, 145000000, 976.56, 2, -20.0, -21.0
+            2024-02-03, 14:11:58, 144000000, 145000000, 976.56, 2, -30.0, dB
+        ";
+        let df = DataFrame::from_string(csv);
+        assert_eq!(df.records.len(), 1);
+        assert_eq!(df.skipped_rows(), 2);
+        assert_eq!(DataFrame::from_string_sequential(csv).skipped_rows(), 2);
+
+        let record = CsvRecord { freq_low: 146_000_000, freq_high: 145_000_000, freq_step: 1000.0, samples: vec![-10.0], ..Default::default() };
+        assert!(record.bins().is_empty());
+        assert_eq!(DataFrame::from_records(vec![record]).num_bins(), 0);
+    }
+
+    #[test]
+    fn test_timestamps_midnight() {
+        let csv = "\
+            2024-02-03, 23:59:59, 144000000, 145000000, 976.56, 2, -10.0, -11.0
+            2024-02-03, 00:00:01, 145000000, 146000000, 976.56, 2, -20.0, -21.0
+            2024-02-04, 00:00:09, 144000000, 145000000, 976.56, 2, -30.0, -31.0
+            2024-02-04, 00:00:10, 145000000, 146000000, 976.56, 2, -40.0, -41.0
+        ";
+        let df = DataFrame::from_string(csv);
+        let sweeps = df.sweeps();
+
+        assert_eq!(sweeps.len(), 2);
+        assert_eq!(sweeps[0].start.to_rfc3339(), "2024-02-03T23:59:59+00:00");
+        assert_eq!(sweeps[0].end.to_rfc3339(), "2024-02-04T00:00:01+00:00");
+        assert_eq!(sweeps[0].records, 0..2);
+        assert_eq!(sweeps[1].start.to_rfc3339(), "2024-02-04T00:00:09+00:00");
+    }
+
+    #[test]
+    fn test_timestamps_dst() {
+        // Clocks in Poland go back from 03:00 CEST to 02:00 CET on 2023-10-29
+        let csv = "\
+            2023-10-29, 02:30:00, 144000000, 145000000, 976.56, 2, -10.0, -11.0
+            2023-10-29, 02:59:50, 144000000, 145000000, 976.56, 2, -20.0, -21.0
+            2023-10-29, 02:00:10, 144000000, 145000000, 976.56, 2, -30.0, -31.0
+            2023-10-29, 02:30:00, 144000000, 145000000, 976.56, 2, -40.0, -41.0
+        ";
+        let df = DataFrame::from_string(csv).with_time_zone(chrono_tz::Europe::Warsaw);
+        let times: Vec<String> = df.sweeps().iter().map(|s| s.start.to_rfc3339()).collect();
+
+        assert_eq!(times, vec![
+            "2023-10-29T00:30:00+00:00", 
+            "2023-10-29T00:59:50+00:00", 
+            "2023-10-29T01:00:10+00:00", 
+            "2023-10-29T01:30:00+00:00",
+        ]);
+    }
+
+    #[test]
+    fn test_sweep_powers() {
+        let csv = "\
+            2024-02-03, 14:11:38, 144000000, 144002000, 1000, 2, -10.0, -11.0
+            2024-02-03, 14:11:38, 144002000, 144004000, 1000, 2, -20.0, -21.0
+            2024-02-03, 14:11:48, 144000000, 144002000, 1000, 2, -30.0, -31.0
+        ";
+        let df = DataFrame::from_string(csv);
+        let sweeps = df.sweeps();
+
+        assert_eq!(df.sweep_powers(&sweeps[0]), vec![-10.0, -11.0, -20.0, -21.0]);
+        assert!(df.sweep_powers(&sweeps[1])[2].is_nan());
+    }
+
+    #[test]
+    fn test_split_lines() {
+        let data = "a,1\nb,2\nc,3\nd,4";
+        let chunks = split_lines(data, 3);
+        assert!(chunks.iter().take(chunks.len() - 1).all(|c| c.ends_with('\n')));
+        assert_eq!(chunks.concat(), data);
+    }
+
+    #[test]
+    fn test_parallel_same_as_sequential() {
+        let data = std::fs::read_to_string("testdata/ham-70cm.csv").unwrap();
+        let df = DataFrame::from_string(&data);
+        let expected = DataFrame::from_string_sequential(&data);
+
+        assert_eq!(df.records, expected.records);
+        assert_eq!(df.sweep_steps, 8);
+        assert_eq!(df.bin_stats(), expected.bin_stats_sequential());
+    }
+
+    #[test]
+    fn test_bin_stats() {
+        let csv = "\
+            2024-02-03, 14:11:38, 144000000, 144002000, 1000, 2, -10.0, -11.0
+            2024-02-03, 14:11:38, 144002000, 144004000, 1000, 2, -20.0, -21.0
+            2024-02-03, 14:11:48, 144000000, 144002000, 1000, 2, -30.0, -31.0
+            2024-02-03, 14:11:48, 144002000, 144004000, 1000, 2, -40.0, -41.0
+        ";
+        let df = DataFrame::from_string(csv);
+        let stats = df.bin_stats();
+
+        assert_eq!(df.num_bins(), 4);
+        assert_eq!(stats.len(), 4);
+        assert_eq!(stats[1].frequency, 144_001_000.0);
+        assert_eq!(stats[1].min, -31.0);
+        assert_eq!(stats[1].max, -11.0);
+        assert_eq!(stats[1].mean, -21.0);
+        assert_eq!(stats[3].count, 2);
     }
 }
\ No newline at end of file
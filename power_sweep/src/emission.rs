@@ -0,0 +1,251 @@
+// Find emissions in the sweep matrix and measure their features.
+//
+// Bin is active in the sweep when its power is above the noise floor of that sweep by the given threshold.
+// Adjacent bins which were active in any sweep make a single emission.
+
+use chrono::{DateTime, Utc};
+use rayon::prelude::*;
+
+use crate::dataframe::DataFrame;
+use crate::noise::percentile;
+use crate::periodicity::{periodicity, Periodicity};
+
+
+pub struct EmissionOptions {
+    /// Level above the noise floor in dB which makes the bin active
+    pub threshold: f32,
+    /// Percentile (0.0..=1.0) of the sweep used as the noise floor
+    pub noise_percentile: f32,
+    /// Number of inactive bins allowed inside a single emission
+    pub max_gap: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Emission {
+    pub freq_low: f64,
+    pub freq_high: f64,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    /// Strongest power observed in dB
+    pub peak_power: f32,
+    /// Mean occupied bandwidth in Hz in the sweeps where the emission is active
+    pub bandwidth: f64,
+    /// Part of the sweeps (0.0..=1.0) in which the emission is active
+    pub duty_cycle: f32,
+    /// Mean number of the consecutive sweeps with the emission
+    pub burst_sweeps: f32,
+    /// Mean burst length in seconds
+    pub burst_length: f64,
+    /// Dominant repetition period of the on/off series
+    pub periodicity: Option<Periodicity>,
+    /// Drift of the peak frequency in Hz/s
+    pub drift: f64,
+    /// On/off state in every sweep
+    pub activity: Vec<bool>,
+}
+
+/// On/off state of every bin in every sweep
+pub struct ActivityMatrix {
+    /// Start of every sweep
+    pub times: Vec<DateTime<Utc>>,
+    /// Power in dB indexed by [sweep][bin]
+    pub powers: Vec<Vec<f32>>,
+    /// Active bins indexed by [sweep][bin]
+    pub active: Vec<Vec<bool>>,
+}
+
+impl Default for EmissionOptions {
+    fn default() -> Self {
+        Self {
+            threshold: 10.0,
+            noise_percentile: 0.2,
+            max_gap: 1,
+        }
+    }
+}
+
+/// Detect emissions in all sweeps of the data frame
+pub fn detect(df: &DataFrame, options: &EmissionOptions) -> Vec<Emission> {
+    let matrix = activity_matrix(df, options);
+    if matrix.times.is_empty() {
+        return vec![];
+    }
+    let occupied: Vec<bool> = (0..df.num_bins())
+        .map(|bin| matrix.active.iter().any(|a| a[bin]))
+        .collect();
+
+    bin_ranges(&occupied, options.max_gap).into_iter()
+        .map(|(start, end)| measure(df, &matrix, start, end))
+        .collect()
+}
+
+/// Mark bins above the noise floor of their sweep
+pub fn activity_matrix(df: &DataFrame, options: &EmissionOptions) -> ActivityMatrix {
+    let sweeps = df.sweeps();
+    let powers: Vec<Vec<f32>> = sweeps.par_iter().map(|s| df.sweep_powers(s)).collect();
+    let active = powers.iter()
+        .map(|row| {
+            let floor = percentile(row, options.noise_percentile);
+            row.iter().map(|&v| v > floor + options.threshold).collect()
+        })
+        .collect();
+    let times = sweeps.iter().map(|s| s.start).collect();
+
+    ActivityMatrix { times, powers, active }
+}
+
+/// Median time between sweeps in seconds
+pub fn sweep_interval(times: &[DateTime<Utc>]) -> f64 {
+    let diffs: Vec<f32> = times.windows(2)
+        .map(|w| (w[1] - w[0]).num_milliseconds() as f32 / 1000.0)
+        .collect();
+    let interval = percentile(&diffs, 0.5);
+    if interval.is_finite() { interval as f64 } else { 0.0 }
+}
+
+// Ranges of the occupied bins. Ranges separated by no more than `max_gap` bins are merged.
+fn bin_ranges(occupied: &[bool], max_gap: usize) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = vec![];
+    for (bin, _) in occupied.iter().enumerate().filter(|(_, &o)| o) {
+        match ranges.last_mut() {
+            Some((_, end)) if bin - *end <= max_gap => *end = bin + 1,
+            _ => ranges.push((bin, bin + 1)),
+        }
+    }
+    ranges
+}
+
+fn measure(df: &DataFrame, matrix: &ActivityMatrix, start: usize, end: usize) -> Emission {
+    let ActivityMatrix { times, powers, active } = matrix;
+    let bin_width = df.freq_step() as f64;
+    let activity: Vec<bool> = active.iter().map(|a| a[start..end].iter().any(|&v| v)).collect();
+    let seconds: Vec<f64> = times.iter().map(|t| (*t - times[0]).num_milliseconds() as f64 / 1000.0).collect();
+
+    let mut peak_power = f32::NEG_INFINITY;
+    let mut widths = vec![];
+    let mut peaks: Vec<(f64, f64)> = vec![];
+    for (sweep, row) in powers.iter().enumerate().filter(|(i, _)| activity[*i]) {
+        let bins = (start..end).filter(|&b| active[sweep][b]);
+        let peak = bins.clone().max_by(|&a, &b| row[a].total_cmp(&row[b])).unwrap_or(start);
+        peak_power = peak_power.max(row[peak]);
+        widths.push(bins.count() as f64 * bin_width);
+        peaks.push((seconds[sweep], df.bin_frequency(peak)));
+    }
+
+    let bursts = bursts(&activity);
+    let burst_sweeps = bursts.iter().map(|(_, len)| *len as f32).sum::<f32>() / bursts.len().max(1) as f32;
+    let first = activity.iter().position(|&a| a).unwrap_or(0);
+    let last = activity.iter().rposition(|&a| a).unwrap_or(0);
+    let interval = sweep_interval(times);
+
+    Emission {
+        freq_low: df.bin_frequency(start),
+        freq_high: df.bin_frequency(end),
+        first_seen: times[first],
+        last_seen: times[last],
+        peak_power,
+        bandwidth: widths.iter().sum::<f64>() / widths.len().max(1) as f64,
+        duty_cycle: peaks.len() as f32 / activity.len() as f32,
+        burst_sweeps,
+        burst_length: burst_sweeps as f64 * interval,
+        periodicity: periodicity(&activity, interval),
+        drift: slope(&peaks),
+        activity,
+    }
+}
+
+// Start and length of every run of the active sweeps
+fn bursts(activity: &[bool]) -> Vec<(usize, usize)> {
+    let mut bursts: Vec<(usize, usize)> = vec![];
+    for (i, &a) in activity.iter().enumerate() {
+        match bursts.last_mut() {
+            Some((start, len)) if a && *start + *len == i => *len += 1,
+            _ if a => bursts.push((i, 1)),
+            _ => (),
+        }
+    }
+    bursts
+}
+
+// Slope of the least squares line fitted to the points
+fn slope(points: &[(f64, f64)]) -> f64 {
+    let n = points.len() as f64;
+    if points.len() < 2 {
+        return 0.0;
+    }
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let cov = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum::<f64>();
+    let var = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum::<f64>();
+    if var > 0.0 { cov / var } else { 0.0 }
+}
+
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 12 sweeps every 10 seconds, 20 bins of 1 kHz.
+    // Carrier at bins 2-3 is always on and drifts up by one bin at sweep 6.
+    // Beacon at bin 10 is on every 4th sweep.
+    // Transmission at bins 15-17 lasts 3 sweeps.
+    fn test_data() -> String {
+        let mut csv = String::new();
+        for sweep in 0..12 {
+            csv.push_str(&format!("2024-02-03, 14:{:02}:{:02}, 144000000, 144020000, 1000, 2", sweep / 6, (sweep % 6) * 10));
+            for bin in 0..20 {
+                let carrier = if sweep < 6 { bin == 2 } else { bin == 3 };
+                let beacon = bin == 10 && sweep % 4 == 1;
+                let voice = (15..18).contains(&bin) && (4..7).contains(&sweep);
+                let v = if carrier || beacon || voice { -50.0 } else { -90.0 };
+                csv.push_str(&format!(", {:.1}", v));
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+
+    #[test]
+    fn test_detect() {
+        let df = DataFrame::from_string(&test_data());
+        let emissions = detect(&df, &EmissionOptions::default());
+
+        assert_eq!(emissions.len(), 3);
+
+        let carrier = &emissions[0];
+        assert_eq!(carrier.freq_low, 144_002_000.0);
+        assert_eq!(carrier.freq_high, 144_004_000.0);
+        assert_eq!(carrier.duty_cycle, 1.0);
+        assert_eq!(carrier.bandwidth, 1000.0);
+        assert_eq!(carrier.peak_power, -50.0);
+        assert!(carrier.drift > 0.0);
+
+        let beacon = &emissions[1];
+        assert_eq!(beacon.duty_cycle, 0.25);
+        assert_eq!(beacon.burst_sweeps, 1.0);
+        assert_eq!(beacon.periodicity.map(|p| p.period), Some(40.0));
+        assert_eq!(beacon.first_seen.to_rfc3339(), "2024-02-03T14:00:10+00:00");
+
+        let voice = &emissions[2];
+        assert_eq!(voice.bandwidth, 3000.0);
+        assert_eq!(voice.burst_sweeps, 3.0);
+        assert_eq!(voice.burst_length, 30.0);
+        assert_eq!(voice.drift, 0.0);
+    }
+
+    #[test]
+    fn test_bin_ranges() {
+        let occupied = [false, true, true, false, true, false, false, true];
+        assert_eq!(bin_ranges(&occupied, 1), vec![(1, 5), (7, 8)]);
+        assert_eq!(bin_ranges(&occupied, 0), vec![(1, 3), (4, 5), (7, 8)]);
+    }
+
+    #[test]
+    fn test_bursts() {
+        let activity = [true, true, false, true, false, false, true];
+        assert_eq!(bursts(&activity), vec![(0, 2), (3, 1), (6, 1)]);
+    }
+}
@@ -1 +1,5 @@
-pub mod dataframe;
\ No newline at end of file
+pub mod classify;
+pub mod dataframe;
+pub mod emission;
+pub mod noise;
+pub mod periodicity;
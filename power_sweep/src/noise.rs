@@ -0,0 +1,251 @@
+// Track the noise floor over time and find interference events.
+//
+// The band is split into segments. For every sweep the noise floor of the segment is estimated
+// as a low percentile of its bins, so narrow carriers don't affect it.
+// Steps in the noise floor are typical for switching power supplies or PLC turning on and off.
+
+use chrono::{DateTime, Utc};
+
+use crate::dataframe::DataFrame;
+
+
+pub struct NoiseFloorOptions {
+    /// Width of a single band segment in Hz
+    pub segment_width: f64,
+    /// Percentile (0.0..=1.0) of the bin powers used as the noise floor
+    pub percentile: f32,
+    /// Number of sweeps compared before and after the step
+    pub window: usize,
+    /// Minimal change of the noise floor in dB reported as an event
+    pub threshold: f32,
+    /// Part of the band (0.0..=1.0) which has to rise together to report a broadband event
+    pub broadband_fraction: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    StepUp,
+    StepDown,
+    BroadbandRise,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterferenceEvent {
+    pub time: DateTime<Utc>,
+    pub kind: EventKind,
+    pub freq_low: f64,
+    pub freq_high: f64,
+    /// Change of the noise floor in dB, averaged over the affected segments
+    pub delta: f32,
+}
+
+/// Noise floor of every band segment in every sweep.
+pub struct NoiseFloor {
+    pub times: Vec<DateTime<Utc>>,
+    /// Frequency range of each segment in Hz
+    pub segments: Vec<(f64, f64)>,
+    /// Noise floor in dB indexed by [sweep][segment]
+    pub levels: Vec<Vec<f32>>,
+}
+
+impl Default for NoiseFloorOptions {
+    fn default() -> Self {
+        Self {
+            segment_width: 1_000_000.0,
+            percentile: 0.1,
+            window: 3,
+            threshold: 3.0,
+            broadband_fraction: 0.5,
+        }
+    }
+}
+
+impl EventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::StepUp => "step-up",
+            EventKind::StepDown => "step-down",
+            EventKind::BroadbandRise => "broadband-rise",
+        }
+    }
+}
+
+impl NoiseFloor {
+    pub fn from_dataframe(df: &DataFrame, options: &NoiseFloorOptions) -> Self {
+        let num_bins = df.num_bins();
+        let bins_per_segment = ((options.segment_width / df.freq_step() as f64).round() as usize).clamp(1, num_bins.max(1));
+        let segments = (0..num_bins).step_by(bins_per_segment)
+            .map(|start| {
+                let end = num_bins.min(start + bins_per_segment);
+                (df.bin_frequency(start), df.bin_frequency(end))
+            })
+            .collect();
+
+        let sweeps = df.sweeps();
+        let times = sweeps.iter().map(|s| s.start).collect();
+        let levels = sweeps.iter()
+            .map(|sweep| {
+                df.sweep_powers(sweep)
+                    .chunks(bins_per_segment)
+                    .map(|xs| percentile(xs, options.percentile))
+                    .collect()
+            })
+            .collect();
+
+        Self { times, segments, levels }
+    }
+
+    /// Timeline of the noise floor changes.
+    /// Changes which happen in the same sweep in the adjacent segments are reported as a single event.
+    pub fn events(&self, options: &NoiseFloorOptions) -> Vec<InterferenceEvent> {
+        let steps: Vec<Vec<f32>> = (0..self.segments.len())
+            .map(|segment| self.segment_steps(segment, options))
+            .collect();
+
+        let mut events = vec![];
+        for (sweep, &time) in self.times.iter().enumerate() {
+            let mut segment = 0;
+            while segment < self.segments.len() {
+                let delta = steps[segment][sweep];
+                if delta == 0.0 {
+                    segment += 1;
+                    continue;
+                }
+                let first = segment;
+                let mut total = 0.0;
+                while segment < self.segments.len() && steps[segment][sweep].signum() == delta.signum() && steps[segment][sweep] != 0.0 {
+                    total += steps[segment][sweep];
+                    segment += 1;
+                }
+                let count = segment - first;
+                let broadband = count as f32 >= options.broadband_fraction * self.segments.len() as f32;
+                let kind = match (delta > 0.0, broadband) {
+                    (true, true) => EventKind::BroadbandRise,
+                    (true, false) => EventKind::StepUp,
+                    (false, _) => EventKind::StepDown,
+                };
+                events.push(InterferenceEvent {
+                    time,
+                    kind,
+                    freq_low: self.segments[first].0,
+                    freq_high: self.segments[segment - 1].1,
+                    delta: total / count as f32,
+                });
+            }
+        }
+        events
+    }
+
+    // Size of the step in the given segment for every sweep. 0.0 if there is no step.
+    // The step is the difference of the mean levels in the windows after and before the sweep.
+    // Only the largest change in the neighbourhood is kept.
+    fn segment_steps(&self, segment: usize, options: &NoiseFloorOptions) -> Vec<f32> {
+        let n = self.levels.len();
+        let w = options.window.max(1);
+        let series: Vec<f32> = self.levels.iter().map(|l| l[segment]).collect();
+        let deltas: Vec<f32> = (0..n)
+            .map(|i| {
+                if i < w || i + w > n {
+                    return 0.0;
+                }
+                let delta = mean(&series[i..i + w]) - mean(&series[i - w..i]);
+                if delta.abs() >= options.threshold { delta } else { 0.0 }
+            })
+            .collect();
+
+        (0..n)
+            .map(|i| {
+                let lo = i.saturating_sub(w - 1);
+                let hi = n.min(i + w);
+                let is_peak = (lo..hi).all(|j| deltas[j].abs() < deltas[i].abs() || (deltas[j].abs() == deltas[i].abs() && j >= i));
+                if is_peak { deltas[i] } else { 0.0 }
+            })
+            .collect()
+    }
+}
+
+/// Value at the given percentile (0.0..=1.0) ignoring NaNs. NaN if there is no data.
+pub fn percentile(xs: &[f32], p: f32) -> f32 {
+    let mut sorted: Vec<f32> = xs.iter().copied().filter(|v| v.is_finite()).collect();
+    if sorted.is_empty() {
+        return f32::NAN;
+    }
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let pos = (p.clamp(0.0, 1.0) * (sorted.len() - 1) as f32).round() as usize;
+    sorted[pos]
+}
+
+// Mean value ignoring NaNs
+fn mean(xs: &[f32]) -> f32 {
+    let (sum, count) = xs.iter()
+        .filter(|v| v.is_finite())
+        .fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+    sum / count as f32
+}
+
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 10 sweeps, 4 segments of 4 bins. Noise floor in all segments rises by 10 dB at sweep 5.
+    // Only the first segment has the noise which goes down by 6 dB at sweep 8.
+    fn test_data() -> String {
+        let mut csv = String::new();
+        for sweep in 0..10 {
+            for step in 0..2 {
+                let freq_low = 144_000_000 + step * 8_000;
+                csv.push_str(&format!("2024-02-03, 14:11:{:02}, {}, {}, 1000, 2", sweep * 5, freq_low, freq_low + 8_000));
+                for bin in 0..8 {
+                    let mut v = if sweep >= 5 { -80.0 } else { -90.0 };
+                    if step == 0 && bin < 4 && sweep >= 8 {
+                        v -= 6.0;
+                    }
+                    csv.push_str(&format!(", {:.1}", v));
+                }
+                csv.push('\n');
+            }
+        }
+        csv
+    }
+
+    #[test]
+    fn test_noise_floor_levels() {
+        let df = DataFrame::from_string(&test_data());
+        let options = NoiseFloorOptions { segment_width: 4_000.0, ..Default::default() };
+        let floor = NoiseFloor::from_dataframe(&df, &options);
+
+        assert_eq!(floor.segments.len(), 4);
+        assert_eq!(floor.segments[1], (144_004_000.0, 144_008_000.0));
+        assert_eq!(floor.levels.len(), 10);
+        assert_eq!(floor.levels[0], vec![-90.0; 4]);
+        assert_eq!(floor.levels[9], vec![-86.0, -80.0, -80.0, -80.0]);
+    }
+
+    #[test]
+    fn test_events() {
+        let df = DataFrame::from_string(&test_data());
+        let options = NoiseFloorOptions { segment_width: 4_000.0, window: 2, ..Default::default() };
+        let events = NoiseFloor::from_dataframe(&df, &options).events(&options);
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].kind, EventKind::BroadbandRise);
+        assert_eq!(events[0].time.to_rfc3339(), "2024-02-03T14:11:25+00:00");
+        assert_eq!(events[0].freq_low, 144_000_000.0);
+        assert_eq!(events[0].freq_high, 144_016_000.0);
+        assert_eq!(events[0].delta, 10.0);
+        assert_eq!(events[1].kind, EventKind::StepDown);
+        assert_eq!(events[1].freq_high, 144_004_000.0);
+        assert_eq!(events[1].delta, -6.0);
+    }
+
+    #[test]
+    fn test_percentile() {
+        assert_eq!(percentile(&[3.0, f32::NAN, 1.0, 2.0], 0.5), 2.0);
+        assert_eq!(percentile(&[3.0, 1.0, 2.0], 0.0), 1.0);
+        assert!(percentile(&[], 0.5).is_nan());
+    }
+}
@@ -0,0 +1,120 @@
+// Find the repetition period of the on/off time series.
+//
+// Autocorrelation of the series is computed for lags up to half of its length,
+// so the signal has to repeat at least twice. Period is measured in sweeps, so it is only
+// as accurate as the sweep interval.
+
+use crate::dataframe::DataFrame;
+use crate::emission::{activity_matrix, sweep_interval, EmissionOptions};
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Periodicity {
+    /// Dominant repetition period in seconds
+    pub period: f64,
+    /// Normalized autocorrelation at the period (0.0..=1.0)
+    pub confidence: f32,
+}
+
+/// Dominant period of the on/off series sampled every `interval` seconds.
+/// None if the signal is always on, always off or doesn't repeat.
+pub fn periodicity(activity: &[bool], interval: f64) -> Option<Periodicity> {
+    let acf = autocorrelation(activity);
+    let best = acf.iter().skip(1).copied().fold(0.0, f32::max);
+    if best <= 0.0 {
+        return None;
+    }
+    // Multiples of the period correlate as well as the period itself, so take the first peak close to the best one
+    let lag = (1..acf.len())
+        .find(|&k| acf[k] >= 0.9 * best && acf[k] >= acf[k - 1] && acf.get(k + 1).is_none_or(|&r| acf[k] >= r))?;
+    Some(Periodicity { period: lag as f64 * interval, confidence: acf[lag].min(1.0) })
+}
+
+/// Period of every frequency bin
+pub fn bin_periodicity(df: &DataFrame, options: &EmissionOptions) -> Vec<Option<Periodicity>> {
+    let matrix = activity_matrix(df, options);
+    let interval = sweep_interval(&matrix.times);
+    (0..df.num_bins())
+        .map(|bin| {
+            let activity: Vec<bool> = matrix.active.iter().map(|a| a[bin]).collect();
+            periodicity(&activity, interval)
+        })
+        .collect()
+}
+
+// Normalized autocorrelation for lags 0..=n/2. Empty if the series is constant.
+fn autocorrelation(activity: &[bool]) -> Vec<f32> {
+    let n = activity.len();
+    let mean = activity.iter().filter(|&&a| a).count() as f32 / n.max(1) as f32;
+    let xs: Vec<f32> = activity.iter().map(|&a| if a { 1.0 } else { 0.0 } - mean).collect();
+    let var = xs.iter().map(|x| x * x).sum::<f32>() / n.max(1) as f32;
+    if n < 4 || var <= 0.0 {
+        return vec![];
+    }
+    (0..=n / 2)
+        .map(|k| {
+            let cov = xs.iter().zip(&xs[k..]).map(|(a, b)| a * b).sum::<f32>() / (n - k) as f32;
+            cov / var
+        })
+        .collect()
+}
+
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_periodicity() {
+        let activity: Vec<bool> = (0..40).map(|i| i % 4 == 1).collect();
+        let p = periodicity(&activity, 10.0).unwrap();
+
+        assert_eq!(p.period, 40.0);
+        assert!(p.confidence > 0.9);
+    }
+
+    #[test]
+    fn test_periodicity_burst() {
+        // Transmission lasting 2 sweeps every 6 sweeps
+        let activity: Vec<bool> = (0..36).map(|i| i % 6 < 2).collect();
+        let p = periodicity(&activity, 5.0).unwrap();
+
+        assert_eq!(p.period, 30.0);
+    }
+
+    #[test]
+    fn test_no_periodicity() {
+        assert_eq!(periodicity(&[true; 20], 10.0), None);
+        assert_eq!(periodicity(&[false; 20], 10.0), None);
+
+        let activity: Vec<bool> = (0..20).map(|i| i == 3 || i == 4 || i == 11).collect();
+        let p = periodicity(&activity, 10.0);
+        assert!(p.is_none_or(|p| p.confidence < 0.5));
+    }
+
+    #[test]
+    fn test_bin_periodicity() {
+        // Beacon in the bin 5 every 4 sweeps, constant carrier in the bin 2
+        let mut csv = String::new();
+        for sweep in 0..24 {
+            csv.push_str(&format!("2024-02-03, 14:{:02}:{:02}, 144000000, 144010000, 1000, 10", sweep / 6, (sweep % 6) * 10));
+            for bin in 0..10 {
+                let on = bin == 2 || (bin == 5 && sweep % 4 == 1);
+                csv.push_str(if on { ", -50.0" } else { ", -90.0" });
+            }
+            csv.push('\n');
+        }
+        let df = DataFrame::from_string(&csv);
+        let periods = bin_periodicity(&df, &EmissionOptions::default());
+
+        assert_eq!(periods.len(), 10);
+        let beacon = periods[5].unwrap();
+        assert_eq!(beacon.period, 40.0);
+        assert!(beacon.confidence > 0.9);
+        assert_eq!(periods[2], None);
+        assert!(periods.iter().enumerate().all(|(bin, p)| bin == 5 || p.is_none()));
+    }
+}
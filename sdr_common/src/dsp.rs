@@ -0,0 +1,433 @@
+//! Small DSP building blocks used by the demodulators and decoders.
+//!
+//! Demodulators run inside a single flowgraph block, so these work on plain samples
+//! and keep their own state between calls.
+
+use std::f32::consts::PI;
+use std::ops::{Add, Mul};
+
+use futuresdr::num_complex::Complex32;
+use serde::{Deserialize, Serialize};
+
+
+/// Design low pass filter with the windowed sinc method (Blackman window).
+/// `cutoff` is normalized to the sample rate (0.0..0.5).
+pub fn lowpass(cutoff: f32, num_taps: usize) -> Vec<f32> {
+    let m = (num_taps - 1) as f32;
+    let taps: Vec<f32> = (0..num_taps)
+        .map(|i| {
+            let x = i as f32 - m / 2.0;
+            let sinc = if x == 0.0 { 2.0 * cutoff } else { (2.0 * PI * cutoff * x).sin() / (PI * x) };
+            let window = 0.42 - 0.5 * (2.0 * PI * i as f32 / m).cos() + 0.08 * (4.0 * PI * i as f32 / m).cos();
+            sinc * window
+        })
+        .collect();
+    let gain: f32 = taps.iter().sum();
+    taps.iter().map(|t| t / gain).collect()
+}
+
+/// Complex band pass filter which passes only frequencies between `low` and `high`.
+/// Frequencies are normalized to the sample rate and can be negative.
+pub fn complex_bandpass(low: f32, high: f32, num_taps: usize) -> Vec<Complex32> {
+    let center = (low + high) / 2.0;
+    let m = (num_taps - 1) as f32;
+    lowpass((high - low) / 2.0, num_taps).iter()
+        .enumerate()
+        .map(|(i, &t)| Complex32::from_polar(t, 2.0 * PI * center * (i as f32 - m / 2.0)))
+        .collect()
+}
+
+/// Number of taps for the filter with the given transition width (normalized to the sample rate)
+pub fn num_taps(transition: f32) -> usize {
+    ((4.0 / transition).ceil() as usize).max(3) | 1
+}
+
+/// FIR filter with the optional decimation.
+pub struct Fir<T, C> {
+    // Taps in reversed order, so they can be applied to the history directly
+    taps: Vec<C>,
+    // History is stored twice, so the last `taps.len()` samples are always continuous
+    history: Vec<T>,
+    pos: usize,
+    decim: usize,
+    phase: usize,
+}
+
+impl<T, C> Fir<T, C>
+where
+    T: Copy + Default + Add<Output = T> + Mul<C, Output = T>,
+    C: Copy,
+{
+    pub fn new(taps: Vec<C>) -> Self {
+        Self::with_decimation(taps, 1)
+    }
+
+    pub fn with_decimation(mut taps: Vec<C>, decim: usize) -> Self {
+        taps.reverse();
+        let history = vec![T::default(); 2 * taps.len()];
+        Self { taps, history, pos: 0, decim: decim.max(1), phase: 0 }
+    }
+
+    /// Push a sample. Returns the filtered value every `decim` samples.
+    pub fn process_sample(&mut self, x: T) -> Option<T> {
+        let n = self.taps.len();
+        self.history[self.pos] = x;
+        self.history[self.pos + n] = x;
+        self.pos = (self.pos + 1) % n;
+        self.phase += 1;
+        if self.phase < self.decim {
+            return None;
+        }
+        self.phase = 0;
+        let window = &self.history[self.pos..self.pos + n];
+        let acc = window.iter()
+            .zip(&self.taps)
+            .fold(T::default(), |acc, (&x, &t)| acc + x * t);
+        Some(acc)
+    }
+}
+
+/// Numerically controlled oscillator
+pub struct Nco {
+    phase: Complex32,
+    step: Complex32,
+}
+
+impl Nco {
+    /// `freq` is normalized to the sample rate
+    pub fn new(freq: f32) -> Self {
+        Self { phase: Complex32::new(1.0, 0.0), step: Complex32::from_polar(1.0, 2.0 * PI * freq) }
+    }
+
+    pub fn set_freq(&mut self, freq: f32) {
+        self.step = Complex32::from_polar(1.0, 2.0 * PI * freq);
+    }
+
+    pub fn next_sample(&mut self) -> Complex32 {
+        let v = self.phase;
+        self.phase *= self.step;
+        // Keep the amplitude from drifting away
+        self.phase /= self.phase.norm();
+        v
+    }
+}
+
+/// Second order phase locked loop.
+/// Phase and frequency are in radians per sample.
+pub struct Pll {
+    phase: f32,
+    freq: f32,
+    center: f32,
+    max_offset: f32,
+    alpha: f32,
+    beta: f32,
+}
+
+impl Pll {
+    /// `center` and `max_offset` limit the frequency the loop can lock to.
+    /// `loop_bw` is the loop bandwidth normalized to the sample rate.
+    pub fn new(center: f32, max_offset: f32, loop_bw: f32) -> Self {
+        let damping = std::f32::consts::FRAC_1_SQRT_2;
+        let wn = 2.0 * PI * loop_bw;
+        Self { 
+            phase: 0.0, 
+            freq: center, 
+            center, 
+            max_offset, 
+            alpha: 2.0 * damping * wn, 
+            beta: wn * wn,
+        }
+    }
+
+    pub fn phase(&self) -> f32 {
+        self.phase
+    }
+
+    pub fn freq(&self) -> f32 {
+        self.freq
+    }
+
+    /// Advance the loop by one sample using the measured phase error
+    pub fn update(&mut self, error: f32) {
+        self.freq = (self.freq + self.beta * error).clamp(self.center - self.max_offset, self.center + self.max_offset);
+        self.phase = (self.phase + self.freq + self.alpha * error) % (2.0 * PI);
+    }
+}
+
+/// Second order IIR section
+pub struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2],
+    z: [f32; 2],
+}
+
+impl Biquad {
+    /// High pass filter. `freq` is normalized to the sample rate.
+    /// See https://www.w3.org/TR/audio-eq-cookbook/
+    pub fn highpass(freq: f32, q: f32) -> Self {
+        let w0 = 2.0 * PI * freq;
+        let alpha = w0.sin() / (2.0 * q);
+        let cos = w0.cos();
+        let a0 = 1.0 + alpha;
+        Self {
+            b: [(1.0 + cos) / 2.0 / a0, -(1.0 + cos) / a0, (1.0 + cos) / 2.0 / a0],
+            a: [-2.0 * cos / a0, (1.0 - alpha) / a0],
+            z: [0.0; 2],
+        }
+    }
+
+    /// Low pass filter. `freq` is normalized to the sample rate.
+    pub fn lowpass(freq: f32, q: f32) -> Self {
+        let w0 = 2.0 * PI * freq;
+        let alpha = w0.sin() / (2.0 * q);
+        let cos = w0.cos();
+        let a0 = 1.0 + alpha;
+        Self {
+            b: [(1.0 - cos) / 2.0 / a0, (1.0 - cos) / a0, (1.0 - cos) / 2.0 / a0],
+            a: [-2.0 * cos / a0, (1.0 - alpha) / a0],
+            z: [0.0; 2],
+        }
+    }
+
+    /// Butterworth high pass filter of the given (even) order as the cascade of the sections
+    pub fn butterworth_highpass(freq: f32, order: usize) -> Vec<Self> {
+        butterworth_q(order).into_iter().map(|q| Self::highpass(freq, q)).collect()
+    }
+
+    /// Butterworth low pass filter of the given (even) order as the cascade of the sections
+    pub fn butterworth_lowpass(freq: f32, order: usize) -> Vec<Self> {
+        butterworth_q(order).into_iter().map(|q| Self::lowpass(freq, q)).collect()
+    }
+
+    // Transposed direct form II
+    pub fn process_sample(&mut self, x: f32) -> f32 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+// Quality factors of the second order sections of the Butterworth filter
+fn butterworth_q(order: usize) -> Vec<f32> {
+    let n = order / 2;
+    (1..=n)
+        .map(|k| 1.0 / (2.0 * ((2 * k - 1) as f32 * PI / (2 * order) as f32).sin()))
+        .collect()
+}
+
+/// Run the sample through the cascade of the filters
+pub fn cascade(filters: &mut [Biquad], x: f32) -> f32 {
+    filters.iter_mut().fold(x, |x, f| f.process_sample(x))
+}
+
+/// Single pole de-emphasis filter with the time constant `tau` in seconds
+pub struct Deemphasis {
+    alpha: f32,
+    last: f32,
+}
+
+impl Deemphasis {
+    pub fn new(tau: f32, sample_rate: f32) -> Self {
+        Self { alpha: 1.0 - (-1.0 / (tau * sample_rate)).exp(), last: 0.0 }
+    }
+
+    pub fn process_sample(&mut self, x: f32) -> f32 {
+        self.last += self.alpha * (x - self.last);
+        self.last
+    }
+}
+
+/// Remove DC with a single pole high pass filter
+pub struct DcBlocker {
+    alpha: f32,
+    last_x: f32,
+    last_y: f32,
+}
+
+impl DcBlocker {
+    pub fn new(alpha: f32) -> Self {
+        Self { alpha, last_x: 0.0, last_y: 0.0 }
+    }
+
+    pub fn process_sample(&mut self, x: f32) -> f32 {
+        let y = x - self.last_x + self.alpha * self.last_y;
+        self.last_x = x;
+        self.last_y = y;
+        y
+    }
+}
+
+/// Attack, decay and hang times of the AGC in seconds
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AgcTimes {
+    pub attack: f32,
+    pub decay: f32,
+    pub hang: f32,
+}
+
+/// Automatic gain control with the peak detector.
+/// Detected level follows the rising signal with the attack time. When the signal falls
+/// the level is held for the hang time and then decays.
+pub struct Agc {
+    target: f32,
+    max_gain: f32,
+    attack: f32,
+    decay: f32,
+    hang: usize,
+    hang_count: usize,
+    level: f32,
+}
+
+impl Agc {
+    /// Gain brings the peaks to `target`, but it is never higher than `max_gain`
+    pub fn new(times: AgcTimes, sample_rate: f32, target: f32, max_gain: f32) -> Self {
+        let coef = |time: f32| 1.0 - (-1.0 / (time * sample_rate)).exp();
+        Self {
+            target,
+            max_gain,
+            attack: coef(times.attack),
+            decay: coef(times.decay),
+            hang: (times.hang * sample_rate) as usize,
+            hang_count: 0,
+            level: 0.0,
+        }
+    }
+
+    /// Update the detector with the magnitude of the sample and return the gain
+    pub fn gain(&mut self, magnitude: f32) -> f32 {
+        if magnitude >= self.level {
+            // Start at the level of the first sample, so the gain doesn't begin at its maximum
+            let attack = if self.level == 0.0 { 1.0 } else { self.attack };
+            self.level += attack * (magnitude - self.level);
+            self.hang_count = self.hang;
+        } else if self.hang_count > 0 {
+            self.hang_count -= 1;
+        } else {
+            self.level += self.decay * (magnitude - self.level);
+        }
+        (self.target / self.level).min(self.max_gain)
+    }
+}
+
+/// FM detector using the conjugate delay method.
+/// See https://en.wikipedia.org/wiki/Detector_(radio)#Quadrature_detector
+#[derive(Default)]
+pub struct QuadratureDetector {
+    last: Complex32,
+}
+
+impl QuadratureDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn process_sample(&mut self, v: Complex32) -> f32 {
+        let arg = (v * self.last.conj()).arg(); // Obtain phase of x[n] * conj(x[n-1])
+        self.last = v;
+        arg
+    }
+}
+
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Gain of the real filter at the given normalized frequency
+    fn response(taps: &[f32], freq: f32) -> f32 {
+        taps.iter()
+            .enumerate()
+            .map(|(i, &t)| Complex32::from_polar(t, -2.0 * PI * freq * i as f32))
+            .sum::<Complex32>()
+            .norm()
+    }
+
+    #[test]
+    fn test_lowpass() {
+        let taps = lowpass(0.1, 101);
+        assert!((response(&taps, 0.0) - 1.0).abs() < 1e-3);
+        assert!(response(&taps, 0.05) > 0.99);
+        assert!(response(&taps, 0.2) < 1e-3);
+    }
+
+    #[test]
+    fn test_complex_bandpass() {
+        let taps = complex_bandpass(0.05, 0.15, 101);
+        let gain = |f: f32| taps.iter()
+            .enumerate()
+            .map(|(i, &t)| t * Complex32::from_polar(1.0, -2.0 * PI * f * i as f32))
+            .sum::<Complex32>()
+            .norm();
+        assert!(gain(0.1) > 0.99);
+        assert!(gain(-0.1) < 1e-3);
+    }
+
+    #[test]
+    fn test_fir_decimation() {
+        let mut fir: Fir<f32, f32> = Fir::with_decimation(vec![0.5, 0.5], 2);
+        let out: Vec<f32> = [1.0, 3.0, 5.0, 7.0].iter().filter_map(|&x| fir.process_sample(x)).collect();
+        assert_eq!(out, vec![2.0, 6.0]);
+    }
+
+    #[test]
+    fn test_pll_lock() {
+        let mut nco = Nco::new(0.005);
+        let mut pll = Pll::new(0.0, 0.1, 0.002);
+        for _ in 0..20_000 {
+            let y = nco.next_sample() * Complex32::from_polar(1.0, -pll.phase());
+            pll.update(y.im.atan2(y.re));
+        }
+        assert!((pll.freq() - 2.0 * PI * 0.005).abs() < 1e-4);
+    }
+
+    // Gain of the filter for the sine at the normalized frequency
+    fn iir_gain(mut f: impl FnMut(f32) -> f32, freq: f32) -> f32 {
+        let out: Vec<f32> = (0..20_000).map(|i| f((2.0 * PI * freq * i as f32).sin())).collect();
+        out[10_000..].iter().fold(0.0, |acc: f32, x| acc.max(x.abs()))
+    }
+
+    #[test]
+    fn test_butterworth_highpass() {
+        let mut hp = Biquad::butterworth_highpass(300.0 / 48_000.0, 6);
+        assert!(iir_gain(|x| cascade(&mut hp, x), 1_000.0 / 48_000.0) > 0.99);
+        let mut hp = Biquad::butterworth_highpass(300.0 / 48_000.0, 6);
+        assert!((iir_gain(|x| cascade(&mut hp, x), 300.0 / 48_000.0) - 0.707).abs() < 0.01);
+        let mut hp = Biquad::butterworth_highpass(300.0 / 48_000.0, 6);
+        assert!(iir_gain(|x| cascade(&mut hp, x), 150.0 / 48_000.0) < 0.02);
+    }
+
+    #[test]
+    fn test_deemphasis() {
+        // 750 us has the corner frequency at 212 Hz
+        let mut de = Deemphasis::new(750e-6, 48_000.0);
+        assert!((iir_gain(|x| de.process_sample(x), 212.2 / 48_000.0) - 0.707).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_agc() {
+        let times = AgcTimes { attack: 0.001, decay: 0.01, hang: 0.05 };
+        let mut agc = Agc::new(times, 1_000.0, 0.5, 100.0);
+        // Attack
+        let gains: Vec<f32> = (0..20).map(|_| agc.gain(2.0)).collect();
+        assert!((gains[19] - 0.25).abs() < 1e-3);
+        // Hang holds the gain after the signal drops
+        let gains: Vec<f32> = (0..100).map(|_| agc.gain(0.2)).collect();
+        assert!((gains[40] - 0.25).abs() < 1e-3);
+        assert!((gains[99] - 2.5).abs() < 0.2);
+        // Gain is limited when there is no signal
+        let gains: Vec<f32> = (0..1000).map(|_| agc.gain(0.0)).collect();
+        assert_eq!(gains[999], 100.0);
+    }
+
+    #[test]
+    fn test_quadrature_detector() {
+        let mut nco = Nco::new(0.01);
+        let mut demod = QuadratureDetector::new();
+        let out: Vec<f32> = (0..100).map(|_| demod.process_sample(nco.next_sample())).collect();
+        assert!((out[50] - 2.0 * PI * 0.01).abs() < 1e-4);
+    }
+}
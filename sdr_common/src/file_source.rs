@@ -0,0 +1,112 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futuresdr::anyhow::Result;
+use futuresdr::async_io::Timer;
+use futuresdr::log;
+use futuresdr::macros::async_trait;
+use futuresdr::num_complex::Complex32;
+use futuresdr::runtime::BlockMeta;
+use futuresdr::runtime::BlockMetaBuilder;
+use futuresdr::runtime::Kernel;
+use futuresdr::runtime::MessageIo;
+use futuresdr::runtime::MessageIoBuilder;
+use futuresdr::runtime::StreamIo;
+use futuresdr::runtime::StreamIoBuilder;
+use futuresdr::runtime::WorkIo;
+use futuresdr::runtime::{Block, TypedBlock};
+
+use crate::iq_file::{FileArgs, IqFile, IqReader};
+
+/// Reader shared by the flowgraph and the UI, which can seek in the file
+pub type SharedReader = Arc<Mutex<IqReader>>;
+
+// How long to wait when the samples are ahead of the real time
+const THROTTLE_SLEEP: Duration = Duration::from_millis(10);
+
+/// Open the IQ file at the start position from the args.
+/// Returns the reader and if the file should be throttled.
+pub fn open(args: &FileArgs) -> Result<(SharedReader, bool)> {
+    let file = IqFile::open(args)?;
+    log::info!("Play {} {:?} at {} S/s, {} Hz", file.data.display(), file.encoding, file.sample_rate, file.frequency);
+    let start = (args.start * file.sample_rate) as u64;
+    let mut reader = IqReader::open(file, args.looping)?;
+    reader.seek(start)?;
+    Ok((Arc::new(Mutex::new(reader)), args.throttle))
+}
+
+/// Play the IQ file in place of the SDR device.
+///
+/// With the throttle the samples are produced at the sample rate of the file,
+/// otherwise as fast as the flowgraph consumes them.
+///
+/// # Outputs
+///
+/// `out`: IQ samples
+pub struct FileSource {
+    reader: SharedReader,
+    // Sample rate when throttled
+    throttle: Option<f64>,
+    started: Option<Instant>,
+    produced: u64,
+}
+
+impl FileSource {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(reader: SharedReader, throttle: bool) -> Block {
+        Block::from_typed(Self::new_typed(reader, throttle))
+    }
+
+    pub fn new_typed(reader: SharedReader, throttle: bool) -> TypedBlock<Self> {
+        let rate = reader.lock().unwrap().info().sample_rate;
+        TypedBlock::new(
+            BlockMetaBuilder::new("FileSource").build(),
+            StreamIoBuilder::new().add_output::<Complex32>("out").build(),
+            MessageIoBuilder::<Self>::new().build(),
+            FileSource { reader, throttle: throttle.then_some(rate), started: None, produced: 0 },
+        )
+    }
+}
+
+#[doc(hidden)]
+#[async_trait]
+impl Kernel for FileSource {
+    async fn work(
+        &mut self,
+        io: &mut WorkIo,
+        sio: &mut StreamIo,
+        _mio: &mut MessageIo<Self>,
+        _meta: &mut BlockMeta,
+    ) -> Result<()> {
+        let o = sio.output(0).slice::<Complex32>();
+        let mut n = o.len();
+
+        if let Some(rate) = self.throttle {
+            let started = *self.started.get_or_insert_with(Instant::now);
+            let due = (started.elapsed().as_secs_f64() * rate) as u64;
+            let allowed = due.saturating_sub(self.produced) as usize;
+            if allowed == 0 {
+                io.block_on(async move { Timer::after(THROTTLE_SLEEP).await; });
+                return Ok(());
+            }
+            if allowed < n {
+                n = allowed;
+                io.call_again = true;
+            }
+        }
+
+        let mut reader = self.reader.lock().unwrap();
+        let read = reader.read(&mut o[..n])?;
+        // Empty looping file has no samples to produce
+        let finished = reader.is_finished() || (read == 0 && n > 0);
+        drop(reader);
+
+        sio.output(0).produce(read);
+        self.produced += read as u64;
+        if finished {
+            io.finished = true;
+        }
+
+        Ok(())
+    }
+}
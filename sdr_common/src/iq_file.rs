@@ -0,0 +1,437 @@
+//! IQ files which replace the SDR device.
+//!
+//! The file is chosen with the device args, e.g. `file=capture.cu8,rate=2048000,frequency=100e6`.
+//! Supported are SigMF recordings, raw cu8 (rtl_sdr), cs8 (hackrf_transfer), cs16 and cf32 files
+//! and stereo WAV files with I and Q in the channels.
+//! Raw files don't store the sample rate, so it must be given in the args.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use futuresdr::anyhow::{anyhow, bail, Result};
+use futuresdr::num_complex::Complex32;
+
+use crate::sigmf::Meta;
+
+
+/// Encoding of the IQ samples in the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Unsigned 8 bit, as written by rtl_sdr
+    Cu8,
+    /// Signed 8 bit, as written by hackrf_transfer
+    Cs8,
+    /// Signed 16 bit little endian
+    Cs16,
+    /// 32 bit float little endian
+    Cf32,
+}
+
+/// Settings of the file source parsed from the device args
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileArgs {
+    pub path: PathBuf,
+    /// Encoding of the raw file, when its extension doesn't tell
+    pub encoding: Option<Encoding>,
+    /// Overrides the sample rate from the file
+    pub rate: Option<f64>,
+    /// Overrides the center frequency from the file
+    pub frequency: Option<f64>,
+    /// Start again from the beginning at the end of the file
+    pub looping: bool,
+    /// Produce the samples at the sample rate, like the device would
+    pub throttle: bool,
+    /// Position in seconds where the playback starts
+    pub start: f64,
+}
+
+/// IQ samples found in the file
+#[derive(Debug, Clone, PartialEq)]
+pub struct IqFile {
+    /// File with the samples, which is the data file of the SigMF recording
+    pub data: PathBuf,
+    pub encoding: Encoding,
+    /// Bytes before the first sample
+    pub offset: u64,
+    /// Number of the samples
+    pub samples: u64,
+    pub sample_rate: f64,
+    /// Center frequency, zero when unknown
+    pub frequency: f64,
+}
+
+/// Reads samples from the IQ file
+pub struct IqReader {
+    file: File,
+    info: IqFile,
+    looping: bool,
+    position: u64,
+    buffer: Vec<u8>,
+}
+
+impl Encoding {
+    /// Size of one IQ sample in bytes
+    pub fn sample_size(&self) -> usize {
+        match self {
+            Encoding::Cu8 | Encoding::Cs8 => 2,
+            Encoding::Cs16 => 4,
+            Encoding::Cf32 => 8,
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "cu8" | "u8" => Some(Encoding::Cu8),
+            "cs8" | "s8" | "ci8" => Some(Encoding::Cs8),
+            "cs16" | "s16" | "ci16" | "ci16_le" => Some(Encoding::Cs16),
+            "cf32" | "fc32" | "cfile" | "cf32_le" => Some(Encoding::Cf32),
+            _ => None,
+        }
+    }
+
+    // Samples scaled to the range -1..1
+    fn decode(&self, bytes: &[u8], samples: &mut [Complex32]) {
+        let size = self.sample_size();
+        for (sample, b) in samples.iter_mut().zip(bytes.chunks_exact(size)) {
+            *sample = match self {
+                Encoding::Cu8 => Complex32::new((b[0] as f32 - 127.5) / 127.5, (b[1] as f32 - 127.5) / 127.5),
+                Encoding::Cs8 => Complex32::new(b[0] as i8 as f32 / 128.0, b[1] as i8 as f32 / 128.0),
+                Encoding::Cs16 => Complex32::new(
+                    i16::from_le_bytes([b[0], b[1]]) as f32 / i16::MAX as f32,
+                    i16::from_le_bytes([b[2], b[3]]) as f32 / i16::MAX as f32,
+                ),
+                Encoding::Cf32 => Complex32::new(
+                    f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+                    f32::from_le_bytes([b[4], b[5], b[6], b[7]]),
+                ),
+            };
+        }
+    }
+}
+
+impl FileArgs {
+    /// Settings from the args like "file=capture.cu8,rate=2048000,loop=false".
+    /// Returns none when the args don't name a file, so they are for the device.
+    pub fn parse(args: &str) -> Option<Result<Self>> {
+        let items: Vec<(&str, &str)> = args.split(',')
+            .map(|item| item.split_once('=').unwrap_or((item, "")))
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .collect();
+        let path = items.iter().find(|(key, _)| *key == "file")?.1;
+        Some(Self::parse_items(path, &items))
+    }
+
+    fn parse_items(path: &str, items: &[(&str, &str)]) -> Result<Self> {
+        let mut args = Self {
+            path: PathBuf::from(path),
+            encoding: None,
+            rate: None,
+            frequency: None,
+            looping: true,
+            throttle: true,
+            start: 0.0,
+        };
+        for &(key, value) in items {
+            let number = || value.parse::<f64>().map_err(|_| anyhow!("Wrong number: {key}={value}"));
+            let flag = || value.parse::<bool>().map_err(|_| anyhow!("Expected true or false: {key}={value}"));
+            match key {
+                "format" => args.encoding = Some(Encoding::from_name(value).ok_or_else(|| anyhow!("Unknown format: {value}"))?),
+                "rate" => args.rate = Some(number()?),
+                "frequency" | "freq" => args.frequency = Some(number()?),
+                "loop" => args.looping = flag()?,
+                "throttle" => args.throttle = flag()?,
+                "start" => args.start = number()?,
+                // Other args are for the device
+                _ => {}
+            }
+        }
+        Ok(args)
+    }
+}
+
+impl IqFile {
+    /// Find the samples and their format in the file
+    pub fn open(args: &FileArgs) -> Result<Self> {
+        let extension = args.path.extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let mut file = match extension.as_str() {
+            "sigmf-meta" | "sigmf-data" => Self::sigmf(&args.path)?,
+            "wav" => Self::wav(&args.path)?,
+            _ => {
+                let encoding = args.encoding
+                    .or_else(|| Encoding::from_name(&extension))
+                    .ok_or_else(|| anyhow!("Unknown format of {}, add format=cu8, cs8, cs16 or cf32", args.path.display()))?;
+                Self::raw(&args.path, encoding, 0)?
+            }
+        };
+        if let Some(rate) = args.rate {
+            file.sample_rate = rate;
+        }
+        if let Some(frequency) = args.frequency {
+            file.frequency = frequency;
+        }
+        if file.sample_rate <= 0.0 {
+            bail!("Sample rate of {} is unknown, add rate=<samples per second>", args.path.display());
+        }
+        Ok(file)
+    }
+
+    /// Length in seconds
+    pub fn duration(&self) -> f64 {
+        self.samples as f64 / self.sample_rate
+    }
+
+    // Raw samples from the offset to the end of the file
+    fn raw(path: &Path, encoding: Encoding, offset: u64) -> Result<Self> {
+        let size = fs::metadata(path)?.len();
+        Ok(Self {
+            data: path.to_path_buf(),
+            encoding,
+            offset,
+            samples: size.saturating_sub(offset) / encoding.sample_size() as u64,
+            sample_rate: 0.0,
+            frequency: 0.0,
+        })
+    }
+
+    // Format, rate and frequency from the metadata next to the data file
+    fn sigmf(path: &Path) -> Result<Self> {
+        let meta = Meta::read(&path.with_extension("sigmf-meta"))?;
+        let datatype = &meta.global.datatype;
+        let encoding = Encoding::from_name(datatype).ok_or_else(|| anyhow!("Unsupported SigMF datatype: {datatype}"))?;
+        let mut file = Self::raw(&path.with_extension("sigmf-data"), encoding, 0)?;
+        file.sample_rate = meta.global.sample_rate.unwrap_or(0.0);
+        file.frequency = meta.captures.first().and_then(|c| c.frequency).unwrap_or(0.0);
+        // Samples are played at one center frequency, so a retune in the recording can't be followed
+        if let Some(c) = meta.captures.iter().find(|c| c.frequency.unwrap_or(0.0) != file.frequency) {
+            bail!("SigMF recording is retuned to {} Hz at sample {}, only recordings at one frequency can be played",
+                c.frequency.unwrap_or(0.0), c.sample_start);
+        }
+        Ok(file)
+    }
+
+    fn wav(path: &Path) -> Result<Self> {
+        let mut header = vec![0; 4096];
+        let n = File::open(path)?.read(&mut header)?;
+        let (encoding, sample_rate, offset, size) = parse_wav(&header[..n])?;
+        let mut file = Self::raw(path, encoding, offset)?;
+        file.samples = file.samples.min(size / encoding.sample_size() as u64);
+        file.sample_rate = sample_rate;
+        Ok(file)
+    }
+}
+
+impl IqReader {
+    pub fn open(info: IqFile, looping: bool) -> io::Result<Self> {
+        let mut file = File::open(&info.data)?;
+        file.seek(SeekFrom::Start(info.offset))?;
+        Ok(Self { file, info, looping, position: 0, buffer: vec![] })
+    }
+
+    pub fn info(&self) -> &IqFile {
+        &self.info
+    }
+
+    /// Fill the samples. Returns how many were read, zero at the end of the file.
+    pub fn read(&mut self, samples: &mut [Complex32]) -> io::Result<usize> {
+        if self.position >= self.info.samples && self.looping && self.info.samples > 0 {
+            self.seek(0)?;
+        }
+        let n = samples.len().min((self.info.samples - self.position.min(self.info.samples)) as usize);
+        let size = self.info.encoding.sample_size();
+        self.buffer.resize(n * size, 0);
+        self.file.read_exact(&mut self.buffer)?;
+        self.info.encoding.decode(&self.buffer, &mut samples[..n]);
+        self.position += n as u64;
+        Ok(n)
+    }
+
+    /// Continue from the sample
+    pub fn seek(&mut self, sample: u64) -> io::Result<()> {
+        self.position = sample.min(self.info.samples);
+        let size = self.info.encoding.sample_size() as u64;
+        self.file.seek(SeekFrom::Start(self.info.offset + self.position * size))?;
+        Ok(())
+    }
+
+    /// Index of the next sample
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// True when all samples were read and the file doesn't loop
+    pub fn is_finished(&self) -> bool {
+        !self.looping && self.position >= self.info.samples
+    }
+}
+
+// Encoding, sample rate, offset and size in bytes of the IQ samples in the WAV file
+fn parse_wav(header: &[u8]) -> Result<(Encoding, f64, u64, u64)> {
+    if header.len() < 12 || &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        bail!("Not a WAV file");
+    }
+    let u16_at = |pos: usize| u16::from_le_bytes([header[pos], header[pos + 1]]);
+    let u32_at = |pos: usize| u32::from_le_bytes([header[pos], header[pos + 1], header[pos + 2], header[pos + 3]]);
+
+    let mut format = None;
+    let mut pos = 12;
+    while pos + 8 <= header.len() {
+        let id = &header[pos..pos + 4];
+        let size = u32_at(pos + 4) as u64;
+        let body = pos + 8;
+        if id == b"fmt " && body + 16 <= header.len() {
+            let mut audio_format = u16_at(body);
+            // WAVE_FORMAT_EXTENSIBLE keeps the format in the sub format GUID
+            if audio_format == 0xFFFE && body + 26 <= header.len() {
+                audio_format = u16_at(body + 24);
+            }
+            let (channels, rate, bits) = (u16_at(body + 2), u32_at(body + 4), u16_at(body + 14));
+            if channels != 2 {
+                bail!("WAV file must have 2 channels with I and Q, found {channels}");
+            }
+            let encoding = match (audio_format, bits) {
+                (1, 8) => Encoding::Cu8,
+                (1, 16) => Encoding::Cs16,
+                (3, 32) => Encoding::Cf32,
+                _ => bail!("Unsupported WAV format {audio_format} with {bits} bits"),
+            };
+            format = Some((encoding, rate as f64));
+        } else if id == b"data" {
+            let (encoding, rate) = format.ok_or_else(|| anyhow!("WAV data before the format"))?;
+            return Ok((encoding, rate, body as u64, size));
+        }
+        // Chunks are aligned to 2 bytes
+        pos = body + size as usize + (size % 2) as usize;
+    }
+    bail!("Missing WAV data")
+}
+
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sdr_common_iq_file_{}_{name}", std::process::id()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(FileArgs::parse("driver=rtlsdr").map(|a| a.is_ok()), None);
+        let args = FileArgs::parse("file=/tmp/capture.bin, format=cs8, rate=2e6, frequency=100e6, loop=false, start=1.5")
+            .unwrap()
+            .unwrap();
+        assert_eq!(args.path, PathBuf::from("/tmp/capture.bin"));
+        assert_eq!(args.encoding, Some(Encoding::Cs8));
+        assert_eq!(args.rate, Some(2e6));
+        assert_eq!(args.frequency, Some(100e6));
+        assert!(!args.looping);
+        assert!(args.throttle);
+        assert_eq!(args.start, 1.5);
+        assert!(FileArgs::parse("file=a.cu8,rate=fast").unwrap().is_err());
+        assert!(FileArgs::parse("rate=fast,file=a.cu8").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_decode() {
+        let mut samples = [Complex32::default(); 2];
+        Encoding::Cu8.decode(&[0, 255, 128, 127], &mut samples);
+        assert_eq!(samples[0], Complex32::new(-1.0, 1.0));
+        assert!((samples[1].re - 0.5 / 127.5).abs() < 1e-6);
+        Encoding::Cs8.decode(&[0x80, 64, 0, 0], &mut samples);
+        assert_eq!(samples[0], Complex32::new(-1.0, 0.5));
+        Encoding::Cs16.decode(&[0xFF, 0x7F, 0x00, 0x00, 0x01, 0x80, 0x00, 0x40], &mut samples);
+        assert_eq!(samples[0], Complex32::new(1.0, 0.0));
+        assert_eq!(samples[1].re, -1.0);
+    }
+
+    #[test]
+    fn test_parse_wav() {
+        let mut wav = b"RIFF\0\0\0\0WAVE".to_vec();
+        wav.extend(b"LIST\x03\0\0\0abc\0");
+        wav.extend(b"fmt \x10\0\0\0");
+        wav.extend(1u16.to_le_bytes());
+        wav.extend(2u16.to_le_bytes());
+        wav.extend(2_048_000u32.to_le_bytes());
+        wav.extend(8_192_000u32.to_le_bytes());
+        wav.extend(4u16.to_le_bytes());
+        wav.extend(16u16.to_le_bytes());
+        wav.extend(b"data\x08\0\0\0");
+        assert_eq!(parse_wav(&wav).unwrap(), (Encoding::Cs16, 2_048_000.0, 56, 8));
+        assert!(parse_wav(b"RIFF\0\0\0\0AVI ").is_err());
+    }
+
+    #[test]
+    fn test_sigmf() {
+        let base = temp_path("sigmf");
+        fs::write(base.with_extension("sigmf-meta"), r#"{"global": {"core:datatype": "ci16_le", "core:sample_rate": 1000000}, "captures": [{"core:sample_start": 0, "core:frequency": 92000000}]}"#).unwrap();
+        fs::write(base.with_extension("sigmf-data"), [0; 40]).unwrap();
+        let args = FileArgs::parse(&format!("file={}", base.with_extension("sigmf-meta").display())).unwrap().unwrap();
+        let file = IqFile::open(&args).unwrap();
+        assert_eq!(file.encoding, Encoding::Cs16);
+        assert_eq!(file.samples, 10);
+        assert_eq!(file.sample_rate, 1e6);
+        assert_eq!(file.frequency, 92e6);
+        fs::remove_file(base.with_extension("sigmf-meta")).unwrap();
+        fs::remove_file(base.with_extension("sigmf-data")).unwrap();
+    }
+
+    #[test]
+    fn test_read_loop_and_seek() {
+        let path = temp_path("read.cs8");
+        fs::write(&path, [0, 0, 64, 64, 32, 32, 7]).unwrap();
+        let args = FileArgs::parse(&format!("file={},rate=1000", path.display())).unwrap().unwrap();
+        let file = IqFile::open(&args).unwrap();
+        assert_eq!(file.samples, 3);
+        assert!(IqFile::open(&FileArgs { rate: None, ..args.clone() }).is_err());
+
+        let mut reader = IqReader::open(file.clone(), true).unwrap();
+        let mut samples = [Complex32::default(); 5];
+        assert_eq!(reader.read(&mut samples).unwrap(), 3);
+        assert_eq!(samples[1], Complex32::new(0.5, 0.5));
+        assert_eq!(reader.read(&mut samples).unwrap(), 3);
+        reader.seek(2).unwrap();
+        assert_eq!(reader.read(&mut samples[..1]).unwrap(), 1);
+        assert_eq!(samples[0], Complex32::new(0.25, 0.25));
+
+        let mut reader = IqReader::open(file, false).unwrap();
+        assert_eq!(reader.read(&mut samples).unwrap(), 3);
+        assert!(reader.is_finished());
+        assert_eq!(reader.read(&mut samples).unwrap(), 0);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_sigmf_other_recorder() {
+        // Key names in the strings and the unknown fields don't confuse the reader
+        let base = temp_path("sigmf_other");
+        let meta = r#"{
+            "global": {"core:description": "core:frequency 1", "core:datatype": "cf32_le", "core:version": "1.0.0",
+                       "core:sample_rate": 2.4e6, "core:extensions": [{"name": "x", "version": "1", "optional": true}]},
+            "captures": [{"core:sample_start": 0, "core:frequency": 1.0e8}, {"core:sample_start": 1, "core:frequency": 1e8}],
+            "annotations": [{"core:sample_start": 0, "core:label": "\"core:sample_rate\": 1"}]
+        }"#;
+        fs::write(base.with_extension("sigmf-meta"), meta).unwrap();
+        fs::write(base.with_extension("sigmf-data"), [0; 16]).unwrap();
+        let args = FileArgs::parse(&format!("file={}", base.with_extension("sigmf-data").display())).unwrap().unwrap();
+        let file = IqFile::open(&args).unwrap();
+        assert_eq!(file.encoding, Encoding::Cf32);
+        assert_eq!(file.samples, 2);
+        assert_eq!(file.sample_rate, 2.4e6);
+        assert_eq!(file.frequency, 1e8);
+
+        // Retuned recording is refused
+        fs::write(base.with_extension("sigmf-meta"), meta.replace("\"core:frequency\": 1e8", "\"core:frequency\": 2e8")).unwrap();
+        assert!(IqFile::open(&args).is_err());
+
+        fs::write(base.with_extension("sigmf-meta"), r#"{"global": {"core:sample_rate": 1}}"#).unwrap();
+        assert!(IqFile::open(&args).is_err());
+        fs::remove_file(base.with_extension("sigmf-meta")).unwrap();
+        fs::remove_file(base.with_extension("sigmf-data")).unwrap();
+    }
+}
@@ -0,0 +1,83 @@
+use std::sync::{Arc, Mutex};
+
+use futuresdr::anyhow::Result;
+use futuresdr::log;
+use futuresdr::macros::async_trait;
+use futuresdr::num_complex::Complex32;
+use futuresdr::runtime::BlockMeta;
+use futuresdr::runtime::BlockMetaBuilder;
+use futuresdr::runtime::Kernel;
+use futuresdr::runtime::MessageIo;
+use futuresdr::runtime::MessageIoBuilder;
+use futuresdr::runtime::StreamIo;
+use futuresdr::runtime::StreamIoBuilder;
+use futuresdr::runtime::WorkIo;
+use futuresdr::runtime::{Block, TypedBlock};
+
+use crate::sigmf::SigmfWriter;
+
+/// Recording shared by the flowgraph and the UI, none when not recording
+pub type SharedRecorder = Arc<Mutex<Option<SigmfWriter>>>;
+
+/// Write IQ samples to the SigMF recording, if there is one.
+///
+/// Samples are always consumed, so the block never stops the flowgraph.
+/// The recording is dropped when writing fails, e.g. when the disk is full.
+///
+/// # Inputs
+///
+/// `in`: IQ samples
+pub struct IqRecorder {
+    recorder: SharedRecorder,
+}
+
+impl IqRecorder {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(recorder: SharedRecorder) -> Block {
+        Block::from_typed(Self::new_typed(recorder))
+    }
+
+    pub fn new_typed(recorder: SharedRecorder) -> TypedBlock<Self> {
+        TypedBlock::new(
+            BlockMetaBuilder::new("IqRecorder").build(),
+            StreamIoBuilder::new().add_input::<Complex32>("in").build(),
+            MessageIoBuilder::<Self>::new().build(),
+            IqRecorder { recorder },
+        )
+    }
+}
+
+#[doc(hidden)]
+#[async_trait]
+impl Kernel for IqRecorder {
+    async fn work(
+        &mut self,
+        io: &mut WorkIo,
+        sio: &mut StreamIo,
+        _mio: &mut MessageIo<Self>,
+        _meta: &mut BlockMeta,
+    ) -> Result<()> {
+        let i = sio.input(0).slice::<Complex32>();
+
+        let mut recorder = self.recorder.lock().unwrap();
+        if let Some(writer) = recorder.as_mut() {
+            if let Err(e) = writer.write(i) {
+                log::warn!("IQ recording to {} stopped: {e}", writer.path().display());
+                // Metadata is written by finish, so the samples can be read
+                if let Some(Err(e)) = recorder.take().map(|writer| writer.finish()) {
+                    log::error!("IQ recording can't be finished: {e}");
+                }
+            }
+        }
+        drop(recorder);
+
+        let n = i.len();
+        sio.input(0).consume(n);
+
+        if sio.input(0).finished() {
+            io.finished = true;
+        }
+
+        Ok(())
+    }
+}
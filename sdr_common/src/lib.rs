@@ -0,0 +1,12 @@
+//! Code shared by yasa, fmradio, spectrum and antenna.
+//!
+//! Decoders and filters work on plain samples, so the apps can run them inside their own blocks.
+
+pub mod dsp;
+pub mod file_source;
+pub mod iq_file;
+pub mod iq_recorder;
+pub mod rds;
+pub mod shift;
+pub mod sigmf;
+pub mod stereo;
@@ -0,0 +1,540 @@
+//! Radio Data System decoder
+//!
+//! RDS is sent as the BPSK on the 57 kHz subcarrier of the FM multiplex signal.
+//! Bits (1187.5 bit/s) are differentially encoded and sent as biphase symbols.
+//! Every group has 4 blocks of 26 bits: 16 bits of data and 10 bits of the check word
+//! with the offset word added, which identifies the block position in the group.
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::fmt;
+
+use futuresdr::num_complex::Complex32;
+
+use crate::dsp::{lowpass, num_taps, Fir, Nco, Pll};
+
+
+const SUBCARRIER: f32 = 57_000.0;
+const BIT_RATE: f32 = 1_187.5;
+// Check word generator x^10 + x^8 + x^7 + x^5 + x^4 + x^3 + 1
+const POLY: u32 = 0x5B9;
+// Offset words of the blocks A, B, C, D and C'
+const OFFSETS: [u16; 5] = [0x0FC, 0x198, 0x168, 0x1B4, 0x350];
+// Sync is lost after this number of the uncorrectable blocks in a row
+const MAX_BAD_BLOCKS: usize = 8;
+
+/// Information decoded from the RDS groups
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RdsInfo {
+    /// Programme identification
+    pub pi: Option<u16>,
+    /// Programme type
+    pub pty: Option<u8>,
+    /// Programme service name (station name)
+    pub ps: String,
+    pub radiotext: String,
+    /// Clock time sent by the station
+    pub time: Option<ClockTime>,
+}
+
+/// Clock time and date from the group 4A
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockTime {
+    /// Modified Julian Day
+    pub mjd: u32,
+    /// UTC hour
+    pub hour: u8,
+    /// UTC minute
+    pub minute: u8,
+    /// Local time offset in half hours
+    pub offset: i8,
+}
+
+pub struct RdsDecoder {
+    nco: Nco,
+    filter: Fir<Complex32, f32>,
+    // Costas loop which removes the phase of the subcarrier
+    costas: Pll,
+    clock: BitClock,
+    last_bit: bool,
+    sync: BlockSync,
+    groups: GroupParser,
+}
+
+// Recovers biphase symbols with the early-late gate
+struct BitClock {
+    samples_per_bit: f32,
+    // Samples since the last bit
+    phase: f32,
+    // Length of the correlation window and the early-late spacing
+    window: usize,
+    spacing: usize,
+    history: VecDeque<f32>,
+    // Average correlation at the bit center and half of the bit later
+    level: f32,
+    half_level: f32,
+}
+
+// Finds the block boundaries and corrects errors
+struct BlockSync {
+    register: u32,
+    synced: bool,
+    bits: usize,
+    // Position of the next block in the group
+    block: usize,
+    // Last block found while searching: position in the group and bit count
+    candidate: Option<(usize, usize)>,
+    bad_blocks: usize,
+    group: [Option<u16>; 4],
+    corrections: HashMap<u16, u32>,
+}
+
+struct GroupParser {
+    info: RdsInfo,
+    ps: [u8; 8],
+    radiotext: [u8; 64],
+    text_ab: Option<bool>,
+}
+
+impl RdsDecoder {
+    /// `rate` is the rate of the multiplex signal
+    pub fn new(rate: f32) -> Self {
+        // Filter 2.4 kHz around the subcarrier and decimate to about 20 kHz
+        let decim = ((rate / 20_000.0) as usize).max(1);
+        let taps = lowpass(2_400.0 / rate, num_taps(1_200.0 / rate));
+        let baseband_rate = rate / decim as f32;
+        Self {
+            nco: Nco::new(-SUBCARRIER / rate),
+            filter: Fir::with_decimation(taps, decim),
+            costas: Pll::new(0.0, 2.0 * PI * 10.0 / baseband_rate, 20.0 / baseband_rate),
+            clock: BitClock::new(baseband_rate / BIT_RATE),
+            last_bit: false,
+            sync: BlockSync::new(),
+            groups: GroupParser::new(),
+        }
+    }
+
+    pub fn info(&self) -> &RdsInfo {
+        &self.groups.info
+    }
+
+    /// True when the decoder is synchronized to the blocks
+    pub fn is_synced(&self) -> bool {
+        self.sync.synced
+    }
+
+    /// Process multiplex sample. Returns true when the group was decoded.
+    pub fn process_sample(&mut self, mpx: f32) -> bool {
+        let Some(x) = self.filter.process_sample(mpx * self.nco.next_sample()) else {
+            return false;
+        };
+        let y = x * Complex32::from_polar(1.0, -self.costas.phase());
+        // BPSK phase error, which ignores the data sign
+        let error = if y.re != 0.0 { (y.im / y.re).atan() } else { 0.0 };
+        self.costas.update(error);
+
+        if let Some(symbol) = self.clock.process_sample(y.re) {
+            // Differential decoding
+            let bit = symbol > 0.0;
+            let data = bit != self.last_bit;
+            self.last_bit = bit;
+            if let Some(group) = self.sync.process_bit(data) {
+                self.groups.process(group);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+impl BitClock {
+    fn new(samples_per_bit: f32) -> Self {
+        let window = 2 * (samples_per_bit / 2.0).round() as usize;
+        let spacing = (samples_per_bit / 8.0).round().max(1.0) as usize;
+        Self {
+            samples_per_bit,
+            phase: 0.0,
+            window,
+            spacing,
+            history: VecDeque::from(vec![0.0; window + window / 2 + 2 * spacing]),
+            level: 0.0,
+            half_level: 0.0,
+        }
+    }
+
+    // Correlation with the biphase symbol ending `delay` samples ago
+    fn correlate(&self, delay: usize) -> f32 {
+        let end = self.history.len() - delay;
+        let half = self.window / 2;
+        let first: f32 = self.history.range(end - self.window..end - half).sum();
+        let second: f32 = self.history.range(end - half..end).sum();
+        first - second
+    }
+
+    // Returns the correlation at the bit center once per bit
+    fn process_sample(&mut self, x: f32) -> Option<f32> {
+        self.history.pop_front();
+        self.history.push_back(x);
+        self.phase += 1.0;
+        if self.phase < self.samples_per_bit {
+            return None;
+        }
+        self.phase -= self.samples_per_bit;
+
+        let late = self.correlate(0).abs();
+        let symbol = self.correlate(self.spacing);
+        let early = self.correlate(2 * self.spacing).abs();
+        let error = (late - early) / (late + early + f32::EPSILON);
+        // Sample later if the late correlation is stronger
+        self.phase -= 0.5 * error;
+
+        // Biphase has the false lock half of the bit away. It has a weaker average correlation.
+        let half = self.correlate(self.spacing + self.window / 2).abs();
+        self.level += 0.05 * (symbol.abs() - self.level);
+        self.half_level += 0.05 * (half - self.half_level);
+        if self.half_level > 1.2 * self.level {
+            self.phase -= self.samples_per_bit / 2.0;
+            std::mem::swap(&mut self.level, &mut self.half_level);
+        }
+
+        Some(symbol)
+    }
+}
+
+impl BlockSync {
+    fn new() -> Self {
+        // Syndromes of all the burst errors up to 5 bits long
+        let mut corrections = HashMap::new();
+        for burst in (1..32u32).step_by(2) {
+            for shift in 0..26 {
+                let error = burst << shift;
+                if error < 1 << 26 {
+                    corrections.entry(syndrome(error)).or_insert(error);
+                }
+            }
+        }
+        Self {
+            register: 0,
+            synced: false,
+            bits: 0,
+            block: 0,
+            candidate: None,
+            bad_blocks: 0,
+            group: [None; 4],
+            corrections,
+        }
+    }
+
+    // Returns the group after its last block is received
+    fn process_bit(&mut self, bit: bool) -> Option<[Option<u16>; 4]> {
+        self.register = ((self.register << 1) | bit as u32) & 0x3FF_FFFF;
+        self.bits += 1;
+        if !self.synced {
+            self.search();
+            return None;
+        }
+        if self.bits < 26 {
+            return None;
+        }
+        self.bits = 0;
+
+        let data = self.check(self.block);
+        if data.is_some() {
+            self.bad_blocks = 0;
+        } else {
+            self.bad_blocks += 1;
+            if self.bad_blocks > MAX_BAD_BLOCKS {
+                self.synced = false;
+                self.candidate = None;
+            }
+        }
+        self.group[self.block] = data;
+        self.block = (self.block + 1) % 4;
+        if self.block == 0 {
+            let group = self.group;
+            self.group = [None; 4];
+            Some(group)
+        } else {
+            None
+        }
+    }
+
+    // Sync needs two blocks without errors at the distance matching their positions
+    fn search(&mut self) {
+        let s = syndrome(self.register);
+        let Some(block) = OFFSETS.iter().position(|&o| o == s).map(|b| b.min(2)) else {
+            return;
+        };
+        if let Some((prev, bits)) = self.candidate {
+            let distance = (block + 4 - prev) % 4;
+            if distance > 0 && self.bits - bits == 26 * distance {
+                self.synced = true;
+                self.bad_blocks = 0;
+                self.bits = 0;
+                self.group = [None; 4];
+                self.group[block] = Some((self.register >> 10) as u16);
+                self.block = (block + 1) % 4;
+                return;
+            }
+        }
+        self.candidate = Some((block, self.bits));
+    }
+
+    // Data of the block at the given position, corrected if possible
+    fn check(&self, block: usize) -> Option<u16> {
+        let s = syndrome(self.register);
+        let offsets: &[u16] = if block == 2 { &[OFFSETS[2], OFFSETS[4]] } else { &OFFSETS[block..=block] };
+        offsets.iter()
+            .find_map(|&o| {
+                if s == o {
+                    Some(self.register)
+                } else {
+                    self.corrections.get(&(s ^ o)).map(|e| self.register ^ e)
+                }
+            })
+            .map(|r| (r >> 10) as u16)
+    }
+}
+
+impl GroupParser {
+    fn new() -> Self {
+        Self { info: RdsInfo::default(), ps: [b' '; 8], radiotext: [b' '; 64], text_ab: None }
+    }
+
+    fn process(&mut self, group: [Option<u16>; 4]) {
+        if let Some(a) = group[0] {
+            // Another station
+            if self.info.pi.is_some_and(|pi| pi != a) {
+                *self = Self::new();
+            }
+            self.info.pi = Some(a);
+        }
+        let Some(b) = group[1] else {
+            return;
+        };
+        self.info.pty = Some(((b >> 5) & 0x1F) as u8);
+        let group_type = b >> 12;
+        let version_b = b & 0x800 != 0;
+
+        match (group_type, version_b, group[2], group[3]) {
+            // Programme service name
+            (0, _, _, Some(d)) => {
+                let pos = 2 * (b & 3) as usize;
+                self.ps[pos..pos + 2].copy_from_slice(&d.to_be_bytes());
+                self.info.ps = decode_text(&self.ps);
+            }
+            // Radiotext
+            (2, false, Some(c), Some(d)) => {
+                self.text_ab_flag(b);
+                let pos = 4 * (b & 0xF) as usize;
+                self.radiotext[pos..pos + 2].copy_from_slice(&c.to_be_bytes());
+                self.radiotext[pos + 2..pos + 4].copy_from_slice(&d.to_be_bytes());
+                self.info.radiotext = decode_text(&self.radiotext);
+            }
+            (2, true, _, Some(d)) => {
+                self.text_ab_flag(b);
+                let pos = 2 * (b & 0xF) as usize;
+                self.radiotext[pos..pos + 2].copy_from_slice(&d.to_be_bytes());
+                self.info.radiotext = decode_text(&self.radiotext[..32]);
+            }
+            // Clock time
+            (4, false, Some(c), Some(d)) => {
+                let offset = (d & 0x1F) as i8;
+                self.info.time = Some(ClockTime {
+                    mjd: ((b as u32 & 3) << 15) | (c as u32 >> 1),
+                    hour: (((c & 1) << 4) | (d >> 12)) as u8,
+                    minute: ((d >> 6) & 0x3F) as u8,
+                    offset: if d & 0x20 != 0 { -offset } else { offset },
+                });
+            }
+            _ => (),
+        }
+    }
+
+    // Change of the A/B flag means the new text
+    fn text_ab_flag(&mut self, b: u16) {
+        let ab = b & 0x10 != 0;
+        if self.text_ab.is_some_and(|f| f != ab) {
+            self.radiotext = [b' '; 64];
+        }
+        self.text_ab = Some(ab);
+    }
+}
+
+impl ClockTime {
+    /// Local date as (year, month, day) and time as (hour, minute)
+    pub fn local(&self) -> ((i32, u32, u32), (u32, u32)) {
+        let minutes = self.hour as i32 * 60 + self.minute as i32 + self.offset as i32 * 30;
+        let mjd = self.mjd as i32 + minutes.div_euclid(24 * 60);
+        let minutes = minutes.rem_euclid(24 * 60) as u32;
+        (mjd_to_date(mjd), (minutes / 60, minutes % 60))
+    }
+}
+
+impl fmt::Display for ClockTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ((year, month, day), (hour, minute)) = self.local();
+        write!(f, "{year}-{month:02}-{day:02} {hour:02}:{minute:02}")
+    }
+}
+
+/// Name of the programme type (RDS, Europe)
+pub fn pty_name(pty: u8) -> &'static str {
+    const NAMES: [&str; 32] = [
+        "None", "News", "Current Affairs", "Information", "Sport", "Education", "Drama", "Culture",
+        "Science", "Varied", "Pop Music", "Rock Music", "Easy Listening", "Light Classical", "Serious Classical",
+        "Other Music", "Weather", "Finance", "Children's", "Social Affairs", "Religion", "Phone-in", "Travel",
+        "Leisure", "Jazz Music", "Country Music", "National Music", "Oldies Music", "Folk Music", "Documentary",
+        "Alarm Test", "Alarm",
+    ];
+    NAMES.get(pty as usize).copied().unwrap_or("")
+}
+
+// Remainder of the block divided by the generator polynomial.
+// It is equal to the offset word for the block without errors.
+fn syndrome(block: u32) -> u16 {
+    let mut reg = block;
+    for bit in (10..26).rev() {
+        if reg & (1 << bit) != 0 {
+            reg ^= POLY << (bit - 10);
+        }
+    }
+    reg as u16
+}
+
+// Only the ASCII part of the RDS character set is decoded. Text ends at the carriage return.
+fn decode_text(bytes: &[u8]) -> String {
+    bytes.iter()
+        .take_while(|&&c| c != b'\r')
+        .map(|&c| if (0x20..0x7F).contains(&c) { c as char } else { '?' })
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+// Conversion from the RDS specification (EN 50067 Annex G)
+fn mjd_to_date(mjd: i32) -> (i32, u32, u32) {
+    let mjd = mjd as f64;
+    let y = ((mjd - 15_078.2) / 365.25).floor();
+    let m = ((mjd - 14_956.1 - (y * 365.25).floor()) / 30.6001).floor();
+    let day = mjd - 14_956.0 - (y * 365.25).floor() - (m * 30.6001).floor();
+    let k = if m == 14.0 || m == 15.0 { 1.0 } else { 0.0 };
+    ((y + k) as i32 + 1900, (m - 1.0 - k * 12.0) as u32, day as u32)
+}
+
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: f32 = 240_000.0;
+
+    fn encode_block(data: u16, offset: u16) -> u32 {
+        let word = (data as u32) << 10;
+        word | (syndrome(word) ^ offset) as u32
+    }
+
+    fn groups() -> Vec<[u16; 4]> {
+        let pi = 0x3201;
+        // Pop Music
+        let b = 10 << 5;
+        let mut groups = vec![];
+        for (i, chars) in b"GDANSK  ".chunks(2).enumerate() {
+            groups.push([pi, b | i as u16, 0xE0CD, u16::from_be_bytes([chars[0], chars[1]])]);
+        }
+        for (i, chars) in b"Radio Gdansk 103.7\r     ".chunks(4).enumerate() {
+            let c = u16::from_be_bytes([chars[0], chars[1]]);
+            let d = u16::from_be_bytes([chars[2], chars[3]]);
+            groups.push([pi, 0x2000 | b | i as u16, c, d]);
+        }
+        // 2024-05-01 10:30 UTC, +2 hours
+        let mjd: u32 = 60_431;
+        let (hour, minute, offset) = (10u16, 30u16, 4u16);
+        groups.push([
+            pi,
+            0x4000 | b | (mjd >> 15) as u16,
+            ((mjd << 1) as u16) | (hour >> 4),
+            (hour << 12) | (minute << 6) | offset,
+        ]);
+        groups
+    }
+
+    // Multiplex signal with the mono audio, pilot and RDS
+    fn mpx(groups: &[[u16; 4]], repeat: usize) -> Vec<f32> {
+        let mut bits = vec![];
+        for group in groups.iter().cycle().take(groups.len() * repeat) {
+            for (block, &data) in group.iter().enumerate() {
+                let word = encode_block(data, OFFSETS[block]);
+                bits.extend((0..26).rev().map(|i| word & (1 << i) != 0));
+            }
+        }
+        // Differential encoding
+        let mut level = false;
+        let symbols: Vec<f32> = bits.iter()
+            .map(|&b| {
+                level ^= b;
+                if level { 1.0 } else { -1.0 }
+            })
+            .collect();
+
+        let samples_per_bit = RATE / BIT_RATE;
+        let len = (symbols.len() as f32 * samples_per_bit) as usize;
+        (0..len)
+            .map(|i| {
+                let t = i as f32 / RATE;
+                let pos = i as f32 / samples_per_bit;
+                let biphase = if pos.fract() < 0.5 { 1.0 } else { -1.0 };
+                let rds = symbols[pos as usize] * biphase;
+                0.4 * (2.0 * PI * 1_000.0 * t).sin()
+                    + 0.09 * (2.0 * PI * 19_000.0 * t).sin()
+                    + 0.04 * rds * (2.0 * PI * SUBCARRIER * t + 1.0).sin()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_syndrome() {
+        for (i, &offset) in OFFSETS.iter().enumerate() {
+            assert_eq!(syndrome(encode_block(0x1234 + i as u16, offset)), offset);
+        }
+    }
+
+    #[test]
+    fn test_error_correction() {
+        let mut sync = BlockSync::new();
+        let block = encode_block(0xBEEF, OFFSETS[1]);
+
+        sync.register = block ^ (0b10111 << 12);
+        assert_eq!(sync.check(1), Some(0xBEEF));
+        sync.register = block ^ 1;
+        assert_eq!(sync.check(1), Some(0xBEEF));
+        // Errors far apart can't be corrected
+        sync.register = block ^ (1 << 25) ^ 1;
+        assert_eq!(sync.check(1), None);
+    }
+
+    #[test]
+    fn test_decode() {
+        let mut decoder = RdsDecoder::new(RATE);
+        let decoded = mpx(&groups(), 4).into_iter().filter(|&x| decoder.process_sample(x)).count();
+        let info = decoder.info();
+
+        assert!(decoder.is_synced());
+        assert!(decoded >= groups().len());
+        assert_eq!(info.pi, Some(0x3201));
+        assert_eq!(info.pty.map(pty_name), Some("Pop Music"));
+        assert_eq!(info.ps, "GDANSK");
+        assert_eq!(info.radiotext, "Radio Gdansk 103.7");
+        assert_eq!(info.time.unwrap().to_string(), "2024-05-01 12:30");
+    }
+
+    #[test]
+    fn test_mjd_to_date() {
+        assert_eq!(mjd_to_date(45_218), (1982, 9, 6));
+        assert_eq!(mjd_to_date(60_431), (2024, 5, 1));
+    }
+}
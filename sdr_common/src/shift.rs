@@ -0,0 +1,91 @@
+use std::sync::{Arc, Mutex};
+
+use futuresdr::anyhow::Result;
+use futuresdr::macros::async_trait;
+use futuresdr::num_complex::Complex32;
+use futuresdr::runtime::BlockMeta;
+use futuresdr::runtime::BlockMetaBuilder;
+use futuresdr::runtime::Kernel;
+use futuresdr::runtime::MessageIo;
+use futuresdr::runtime::MessageIoBuilder;
+use futuresdr::runtime::StreamIo;
+use futuresdr::runtime::StreamIoBuilder;
+use futuresdr::runtime::WorkIo;
+use futuresdr::runtime::{Block, TypedBlock};
+
+use crate::dsp::Nco;
+
+/// Offset of the VFO from the hardware frequency in Hz
+pub type SharedOffset = Arc<Mutex<f64>>;
+
+/// Shift the VFO frequency to zero.
+///
+/// The offset can be changed from the other thread while the flowgraph is running.
+///
+/// # Inputs
+///
+/// `in`: IQ from the source
+///
+/// # Outputs
+///
+/// `out`: IQ with the VFO at zero frequency
+pub struct Shift {
+    offset: SharedOffset,
+    current: f64,
+    rate: f64,
+    nco: Nco,
+}
+
+impl Shift {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(offset: SharedOffset, rate: f64) -> Block {
+        Block::from_typed(Self::new_typed(offset, rate))
+    }
+
+    pub fn new_typed(offset: SharedOffset, rate: f64) -> TypedBlock<Self> {
+        let current = *offset.lock().unwrap();
+        TypedBlock::new(
+            BlockMetaBuilder::new("Shift").build(),
+            StreamIoBuilder::new()
+                .add_input::<Complex32>("in")
+                .add_output::<Complex32>("out")
+                .build(),
+            MessageIoBuilder::<Self>::new().build(),
+            Shift { offset, current, rate, nco: Nco::new((-current / rate) as f32) },
+        )
+    }
+}
+
+#[doc(hidden)]
+#[async_trait]
+impl Kernel for Shift {
+    async fn work(
+        &mut self,
+        io: &mut WorkIo,
+        sio: &mut StreamIo,
+        _mio: &mut MessageIo<Self>,
+        _meta: &mut BlockMeta,
+    ) -> Result<()> {
+        let offset = *self.offset.lock().unwrap();
+        if offset != self.current {
+            self.current = offset;
+            self.nco.set_freq((-offset / self.rate) as f32);
+        }
+
+        let i = sio.input(0).slice::<Complex32>();
+        let o = sio.output(0).slice::<Complex32>();
+
+        let n = i.len().min(o.len());
+        for (y, x) in o[..n].iter_mut().zip(&i[..n]) {
+            *y = x * self.nco.next_sample();
+        }
+        sio.input(0).consume(n);
+        sio.output(0).produce(n);
+
+        if sio.input(0).finished() && n == i.len() {
+            io.finished = true;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,316 @@
+//! IQ recording in the SigMF format.
+//!
+//! Samples go to the `.sigmf-data` file and the metadata to the `.sigmf-meta` JSON next to it.
+//! Every retune starts a new capture segment, so the recording keeps the frequency of all samples.
+//! See <https://github.com/sigmf/SigMF/blob/main/sigmf-spec.md>
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futuresdr::num_complex::Complex32;
+use serde::{Deserialize, Serialize};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// 32 bit float I and Q
+    Cf32,
+    /// 16 bit integer I and Q, half the size of cf32
+    Ci16,
+}
+
+/// Description of the recording stored in the global metadata
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    pub sample_rate: f64,
+    /// Device which received the signal
+    pub hw: String,
+    pub description: String,
+    /// Application which made the recording
+    pub recorder: String,
+}
+
+/// Content of the `.sigmf-meta` file. Only the fields used by the apps, the others are skipped
+/// when reading.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Meta {
+    pub global: Global,
+    #[serde(default)]
+    pub captures: Vec<Capture>,
+    #[serde(default)]
+    pub annotations: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Global {
+    #[serde(rename = "core:datatype")]
+    pub datatype: String,
+    #[serde(rename = "core:sample_rate", default, skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<f64>,
+    #[serde(rename = "core:version", default)]
+    pub version: String,
+    #[serde(rename = "core:hw", default, skip_serializing_if = "Option::is_none")]
+    pub hw: Option<String>,
+    #[serde(rename = "core:description", default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(rename = "core:recorder", default, skip_serializing_if = "Option::is_none")]
+    pub recorder: Option<String>,
+}
+
+/// Segment of the recording received at one frequency
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Capture {
+    #[serde(rename = "core:sample_start", default)]
+    pub sample_start: u64,
+    #[serde(rename = "core:frequency", default, skip_serializing_if = "Option::is_none")]
+    pub frequency: Option<f64>,
+    #[serde(rename = "core:datetime", default, skip_serializing_if = "Option::is_none")]
+    pub datetime: Option<String>,
+}
+
+impl Meta {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut text = serde_json::to_string_pretty(self)?;
+        text.push('\n');
+        fs::write(path, text)
+    }
+}
+
+pub struct SigmfWriter {
+    base: PathBuf,
+    format: SampleFormat,
+    recording: Recording,
+    data: BufWriter<File>,
+    samples: u64,
+    // Start sample, frequency and time of the capture segments
+    captures: Vec<(u64, f64, SystemTime)>,
+}
+
+impl SampleFormat {
+    pub const ALL: [SampleFormat; 2] = [SampleFormat::Cf32, SampleFormat::Ci16];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SampleFormat::Cf32 => "cf32",
+            SampleFormat::Ci16 => "ci16",
+        }
+    }
+
+    fn datatype(&self) -> &'static str {
+        match self {
+            SampleFormat::Cf32 => "cf32_le",
+            SampleFormat::Ci16 => "ci16_le",
+        }
+    }
+
+    /// Size of one IQ sample in bytes
+    pub fn sample_size(&self) -> u64 {
+        match self {
+            SampleFormat::Cf32 => 8,
+            SampleFormat::Ci16 => 4,
+        }
+    }
+}
+
+impl SigmfWriter {
+    /// Create `<base>.sigmf-data` and `<base>.sigmf-meta`. The first capture is at `frequency`.
+    pub fn create(base: &Path, format: SampleFormat, recording: Recording, frequency: f64) -> io::Result<Self> {
+        let data = BufWriter::new(File::create(base.with_extension("sigmf-data"))?);
+        let writer = Self {
+            base: base.to_path_buf(),
+            format,
+            recording,
+            data,
+            samples: 0,
+            captures: vec![(0, frequency, SystemTime::now())],
+        };
+        writer.write_meta()?;
+        Ok(writer)
+    }
+
+    pub fn write(&mut self, samples: &[Complex32]) -> io::Result<()> {
+        for v in samples {
+            match self.format {
+                SampleFormat::Cf32 => {
+                    self.data.write_all(&v.re.to_le_bytes())?;
+                    self.data.write_all(&v.im.to_le_bytes())?;
+                }
+                SampleFormat::Ci16 => {
+                    self.data.write_all(&to_i16(v.re).to_le_bytes())?;
+                    self.data.write_all(&to_i16(v.im).to_le_bytes())?;
+                }
+            }
+        }
+        self.samples += samples.len() as u64;
+        Ok(())
+    }
+
+    /// Start the new capture segment at the next sample
+    pub fn retune(&mut self, frequency: f64) -> io::Result<()> {
+        match self.captures.last_mut() {
+            // Nothing was recorded at the previous frequency
+            Some(last) if last.0 == self.samples => *last = (self.samples, frequency, SystemTime::now()),
+            _ => self.captures.push((self.samples, frequency, SystemTime::now())),
+        }
+        self.write_meta()
+    }
+
+    /// Number of the recorded samples
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    /// Size of the data file in bytes
+    pub fn size(&self) -> u64 {
+        self.samples * self.format.sample_size()
+    }
+
+    pub fn sample_rate(&self) -> f64 {
+        self.recording.sample_rate
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.base
+    }
+
+    /// Flush the data and write the final metadata
+    pub fn finish(mut self) -> io::Result<()> {
+        self.data.flush()?;
+        self.write_meta()
+    }
+
+    fn write_meta(&self) -> io::Result<()> {
+        let Recording { sample_rate, hw, description, recorder } = self.recording.clone();
+        let meta = Meta {
+            global: Global {
+                datatype: self.format.datatype().to_string(),
+                sample_rate: Some(sample_rate),
+                version: "1.0.0".to_string(),
+                hw: Some(hw),
+                description: Some(description),
+                recorder: Some(recorder),
+            },
+            captures: self.captures.iter()
+                .map(|(start, frequency, time)| Capture {
+                    sample_start: *start,
+                    frequency: Some(*frequency),
+                    datetime: Some(iso8601(*time)),
+                })
+                .collect(),
+            annotations: vec![],
+        };
+        meta.write(&self.base.with_extension("sigmf-meta"))
+    }
+}
+
+/// Name of the recording from the application, time and frequency, e.g. "yasa_20240131T123456Z_92000000Hz"
+pub fn file_name(prefix: &str, time: SystemTime, frequency: f64) -> String {
+    let stamp: String = iso8601(time)[..19].chars().filter(|c| *c != '-' && *c != ':').collect();
+    format!("{prefix}_{stamp}Z_{}Hz", frequency.round() as u64)
+}
+
+/// UTC time like "2024-01-31T12:34:56.789Z"
+pub fn iso8601(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = ((secs / 86_400) as i64, secs % 86_400);
+
+    // Civil date from the days since 1970-01-01, by Howard Hinnant
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        secs_of_day / 3_600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis(),
+    )
+}
+
+fn to_i16(x: f32) -> i16 {
+    (x * i16::MAX as f32).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn recording() -> Recording {
+        Recording {
+            sample_rate: 1e6,
+            hw: "driver=\"rtlsdr\"".to_string(),
+            description: "Gain 30 dB".to_string(),
+            recorder: "yasa".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_iso8601() {
+        assert_eq!(iso8601(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        assert_eq!(iso8601(UNIX_EPOCH + Duration::from_millis(1_706_704_496_789)), "2024-01-31T12:34:56.789Z");
+        assert_eq!(iso8601(UNIX_EPOCH + Duration::from_secs(951_782_400)), "2000-02-29T00:00:00.000Z");
+        assert_eq!(file_name("yasa", UNIX_EPOCH + Duration::from_secs(1_706_704_496), 92e6), "yasa_20240131T123456Z_92000000Hz");
+    }
+
+    #[test]
+    fn test_write() {
+        let base = std::env::temp_dir().join(format!("sdr_common_sigmf_test_{}", std::process::id()));
+        let mut writer = SigmfWriter::create(&base, SampleFormat::Ci16, recording(), 92e6).unwrap();
+        writer.write(&[Complex32::new(1.0, -1.0), Complex32::new(0.5, 0.0)]).unwrap();
+        writer.retune(93e6).unwrap();
+        writer.write(&[Complex32::new(0.0, 2.0)]).unwrap();
+        assert_eq!(writer.size(), 12);
+        writer.finish().unwrap();
+
+        let data = std::fs::read(base.with_extension("sigmf-data")).unwrap();
+        let values: Vec<i16> = data.chunks(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect();
+        assert_eq!(values, vec![32767, -32767, 16384, 0, 0, 32767]);
+
+        let meta = Meta::read(&base.with_extension("sigmf-meta")).unwrap();
+        assert_eq!(meta.global.datatype, "ci16_le");
+        assert_eq!(meta.global.sample_rate, Some(1e6));
+        assert_eq!(meta.global.hw.as_deref(), Some("driver=\"rtlsdr\""));
+        let captures: Vec<(u64, Option<f64>)> = meta.captures.iter().map(|c| (c.sample_start, c.frequency)).collect();
+        assert_eq!(captures, vec![(0, Some(92e6)), (2, Some(93e6))]);
+
+        std::fs::remove_file(base.with_extension("sigmf-data")).unwrap();
+        std::fs::remove_file(base.with_extension("sigmf-meta")).unwrap();
+    }
+
+    #[test]
+    fn test_retune_before_samples() {
+        let base = std::env::temp_dir().join(format!("sdr_common_sigmf_retune_{}", std::process::id()));
+        let mut writer = SigmfWriter::create(&base, SampleFormat::Cf32, recording(), 92e6).unwrap();
+        writer.retune(93e6).unwrap();
+        writer.write(&[Complex32::new(0.25, 0.5)]).unwrap();
+        writer.finish().unwrap();
+
+        let data = std::fs::read(base.with_extension("sigmf-data")).unwrap();
+        assert_eq!(data, [0.25f32.to_le_bytes(), 0.5f32.to_le_bytes()].concat());
+        let meta = Meta::read(&base.with_extension("sigmf-meta")).unwrap();
+        assert_eq!(meta.captures.len(), 1);
+        assert_eq!(meta.captures[0].frequency, Some(93e6));
+
+        std::fs::remove_file(base.with_extension("sigmf-data")).unwrap();
+        std::fs::remove_file(base.with_extension("sigmf-meta")).unwrap();
+    }
+}
@@ -0,0 +1,180 @@
+//! FM multiplex stereo decoder
+//!
+//! The multiplex signal carries L+R in the audio band, the 19 kHz pilot
+//! and L-R as the DSB-SC signal on 38 kHz, which is locked to the pilot.
+//! PLL locks to the pilot and its doubled phase demodulates L-R.
+//! When the pilot is too weak the decoder falls back to mono.
+//! De-emphasis is applied to L+R and L-R at the multiplex rate, where the single pole filter
+//! follows the analog curve closely, before they are filtered to 15 kHz and decimated.
+
+use std::f32::consts::PI;
+
+use futuresdr::num_complex::Complex32;
+use serde::{Deserialize, Serialize};
+
+use crate::dsp::{lowpass, num_taps, Deemphasis, Fir, Pll};
+
+
+const PILOT: f32 = 19_000.0;
+// Pilot has to be at least at this part of the full deviation to switch to stereo
+const PILOT_ON: f32 = 0.04;
+const PILOT_OFF: f32 = 0.025;
+
+/// Broadcast FM region
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Region {
+    /// 50 us de-emphasis used in Europe and most of the world
+    #[default]
+    Europe,
+    /// 75 us de-emphasis used in the Americas and South Korea
+    America,
+}
+
+pub struct StereoDecoder {
+    pll: Pll,
+    // Pilot mixed down to DC, filtered with the one pole low pass
+    pilot: Complex32,
+    pilot_alpha: f32,
+    sum_deemphasis: Deemphasis,
+    diff_deemphasis: Deemphasis,
+    sum_filter: Fir<f32, f32>,
+    diff_filter: Fir<f32, f32>,
+    is_stereo: bool,
+}
+
+impl Region {
+    /// De-emphasis time constant in seconds
+    pub fn deemphasis(&self) -> f32 {
+        match self {
+            Region::Europe => 50e-6,
+            Region::America => 75e-6,
+        }
+    }
+}
+
+impl StereoDecoder {
+    /// `rate` is the rate of the multiplex signal, which is scaled so the full deviation is 1.0.
+    /// Audio is decimated by `audio_decim`. `tau` is the de-emphasis time constant in seconds.
+    pub fn new(rate: f32, audio_decim: usize, tau: f32) -> Self {
+        // Pass 15 kHz and remove the pilot
+        let taps = lowpass(16_500.0 / rate, num_taps(3_000.0 / rate));
+        Self {
+            pll: Pll::new(2.0 * PI * PILOT / rate, 2.0 * PI * 20.0 / rate, 10.0 / rate),
+            pilot: Complex32::new(0.0, 0.0),
+            pilot_alpha: 1.0 - (-2.0 * PI * 20.0 / rate).exp(),
+            sum_deemphasis: Deemphasis::new(tau, rate),
+            diff_deemphasis: Deemphasis::new(tau, rate),
+            sum_filter: Fir::with_decimation(taps.clone(), audio_decim),
+            diff_filter: Fir::with_decimation(taps, audio_decim),
+            is_stereo: false,
+        }
+    }
+
+    pub fn is_stereo(&self) -> bool {
+        self.is_stereo
+    }
+
+    /// Process multiplex sample. Returns left and right audio every `audio_decim` samples.
+    pub fn process_sample(&mut self, mpx: f32) -> Option<(f32, f32)> {
+        let phase = self.pll.phase();
+        let y = mpx * Complex32::from_polar(1.0, -phase);
+        self.pilot += self.pilot_alpha * (y - self.pilot);
+        self.pll.update(self.pilot.im.atan2(self.pilot.re));
+
+        // Pilot amplitude with hysteresis. Pilot at the phase locked loop is cos(phase).
+        let level = 2.0 * self.pilot.re;
+        if level > PILOT_ON {
+            self.is_stereo = true;
+        } else if level < PILOT_OFF {
+            self.is_stereo = false;
+        }
+
+        // Pilot is sin(wt) and the subcarrier is sin(2wt), so the subcarrier is -sin(2 * phase)
+        let diff = -2.0 * mpx * (2.0 * phase).sin();
+        let sum = self.sum_filter.process_sample(self.sum_deemphasis.process_sample(mpx));
+        let diff = self.diff_filter.process_sample(self.diff_deemphasis.process_sample(diff));
+        match (sum, diff) {
+            (Some(sum), Some(diff)) if self.is_stereo => Some(((sum + diff) / 2.0, (sum - diff) / 2.0)),
+            (Some(sum), _) => Some((sum / 2.0, sum / 2.0)),
+            _ => None,
+        }
+    }
+}
+
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Multiplex signal at 240 kHz with 1 kHz tone in the left channel only.
+    fn mpx(len: usize, pilot: f32) -> Vec<f32> {
+        (0..len)
+            .map(|i| {
+                let t = i as f32 / 240_000.0;
+                let left = 0.45 * (2.0 * PI * 1_000.0 * t).sin();
+                let right = 0.0;
+                let w = 2.0 * PI * PILOT * t;
+                0.5 * (left + right) + 0.5 * (left - right) * (2.0 * w).sin() + pilot * w.sin()
+            })
+            .collect()
+    }
+
+    fn peak(xs: &[f32]) -> f32 {
+        xs.iter().fold(0.0, |acc: f32, x| acc.max(x.abs()))
+    }
+
+    // Gain in dB of the mono audio path for the tone at `freq`
+    fn response(tau: f32, freq: f32) -> f32 {
+        let mut decoder = StereoDecoder::new(240_000.0, 5, tau);
+        let audio: Vec<f32> = (0..48_000)
+            .map(|i| (2.0 * PI * freq * i as f32 / 240_000.0).sin())
+            .filter_map(|x| decoder.process_sample(x))
+            .map(|a| a.0)
+            .skip(4_800)
+            .collect();
+        let c: Complex32 = audio.iter()
+            .enumerate()
+            .map(|(i, &x)| x * Complex32::from_polar(1.0, -2.0 * PI * freq * i as f32 / 48_000.0))
+            .sum();
+        // Mono is sent with the half amplitude to each channel
+        20.0 * (4.0 * c.norm() / audio.len() as f32).log10()
+    }
+
+    #[test]
+    fn test_stereo() {
+        // Without de-emphasis
+        let mut decoder = StereoDecoder::new(240_000.0, 5, 0.0);
+        let audio: Vec<(f32, f32)> = mpx(480_000, 0.09).into_iter().filter_map(|x| decoder.process_sample(x)).collect();
+        let left: Vec<f32> = audio[48_000..].iter().map(|a| a.0).collect();
+        let right: Vec<f32> = audio[48_000..].iter().map(|a| a.1).collect();
+
+        assert!(decoder.is_stereo());
+        assert!((peak(&left) - 0.45 / 2.0).abs() < 0.02);
+        assert!(peak(&right) < 0.02);
+    }
+
+    #[test]
+    fn test_mono_fallback() {
+        let mut decoder = StereoDecoder::new(240_000.0, 5, 50e-6);
+        let audio: Vec<(f32, f32)> = mpx(240_000, 0.0).into_iter().filter_map(|x| decoder.process_sample(x)).collect();
+
+        assert!(!decoder.is_stereo());
+        assert!(audio.iter().all(|a| a.0 == a.1));
+    }
+
+    #[test]
+    fn test_deemphasis_response() {
+        for tau in [Region::Europe.deemphasis(), Region::America.deemphasis()] {
+            for freq in [100.0, 1_000.0, 3_000.0, 6_000.0, 10_000.0, 14_000.0] {
+                // Standard single pole curve
+                let expected = -10.0 * (1.0 + (2.0 * PI * freq * tau).powi(2)).log10();
+                let gain = response(tau, freq);
+                assert!((gain - expected).abs() < 0.2, "tau: {tau}  f: {freq}  gain: {gain}  expected: {expected}");
+            }
+        }
+    }
+}
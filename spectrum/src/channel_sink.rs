@@ -1,6 +1,6 @@
 use crossbeam::channel::Sender;
 use futuresdr::anyhow::Result;
-use futuresdr::log::info;
+use futuresdr::log::trace;
 use futuresdr::macros::async_trait;
 use futuresdr::runtime::BlockMeta;
 use futuresdr::runtime::BlockMetaBuilder;
@@ -69,13 +69,9 @@ impl<T: Send + Copy + 'static> Kernel for CrossbeamSink<T> {
     ) -> Result<()> {
         let i = sio.input(0).slice::<T>();
 
-        match self.sender.try_send(i.into()) {
-            Ok(_) => {
-                info!("sent data...");
-            }
-            Err(err) => {
-                info!("{}", err.to_string());
-            }
+        // The UI takes what it can draw, the rest is dropped
+        if let Err(err) = self.sender.try_send(i.into()) {
+            trace!("{}", err);
         }
         sio.input(0).consume(i.len());
 
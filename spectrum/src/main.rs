@@ -12,10 +12,10 @@ struct YasaApp {
 }
 
 impl<'a> YasaApp {
-    fn new(cc: &CreationContext<'_>) -> Self {
+    fn new(cc: &CreationContext<'_>, args: &str) -> Self {
         // init GUI
         cc.egui_ctx.set_zoom_factor(1.5);
-        let radio = Radio::start().unwrap();
+        let radio = Radio::start(args).unwrap();
 
         Self {
             radio: radio,
@@ -63,6 +63,8 @@ impl eframe::App for YasaApp {
 
 fn main() -> Result<(), eframe::Error> {
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
+    // Device args or the IQ file, e.g. "file=capture.cu8,rate=2048000"
+    let args = std::env::args().nth(1).unwrap_or_default();
 
     // Init GUI
     let options = eframe::NativeOptions {
@@ -73,6 +75,6 @@ fn main() -> Result<(), eframe::Error> {
     eframe::run_native(
         "Spectrum",
         options,
-        Box::new(|cc| Box::new(YasaApp::new(cc))),
+        Box::new(move |cc| Box::new(YasaApp::new(cc, &args))),
     )
 }
@@ -1,3 +1,4 @@
+
 use crossbeam::channel::{self, Receiver};
 use dsp::num_complex::Complex32;
 use futuresdr::anyhow::Result;
@@ -5,24 +6,34 @@ use futuresdr::blocks::seify::SourceBuilder;
 use futuresdr::blocks::{Apply, Fft};
 use futuresdr::macros::connect;
 use futuresdr::runtime::{Block, Flowgraph, Runtime};
+use sdr_common::file_source::{self, FileSource};
+use sdr_common::iq_file::FileArgs;
 
 use crate::channel_sink::CrossbeamSink;
 
 const FFT_SIZE: usize = 4096;
+// Spectra waiting for the UI. The sink drops the new ones when the UI is behind,
+// e.g. when the file plays without the throttle.
+const QUEUE_SIZE: usize = 16;
 
 pub struct Radio {
     receiver: Receiver<Box<[f32]>>,
 }
 
 impl Radio {
-    pub fn start() -> Result<Self> {
+    /// Start receiving from the device or the IQ file given by the args
+    pub fn start(args: &str) -> Result<Self> {
         let source_rate: usize = 2_000_000; 
         let frequency = 192.0 * 1e6;//91.8 * 1e6;
-        let source = SourceBuilder::new()
-            .frequency(frequency)
-            .sample_rate(source_rate as f64)
-            .gain(30.0)
-            .build()?;
+        let source = match FileArgs::parse(args) {
+            Some(file_args) => Self::file(&file_args?)?,
+            None => SourceBuilder::new()
+                .args(args)?
+                .frequency(frequency)
+                .sample_rate(source_rate as f64)
+                .gain(30.0)
+                .build()?,
+        };
         
         // let resample = FirBuilder::new_resampling::<Complex32, Complex32>(1, 4);
         let fft = Fft::with_options(
@@ -32,7 +43,7 @@ impl Radio {
             None,
         );
         let power = Self::lin2power_db(); 
-        let (tx, rx) = channel::unbounded::<Box<[f32]>>();
+        let (tx, rx) = channel::bounded::<Box<[f32]>>(QUEUE_SIZE);
         let sink = CrossbeamSink::new(tx.clone());
 
         // Create the `Flowgraph` and add `Block`s
@@ -46,6 +57,12 @@ impl Radio {
         Ok(Self {receiver: rx})
     }
 
+    // Source which plays the IQ file in place of the device
+    fn file(args: &FileArgs) -> Result<Block> {
+        let (reader, throttle) = file_source::open(args)?;
+        Ok(FileSource::new(reader, throttle))
+    }
+
     pub fn lin2power_db() -> Block {
         Apply::new(|x: &Complex32| 20.0 * (x.norm() / i8::MAX as f32).log10())
     }
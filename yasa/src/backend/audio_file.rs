@@ -0,0 +1,312 @@
+//! Recording of the demodulated audio to WAV or FLAC files.
+//!
+//! The recording can follow the squelch, then every transmission goes to its own file.
+//! Files are named by the frequency and the time when they were started.
+//! WAV files are written by hound and FLAC files by the streaming encoder in `flac`.
+
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use hound::{WavSpec, WavWriter};
+
+use super::flac::FlacWriter;
+use super::sigmf;
+
+
+// How long the squelch must stay closed before the transmission is over, in seconds
+const SEGMENT_HANG: f64 = 2.0;
+// WAV stores the sizes in 32 bits, longer recordings continue in the next file.
+// The header is left out of the limit.
+const WAV_MAX_DATA: u64 = u32::MAX as u64 - 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioFormat {
+    Wav,
+    Flac,
+}
+
+/// Audio file with 16 bit samples
+pub struct AudioWriter {
+    path: PathBuf,
+    channels: u16,
+    rate: u32,
+    encoder: Encoder,
+    // Samples of every channel
+    frames: u64,
+}
+
+enum Encoder {
+    Wav(WavWriter<BufWriter<File>>),
+    Flac(FlacWriter<BufWriter<File>>),
+}
+
+/// Recording which starts a new file for every transmission or frequency
+pub struct AudioRecording {
+    directory: PathBuf,
+    format: AudioFormat,
+    rate: u32,
+    channels: u16,
+    frequency: f64,
+    squelch_triggered: bool,
+    writer: Option<AudioWriter>,
+    // Audio since the squelch closed, written when it opens again before the hang time
+    gap: Vec<f32>,
+    files: usize,
+}
+
+impl AudioFormat {
+    pub const ALL: [AudioFormat; 2] = [AudioFormat::Wav, AudioFormat::Flac];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AudioFormat::Wav => "WAV",
+            AudioFormat::Flac => "FLAC",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AudioFormat::Wav => "wav",
+            AudioFormat::Flac => "flac",
+        }
+    }
+}
+
+impl AudioWriter {
+    pub fn create(path: &Path, format: AudioFormat, rate: u32, channels: u16) -> io::Result<Self> {
+        let encoder = match format {
+            AudioFormat::Wav => {
+                let spec = WavSpec { channels, sample_rate: rate, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+                Encoder::Wav(WavWriter::create(path, spec).map_err(wav_error)?)
+            }
+            AudioFormat::Flac => {
+                let out = BufWriter::new(File::create(path)?);
+                Encoder::Flac(FlacWriter::new(out, rate, channels as usize)?)
+            }
+        };
+        Ok(Self { path: path.to_path_buf(), channels, rate, encoder, frames: 0 })
+    }
+
+    /// Write interleaved samples in the range -1..1
+    pub fn write(&mut self, audio: &[f32]) -> io::Result<()> {
+        let samples: Vec<i16> = audio.iter()
+            .map(|x| (x * i16::MAX as f32).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16)
+            .collect();
+        match &mut self.encoder {
+            Encoder::Wav(wav) => {
+                for &sample in &samples {
+                    wav.write_sample(sample).map_err(wav_error)?;
+                }
+            }
+            Encoder::Flac(flac) => flac.write(&samples)?,
+        }
+        self.frames += (samples.len() / self.channels as usize) as u64;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Length of the recorded audio
+    pub fn seconds(&self) -> f64 {
+        self.frames as f64 / self.rate as f64
+    }
+
+    /// False when the file can't take `samples` more interleaved samples
+    pub fn fits(&self, samples: usize) -> bool {
+        match self.encoder {
+            Encoder::Wav(_) => (self.frames * self.channels as u64 + samples as u64) * 2 <= WAV_MAX_DATA,
+            Encoder::Flac(_) => true,
+        }
+    }
+
+    /// Write the sizes, which are unknown until the end
+    pub fn finish(self) -> io::Result<()> {
+        match self.encoder {
+            Encoder::Wav(wav) => wav.finalize().map_err(wav_error),
+            Encoder::Flac(flac) => flac.finish().map(|_| ()),
+        }
+    }
+}
+
+impl AudioRecording {
+    /// Record to the directory. With `squelch_triggered` only the audio with the open squelch is recorded.
+    pub fn new(directory: &Path, format: AudioFormat, rate: u32, channels: u16, frequency: f64, squelch_triggered: bool) -> Self {
+        Self {
+            directory: directory.to_path_buf(),
+            format,
+            rate,
+            channels,
+            frequency,
+            squelch_triggered,
+            writer: None,
+            gap: vec![],
+            files: 0,
+        }
+    }
+
+    /// Add interleaved audio. `squelch_open` is none when the squelch is disabled, then all audio is recorded.
+    pub fn push(&mut self, audio: &[f32], squelch_open: Option<bool>) -> io::Result<()> {
+        if !self.squelch_triggered || squelch_open.unwrap_or(true) {
+            if self.writer.as_ref().is_some_and(|writer| !writer.fits(self.gap.len() + audio.len())) {
+                self.finish_file()?;
+            }
+            if self.writer.is_none() {
+                self.writer = Some(self.create_file()?);
+            }
+            if let Some(writer) = &mut self.writer {
+                writer.write(&self.gap)?;
+                writer.write(audio)?;
+            }
+            self.gap.clear();
+        } else if self.writer.is_some() {
+            // Short breaks stay in the same file, so the time isn't lost
+            self.gap.extend_from_slice(audio);
+            if self.gap.len() as f64 >= SEGMENT_HANG * self.rate as f64 * self.channels as f64 {
+                self.finish_file()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Audio of the other frequency goes to the new file
+    pub fn retune(&mut self, frequency: f64) -> io::Result<()> {
+        if frequency != self.frequency {
+            self.frequency = frequency;
+            self.finish_file()?;
+        }
+        Ok(())
+    }
+
+    /// File which is written now
+    pub fn current(&self) -> Option<&AudioWriter> {
+        self.writer.as_ref()
+    }
+
+    /// Number of the files started by this recording
+    pub fn files(&self) -> usize {
+        self.files
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.finish_file()
+    }
+
+    fn create_file(&mut self) -> io::Result<AudioWriter> {
+        let name = sigmf::file_name("yasa", SystemTime::now(), self.frequency);
+        let mut path = self.directory.join(&name).with_extension(self.format.extension());
+        // Transmissions can start in the same second after a retune
+        let mut n = 1;
+        while path.exists() {
+            n += 1;
+            path = self.directory.join(format!("{name}_{n}")).with_extension(self.format.extension());
+        }
+        self.files += 1;
+        AudioWriter::create(&path, self.format, self.rate, self.channels)
+    }
+
+    fn finish_file(&mut self) -> io::Result<()> {
+        self.gap.clear();
+        match self.writer.take() {
+            Some(writer) => writer.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+fn wav_error(e: hound::Error) -> io::Error {
+    match e {
+        hound::Error::IoError(e) => e,
+        e => io::Error::other(e),
+    }
+}
+
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("yasa_audio_{}_{name}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn files(dir: &Path) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = fs::read_dir(dir).unwrap().map(|e| e.unwrap().path()).collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn test_wav() {
+        let dir = temp_dir("wav");
+        let path = dir.join("test.wav");
+        let mut writer = AudioWriter::create(&path, AudioFormat::Wav, 48_000, 2).unwrap();
+        writer.write(&[1.0, -1.0, 0.5, 2.0]).unwrap();
+        assert_eq!(writer.seconds(), 2.0 / 48_000.0);
+        writer.finish().unwrap();
+
+        let mut wav = hound::WavReader::open(&path).unwrap();
+        assert_eq!(wav.spec().sample_rate, 48_000);
+        assert_eq!(wav.spec().channels, 2);
+        assert_eq!(wav.duration(), 2);
+        let samples: Vec<i16> = wav.samples().map(|s| s.unwrap()).collect();
+        assert_eq!(samples, vec![32767, -32767, 16384, 32767]);
+        assert_eq!(fs::metadata(&path).unwrap().len(), 44 + 8);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_squelch_segments() {
+        let dir = temp_dir("squelch");
+        let mut recording = AudioRecording::new(&dir, AudioFormat::Wav, 1000, 1, 145e6, true);
+        let second = vec![0.1; 1000];
+        recording.push(&second, Some(false)).unwrap();
+        assert!(recording.current().is_none());
+        recording.push(&second, Some(true)).unwrap();
+        // Short break is kept in the same file
+        recording.push(&second, Some(false)).unwrap();
+        recording.push(&second, Some(true)).unwrap();
+        assert_eq!(recording.files(), 1);
+        recording.push(&second, Some(false)).unwrap();
+        recording.push(&second, Some(false)).unwrap();
+        assert!(recording.current().is_none());
+        recording.push(&second, Some(true)).unwrap();
+        assert_eq!(recording.current().unwrap().seconds(), 1.0);
+        recording.finish().unwrap();
+
+        let files = files(&dir);
+        assert_eq!(files.len(), 2);
+        // Break is recorded too
+        assert_eq!(fs::metadata(&files[0]).unwrap().len(), 44 + 2 * 3000);
+        assert!(files.iter().all(|f| f.to_string_lossy().contains("_145000000Hz")));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_continuous_retune() {
+        let dir = temp_dir("retune");
+        let mut recording = AudioRecording::new(&dir, AudioFormat::Flac, 1000, 2, 92e6, false);
+        recording.push(&[0.0; 200], Some(false)).unwrap();
+        recording.retune(92e6).unwrap();
+        recording.push(&[0.0; 200], None).unwrap();
+        recording.retune(93e6).unwrap();
+        recording.push(&[0.0; 200], None).unwrap();
+        assert_eq!(recording.files(), 2);
+        recording.finish().unwrap();
+
+        let files = files(&dir);
+        assert!(files[0].to_string_lossy().ends_with("_92000000Hz.flac"));
+        assert!(files[1].to_string_lossy().ends_with("_93000000Hz.flac"));
+        assert_eq!(&fs::read(&files[1]).unwrap()[..4], b"fLaC");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,86 @@
+use std::sync::{Arc, Mutex};
+
+use futuresdr::anyhow::Result;
+use futuresdr::log;
+use futuresdr::macros::async_trait;
+use futuresdr::runtime::BlockMeta;
+use futuresdr::runtime::BlockMetaBuilder;
+use futuresdr::runtime::Kernel;
+use futuresdr::runtime::MessageIo;
+use futuresdr::runtime::MessageIoBuilder;
+use futuresdr::runtime::StreamIo;
+use futuresdr::runtime::StreamIoBuilder;
+use futuresdr::runtime::WorkIo;
+use futuresdr::runtime::{Block, TypedBlock};
+
+use super::audio_file::AudioRecording;
+use super::demod::SharedDemodulator;
+
+/// Audio recording shared by the flowgraph and the UI, none when not recording
+pub type SharedAudioRecording = Arc<Mutex<Option<AudioRecording>>>;
+
+/// Write the demodulated audio to the recording, if there is one.
+///
+/// The squelch of the demodulator decides which audio belongs to a transmission.
+/// The recording is dropped when writing fails.
+///
+/// # Inputs
+///
+/// `in`: Interleaved stereo audio
+pub struct AudioRecorder {
+    recording: SharedAudioRecording,
+    demodulator: SharedDemodulator,
+}
+
+impl AudioRecorder {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(recording: SharedAudioRecording, demodulator: SharedDemodulator) -> Block {
+        Block::from_typed(Self::new_typed(recording, demodulator))
+    }
+
+    pub fn new_typed(recording: SharedAudioRecording, demodulator: SharedDemodulator) -> TypedBlock<Self> {
+        TypedBlock::new(
+            BlockMetaBuilder::new("AudioRecorder").build(),
+            StreamIoBuilder::new().add_input::<f32>("in").build(),
+            MessageIoBuilder::<Self>::new().build(),
+            AudioRecorder { recording, demodulator },
+        )
+    }
+}
+
+#[doc(hidden)]
+#[async_trait]
+impl Kernel for AudioRecorder {
+    async fn work(
+        &mut self,
+        io: &mut WorkIo,
+        sio: &mut StreamIo,
+        _mio: &mut MessageIo<Self>,
+        _meta: &mut BlockMeta,
+    ) -> Result<()> {
+        let i = sio.input(0).slice::<f32>();
+        // Whole stereo frames only
+        let n = i.len() / 2 * 2;
+
+        let mut recording = self.recording.lock().unwrap();
+        if let Some(audio) = recording.as_mut() {
+            let squelch_open = self.demodulator.lock().unwrap().squelch().map(|s| s.open);
+            if let Err(e) = audio.push(&i[..n], squelch_open) {
+                log::warn!("Audio recording stopped: {e}");
+                // Finish what was written, so the file stays playable
+                if let Some(Err(e)) = recording.take().map(|audio| audio.finish()) {
+                    log::error!("Audio recording can't be finished: {e}");
+                }
+            }
+        }
+        drop(recording);
+
+        sio.input(0).consume(n);
+
+        if sio.input(0).finished() {
+            io.finished = true;
+        }
+
+        Ok(())
+    }
+}
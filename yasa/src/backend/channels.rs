@@ -0,0 +1,127 @@
+//! Channels demodulated at the same time as the VFO.
+//!
+//! Every channel has its own shift to zero frequency, resampler and demodulator with the squelch,
+//! fed by the whole band received by the device. Audio of the VFO and the channels is mixed
+//! with the gains, which follow the mute and solo of every channel.
+
+use serde::{Deserialize, Serialize};
+
+use super::demod::{DemodSettings, Mode, Region, SquelchSettings};
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Channel {
+    pub frequency: u64,
+    pub mode: Mode,
+    /// Channel bandwidth in Hz
+    pub bandwidth: f32,
+    #[serde(default)]
+    pub squelch: SquelchSettings,
+    /// Mute and solo last only until the app is closed
+    #[serde(skip)]
+    pub mute: bool,
+    /// When any channel is solo, only the solo channels are heard
+    #[serde(skip)]
+    pub solo: bool,
+}
+
+impl Channel {
+    /// Demodulator settings of the channel. The rest is the default of the mode.
+    pub fn demod_settings(&self, region: Region) -> DemodSettings {
+        DemodSettings {
+            bandwidth: self.bandwidth,
+            squelch: self.squelch,
+            region,
+            ..DemodSettings::new(self.mode)
+        }
+    }
+}
+
+/// Gains of the mixer inputs, the VFO first and then the channels.
+/// Channels outside of the received band are silent.
+pub fn mixer_gains(vfo_mute: bool, channels: &[Channel], in_band: impl Fn(&Channel) -> bool) -> Vec<f32> {
+    let any_solo = channels.iter().any(|c| c.solo);
+    let vfo = !vfo_mute && !any_solo;
+    let gain = |audible: bool| if audible { 1.0 } else { 0.0 };
+    std::iter::once(gain(vfo))
+        .chain(channels.iter().map(|c| {
+            let audible = if any_solo { c.solo } else { !c.mute };
+            gain(audible && in_band(c))
+        }))
+        .collect()
+}
+
+/// Sum the inputs with their gains, clipped to the full scale.
+/// Output is as long as the shortest input.
+pub fn mix(inputs: &[&[f32]], gains: &[f32], output: &mut Vec<f32>) {
+    let n = inputs.iter().map(|i| i.len()).min().unwrap_or(0);
+    output.clear();
+    output.resize(n, 0.0);
+    for (input, &gain) in inputs.iter().zip(gains) {
+        // Squelched and muted channels are the most of the inputs
+        if gain == 0.0 {
+            continue;
+        }
+        for (y, x) in output.iter_mut().zip(&input[..n]) {
+            *y += gain * x;
+        }
+    }
+    for y in output.iter_mut() {
+        *y = y.clamp(-1.0, 1.0);
+    }
+}
+
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(frequency: u64, mute: bool, solo: bool) -> Channel {
+        Channel {
+            frequency,
+            mode: Mode::Nfm,
+            bandwidth: 12_500.0,
+            squelch: SquelchSettings::default(),
+            mute,
+            solo,
+        }
+    }
+
+    #[test]
+    fn test_gains() {
+        let all = |_: &Channel| true;
+        let channels = [channel(1, false, false), channel(2, true, false)];
+        assert_eq!(mixer_gains(false, &channels, all), vec![1.0, 1.0, 0.0]);
+        assert_eq!(mixer_gains(true, &channels, all), vec![0.0, 1.0, 0.0]);
+
+        // Solo wins over mute and silences the VFO
+        let channels = [channel(1, false, false), channel(2, true, true), channel(3, false, true)];
+        assert_eq!(mixer_gains(false, &channels, all), vec![0.0, 0.0, 1.0, 1.0]);
+        assert_eq!(mixer_gains(false, &channels, |c| c.frequency != 3), vec![0.0, 0.0, 1.0, 0.0]);
+        assert_eq!(mixer_gains(false, &[], all), vec![1.0]);
+    }
+
+    #[test]
+    fn test_mix() {
+        let mut output = vec![];
+        mix(&[&[0.5, 0.5, 0.5], &[0.25, 0.75], &[1.0, 1.0]], &[1.0, 1.0, 0.0], &mut output);
+        assert_eq!(output, vec![0.75, 1.0]);
+        mix(&[&[-0.5], &[-0.75]], &[1.0, 1.0], &mut output);
+        assert_eq!(output, vec![-1.0]);
+        mix(&[], &[], &mut output);
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_settings() {
+        let mut nfm = channel(145_500_000, false, false);
+        nfm.bandwidth = 6_250.0;
+        let settings = nfm.demod_settings(Region::America);
+        assert_eq!(settings.bandwidth, 6_250.0);
+        assert_eq!(settings.region, Region::America);
+        assert_eq!(settings.agc, Mode::Nfm.agc());
+    }
+}
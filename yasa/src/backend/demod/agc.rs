@@ -0,0 +1,162 @@
+//! Automatic gain control
+//!
+//! AM and SSB normalize the channel IQ before the detection, so the audio level doesn't depend
+//! on the signal strength. Audio AGC after the demodulator evens out the rest and limits the peaks.
+
+use futuresdr::num_complex::Complex32;
+
+use super::{Demodulator, RdsInfo, SquelchState};
+use crate::backend::dsp::{Agc, AgcTimes};
+
+
+// Peak level of the channel IQ and the audio
+const TARGET: f32 = 0.5;
+const MAX_IQ_GAIN: f32 = 1e5;
+const MAX_AUDIO_GAIN: f32 = 10.0;
+
+const FAST: AgcTimes = AgcTimes { attack: 0.002, decay: 0.05, hang: 0.1 };
+pub(super) const SLOW: AgcTimes = AgcTimes { attack: 0.002, decay: 0.5, hang: 0.5 };
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AgcPreset {
+    Off,
+    Fast,
+    Slow,
+    /// Times set by the user
+    Custom,
+}
+
+/// Demodulator with the audio AGC and limiter
+pub struct AudioAgc {
+    demodulator: Box<dyn Demodulator>,
+    agc: Agc,
+}
+
+impl AgcPreset {
+    pub const ALL: [AgcPreset; 4] = [AgcPreset::Off, AgcPreset::Fast, AgcPreset::Slow, AgcPreset::Custom];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AgcPreset::Off => "Off",
+            AgcPreset::Fast => "Fast",
+            AgcPreset::Slow => "Slow",
+            AgcPreset::Custom => "Custom",
+        }
+    }
+
+    /// Times of the preset, `custom` are the times of the custom preset
+    pub fn times(&self, custom: AgcTimes) -> Option<AgcTimes> {
+        match self {
+            AgcPreset::Off => None,
+            AgcPreset::Fast => Some(FAST),
+            AgcPreset::Slow => Some(SLOW),
+            AgcPreset::Custom => Some(custom),
+        }
+    }
+}
+
+/// AGC of the channel IQ at `sample_rate`
+pub fn iq_agc(times: AgcTimes, sample_rate: f32) -> Agc {
+    Agc::new(times, sample_rate, TARGET, MAX_IQ_GAIN)
+}
+
+impl AudioAgc {
+    pub fn new(demodulator: Box<dyn Demodulator>, times: AgcTimes, audio_rate: f32) -> Self {
+        Self { demodulator, agc: Agc::new(times, audio_rate, TARGET, MAX_AUDIO_GAIN) }
+    }
+}
+
+impl Demodulator for AudioAgc {
+    fn process(&mut self, input: &[Complex32], output: &mut Vec<f32>) {
+        let start = output.len();
+        self.demodulator.process(input, output);
+        let channels = self.demodulator.channels();
+        // Channels share the gain, so the stereo image doesn't move
+        for frame in output[start..].chunks_mut(channels) {
+            let peak = frame.iter().fold(0.0, |acc: f32, x| acc.max(x.abs()));
+            let gain = self.agc.gain(peak);
+            frame.iter_mut().for_each(|x| *x = (*x * gain).clamp(-1.0, 1.0));
+        }
+    }
+
+    fn channels(&self) -> usize {
+        self.demodulator.channels()
+    }
+
+    fn is_stereo(&self) -> bool {
+        self.demodulator.is_stereo()
+    }
+
+    fn rds(&self) -> Option<RdsInfo> {
+        self.demodulator.rds()
+    }
+
+    fn squelch(&self) -> Option<SquelchState> {
+        self.demodulator.squelch()
+    }
+}
+
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::demod::{DemodSettings, Mode};
+    use crate::backend::demod::signal::{self, peak};
+
+    fn audio_level(mode: Mode, agc: AgcPreset, amplitude: f32) -> f32 {
+        let mut settings = DemodSettings::new(mode);
+        settings.agc = agc;
+        let mut demod = mode.demodulator(240_000.0, 5, &settings);
+        let mut audio = vec![];
+        demod.process(&signal::am(720_000, amplitude, 0.0), &mut audio);
+        // Last 0.5 s, after the DC blocker settles
+        peak(&audio[120_000..])
+    }
+
+    #[test]
+    fn test_am_agc() {
+        // 40 dB difference in the signal level
+        for mode in [Mode::Am, Mode::Sam, Mode::Usb] {
+            let strong = audio_level(mode, AgcPreset::Slow, 0.1);
+            let weak = audio_level(mode, AgcPreset::Slow, 0.001);
+            assert!((strong / weak - 1.0).abs() < 0.1, "{}: {strong} {weak}", mode.name());
+            assert!(strong > 0.3 && strong <= 1.0, "{}: {strong}", mode.name());
+        }
+    }
+
+    #[test]
+    fn test_agc_off() {
+        let strong = audio_level(Mode::Am, AgcPreset::Off, 0.1);
+        let weak = audio_level(Mode::Am, AgcPreset::Off, 0.001);
+        assert!((strong / weak - 100.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_limiter() {
+        struct Loud;
+        impl Demodulator for Loud {
+            fn process(&mut self, input: &[Complex32], output: &mut Vec<f32>) {
+                output.extend(input.iter().map(|v| 5.0 * v.re));
+            }
+        }
+        let mut demod = AudioAgc::new(Box::new(Loud), SLOW, 48_000.0);
+        let input: Vec<Complex32> = (0..4_800).map(|i| Complex32::new((i as f32 * 0.1).sin(), 0.0)).collect();
+        let mut audio = vec![];
+        demod.process(&input, &mut audio);
+        assert!(peak(&audio) <= 1.0);
+        assert!((peak(&audio[2_400..]) - TARGET).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_custom_times() {
+        let mut settings = DemodSettings::new(Mode::Am);
+        settings.agc = AgcPreset::Custom;
+        settings.custom_agc = FAST;
+        assert_eq!(settings.agc_times(), Some(FAST));
+        settings.agc = AgcPreset::Off;
+        assert_eq!(settings.agc_times(), None);
+    }
+}
@@ -0,0 +1,125 @@
+//! Amplitude modulation
+
+use futuresdr::num_complex::Complex32;
+
+use super::Demodulator;
+use crate::backend::dsp::{lowpass, num_taps, Agc, DcBlocker, Fir, Pll};
+
+
+/// Envelope detector
+pub struct Am {
+    channel_filter: Fir<Complex32, f32>,
+    agc: Option<Agc>,
+    dc_blocker: DcBlocker,
+}
+
+/// Synchronous detector. PLL locks to the carrier and the signal is mixed down with it.
+/// It is less sensitive to the selective fading than the envelope detector.
+pub struct Sam {
+    channel_filter: Fir<Complex32, f32>,
+    agc: Option<Agc>,
+    pll: Pll,
+    dc_blocker: DcBlocker,
+}
+
+// Filter the channel and decimate it to the audio rate
+fn channel_filter(rate: f32, audio_decim: usize, bandwidth: f32) -> Fir<Complex32, f32> {
+    let taps = lowpass(bandwidth / 2.0 / rate, num_taps(bandwidth / 4.0 / rate));
+    Fir::with_decimation(taps, audio_decim)
+}
+
+pub(super) fn apply_agc(agc: &mut Option<Agc>, x: Complex32) -> Complex32 {
+    match agc {
+        Some(agc) => x * agc.gain(x.norm()),
+        None => x,
+    }
+}
+
+impl Am {
+    pub fn new(rate: f32, audio_decim: usize, bandwidth: f32) -> Self {
+        Self {
+            channel_filter: channel_filter(rate, audio_decim, bandwidth),
+            agc: None,
+            dc_blocker: DcBlocker::new(0.999),
+        }
+    }
+
+    /// Normalize the channel before the detection
+    pub fn with_agc(mut self, agc: Option<Agc>) -> Self {
+        self.agc = agc;
+        self
+    }
+}
+
+impl Demodulator for Am {
+    fn process(&mut self, input: &[Complex32], output: &mut Vec<f32>) {
+        for &v in input {
+            if let Some(x) = self.channel_filter.process_sample(v) {
+                let x = apply_agc(&mut self.agc, x);
+                output.push(self.dc_blocker.process_sample(x.norm()));
+            }
+        }
+    }
+}
+
+impl Sam {
+    pub fn new(rate: f32, audio_decim: usize, bandwidth: f32) -> Self {
+        let audio_rate = rate / audio_decim as f32;
+        Self {
+            channel_filter: channel_filter(rate, audio_decim, bandwidth),
+            agc: None,
+            // Carrier can be up to 1 kHz off. Loop bandwidth has to be below the lowest audio frequency.
+            pll: Pll::new(0.0, 2.0 * std::f32::consts::PI * 1_000.0 / audio_rate, 30.0 / audio_rate),
+            dc_blocker: DcBlocker::new(0.999),
+        }
+    }
+
+    /// Normalize the channel before the detection
+    pub fn with_agc(mut self, agc: Option<Agc>) -> Self {
+        self.agc = agc;
+        self
+    }
+}
+
+impl Demodulator for Sam {
+    fn process(&mut self, input: &[Complex32], output: &mut Vec<f32>) {
+        for &v in input {
+            if let Some(x) = self.channel_filter.process_sample(v) {
+                let x = apply_agc(&mut self.agc, x);
+                let y = x * Complex32::from_polar(1.0, -self.pll.phase());
+                // Normalized error, so the loop gain doesn't depend on the signal level
+                let error = if y.re != 0.0 { (y.im / y.re).atan() } else { 0.0 };
+                self.pll.update(error);
+                output.push(self.dc_blocker.process_sample(y.re));
+            }
+        }
+    }
+}
+
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::demod::signal::{self, tone_level};
+
+    #[test]
+    fn test_am() {
+        let mut demod = Am::new(240_000.0, 5, 10_000.0);
+        let mut audio = vec![];
+        // Carrier 300 Hz off the center
+        demod.process(&signal::am(240_000, 1.0, 300.0), &mut audio);
+        assert!((tone_level(&audio[24_000..], 1_000.0) - 0.5).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_sam() {
+        let mut demod = Sam::new(240_000.0, 5, 10_000.0);
+        let mut audio = vec![];
+        // Carrier 300 Hz off the center
+        demod.process(&signal::am(240_000, 1.0, 300.0), &mut audio);
+        assert!((tone_level(&audio[24_000..], 1_000.0) - 0.5).abs() < 0.05);
+    }
+}
@@ -0,0 +1,89 @@
+use futuresdr::anyhow::Result;
+use futuresdr::macros::async_trait;
+use futuresdr::num_complex::Complex32;
+use futuresdr::runtime::BlockMeta;
+use futuresdr::runtime::BlockMetaBuilder;
+use futuresdr::runtime::Kernel;
+use futuresdr::runtime::MessageIo;
+use futuresdr::runtime::MessageIoBuilder;
+use futuresdr::runtime::StreamIo;
+use futuresdr::runtime::StreamIoBuilder;
+use futuresdr::runtime::WorkIo;
+use futuresdr::runtime::{Block, TypedBlock};
+
+use super::SharedDemodulator;
+
+/// Run the shared demodulator on the stream of the channel IQ.
+///
+/// The demodulator can be replaced from the other thread while the flowgraph is running.
+///
+/// # Inputs
+///
+/// `in`: Channel IQ
+///
+/// # Outputs
+///
+/// `out`: Interleaved stereo audio. Mono demodulators are sent to both channels.
+pub struct DemodulatorBlock {
+    demodulator: SharedDemodulator,
+    buffer: Vec<f32>,
+    audio: Vec<f32>,
+}
+
+impl DemodulatorBlock {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(demodulator: SharedDemodulator) -> Block {
+        Block::from_typed(Self::new_typed(demodulator))
+    }
+
+    pub fn new_typed(demodulator: SharedDemodulator) -> TypedBlock<Self> {
+        TypedBlock::new(
+            BlockMetaBuilder::new("Demodulator").build(),
+            StreamIoBuilder::new()
+                .add_input::<Complex32>("in")
+                .add_output::<f32>("out")
+                .build(),
+            MessageIoBuilder::<Self>::new().build(),
+            DemodulatorBlock { demodulator, buffer: vec![], audio: vec![] },
+        )
+    }
+}
+
+#[doc(hidden)]
+#[async_trait]
+impl Kernel for DemodulatorBlock {
+    async fn work(
+        &mut self,
+        io: &mut WorkIo,
+        sio: &mut StreamIo,
+        _mio: &mut MessageIo<Self>,
+        _meta: &mut BlockMeta,
+    ) -> Result<()> {
+        let i = sio.input(0).slice::<Complex32>();
+        let o = sio.output(0).slice::<f32>();
+
+        // Demodulate only when there is a room for the audio, so the buffer doesn't grow
+        if self.buffer.len() < o.len() {
+            let mut demodulator = self.demodulator.lock().unwrap();
+            self.audio.clear();
+            demodulator.process(i, &mut self.audio);
+            if demodulator.channels() == 1 {
+                self.buffer.extend(self.audio.iter().flat_map(|&x| [x, x]));
+            } else {
+                self.buffer.extend_from_slice(&self.audio);
+            }
+            sio.input(0).consume(i.len());
+        }
+
+        let n = self.buffer.len().min(o.len());
+        o[..n].copy_from_slice(&self.buffer[..n]);
+        self.buffer.drain(..n);
+        sio.output(0).produce(n);
+
+        if sio.input(0).finished() && self.buffer.is_empty() {
+            io.finished = true;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+//! Frequency modulation
+
+use futuresdr::num_complex::Complex32;
+use sdr_common::rds::RdsDecoder;
+use sdr_common::stereo::StereoDecoder;
+
+use super::{Demodulator, RdsInfo};
+use crate::backend::dsp::{cascade, lowpass, num_taps, Biquad, Deemphasis, Fir, QuadratureDetector};
+
+
+/// Broadcast FM with stereo. Output is always 2 channels, mono is sent to both.
+pub struct Wfm {
+    demod: QuadratureDetector,
+    gain: f32,
+    stereo: StereoDecoder,
+    rds: RdsDecoder,
+}
+
+/// Narrowband FM used by the voice channels
+pub struct Nfm {
+    channel_filter: Fir<Complex32, f32>,
+    demod: QuadratureDetector,
+    gain: f32,
+    audio_filter: Fir<f32, f32>,
+    // Removes sub-audible CTCSS tones (67-254 Hz)
+    ctcss_filter: Vec<Biquad>,
+    deemphasis: Option<Deemphasis>,
+}
+
+impl Wfm {
+    /// `tau` is the de-emphasis time constant in seconds
+    pub fn new(rate: f32, audio_decim: usize, tau: f32) -> Self {
+        Self {
+            demod: QuadratureDetector::new(),
+            // 75 kHz deviation gives full scale
+            gain: rate / (2.0 * std::f32::consts::PI * 75_000.0),
+            stereo: StereoDecoder::new(rate, audio_decim, tau),
+            rds: RdsDecoder::new(rate),
+        }
+    }
+}
+
+impl Demodulator for Wfm {
+    fn process(&mut self, input: &[Complex32], output: &mut Vec<f32>) {
+        for &v in input {
+            let mpx = self.gain * self.demod.process_sample(v);
+            self.rds.process_sample(mpx);
+            if let Some((left, right)) = self.stereo.process_sample(mpx) {
+                output.push(left);
+                output.push(right);
+            }
+        }
+    }
+
+    fn channels(&self) -> usize {
+        2
+    }
+
+    fn is_stereo(&self) -> bool {
+        self.stereo.is_stereo()
+    }
+
+    fn rds(&self) -> Option<RdsInfo> {
+        Some(self.rds.info().clone())
+    }
+}
+
+impl Nfm {
+    // The channel is filtered and decimated to the audio rate before demodulation.
+    // Peak deviation follows from the channel width: 2.5 kHz for 12.5 kHz and 5 kHz for 25 kHz channels.
+    pub fn new(rate: f32, audio_decim: usize, bandwidth: f32, deemphasis: bool) -> Self {
+        let taps = lowpass(bandwidth / 2.0 / rate, num_taps(bandwidth / 4.0 / rate));
+        let audio_rate = rate / audio_decim as f32;
+        let deviation = bandwidth / 5.0;
+        Self {
+            channel_filter: Fir::with_decimation(taps, audio_decim),
+            demod: QuadratureDetector::new(),
+            // Peak deviation gives full scale
+            gain: audio_rate / (2.0 * std::f32::consts::PI * deviation),
+            audio_filter: Fir::new(lowpass(3_000.0 / audio_rate, num_taps(1_000.0 / audio_rate))),
+            ctcss_filter: Biquad::butterworth_highpass(300.0 / audio_rate, 6),
+            // Voice is sent with 750 us pre-emphasis
+            deemphasis: deemphasis.then(|| Deemphasis::new(750e-6, audio_rate)),
+        }
+    }
+}
+
+impl Demodulator for Nfm {
+    fn process(&mut self, input: &[Complex32], output: &mut Vec<f32>) {
+        for &v in input {
+            if let Some(x) = self.channel_filter.process_sample(v) {
+                let audio = self.gain * self.demod.process_sample(x);
+                let audio = cascade(&mut self.ctcss_filter, audio);
+                let audio = match &mut self.deemphasis {
+                    Some(de) => de.process_sample(audio),
+                    None => audio,
+                };
+                if let Some(y) = self.audio_filter.process_sample(audio) {
+                    output.push(y);
+                }
+            }
+        }
+    }
+}
+
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::demod::signal::{self, tone_level};
+
+    // Voice tone and the CTCSS tone, both at 1 kHz deviation
+    fn nfm_signal(len: usize) -> Vec<Complex32> {
+        signal::fm(len, 1.0, &[(1_000.0, 1_000.0), (100.0, 1_000.0)])
+    }
+
+    #[test]
+    fn test_nfm() {
+        let mut demod = Nfm::new(240_000.0, 5, 12_500.0, false);
+        let mut audio = vec![];
+        demod.process(&nfm_signal(240_000), &mut audio);
+
+        // 1 kHz out of 2.5 kHz deviation
+        assert!((tone_level(&audio[24_000..], 1_000.0) - 0.4).abs() < 0.02);
+        assert!(tone_level(&audio[24_000..], 100.0) < 0.01);
+    }
+
+    #[test]
+    fn test_nfm_wide_deemphasis() {
+        let mut demod = Nfm::new(240_000.0, 5, 25_000.0, true);
+        let mut audio = vec![];
+        demod.process(&nfm_signal(240_000), &mut audio);
+
+        // 1 kHz out of 5 kHz deviation, attenuated by the de-emphasis
+        let level = tone_level(&audio[24_000..], 1_000.0);
+        assert!(level < 0.2 && level > 0.02);
+    }
+}
@@ -0,0 +1,217 @@
+//! Demodulators which turn the channel IQ into audio.
+//!
+//! Every mode gets IQ at the same channel rate and produces audio `audio_decim` times slower,
+//! so the mode can be swapped while the flowgraph is running.
+
+mod agc;
+mod am;
+mod block;
+mod fm;
+#[cfg(test)]
+mod signal;
+mod squelch;
+mod ssb;
+
+use std::sync::{Arc, Mutex};
+
+use futuresdr::num_complex::Complex32;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::dsp::AgcTimes;
+
+pub use agc::AgcPreset;
+pub use block::DemodulatorBlock;
+pub use sdr_common::rds::{pty_name, ClockTime, RdsInfo};
+pub use sdr_common::stereo::Region;
+pub use squelch::{SquelchMode, SquelchSettings, SquelchState};
+
+
+/// Demodulator shared between the flowgraph and the application
+pub type SharedDemodulator = Arc<Mutex<Box<dyn Demodulator>>>;
+
+pub trait Demodulator: Send {
+    /// Demodulate the channel IQ and append audio samples to the output.
+    /// Samples of the multi channel audio are interleaved.
+    fn process(&mut self, input: &[Complex32], output: &mut Vec<f32>);
+
+    /// Number of the audio channels in the output
+    fn channels(&self) -> usize {
+        1
+    }
+
+    /// True if the stereo signal is received
+    fn is_stereo(&self) -> bool {
+        false
+    }
+
+    /// Data decoded from RDS, if the mode supports it
+    fn rds(&self) -> Option<RdsInfo> {
+        None
+    }
+
+    /// State of the squelch, if it is enabled
+    fn squelch(&self) -> Option<SquelchState> {
+        None
+    }
+}
+
+/// Parameters of the demodulator which can be changed by the user
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DemodSettings {
+    /// Channel bandwidth in Hz. For CW this is the width of the narrow filter.
+    pub bandwidth: f32,
+    /// Audio passband of SSB in Hz
+    pub passband: (f32, f32),
+    /// CW tone in Hz
+    pub cw_pitch: f32,
+    /// Apply 750 us de-emphasis to NFM audio
+    pub nfm_deemphasis: bool,
+    /// Broadcast region, which selects the WFM de-emphasis
+    pub region: Region,
+    pub squelch: SquelchSettings,
+    /// AGC of the channel IQ in AM and SSB modes and of the audio
+    pub agc: AgcPreset,
+    /// Times of the custom AGC preset
+    pub custom_agc: AgcTimes,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Wfm,
+    Nfm,
+    Am,
+    Sam,
+    Usb,
+    Lsb,
+    Cw,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 7] = [Mode::Wfm, Mode::Nfm, Mode::Am, Mode::Sam, Mode::Usb, Mode::Lsb, Mode::Cw];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Wfm => "WFM",
+            Mode::Nfm => "NFM",
+            Mode::Am => "AM",
+            Mode::Sam => "SAM",
+            Mode::Usb => "USB",
+            Mode::Lsb => "LSB",
+            Mode::Cw => "CW",
+        }
+    }
+
+    /// Default channel bandwidth in Hz
+    pub fn bandwidth(&self) -> f32 {
+        self.bandwidths()[0]
+    }
+
+    /// Channel bandwidths in Hz which can be selected in this mode
+    pub fn bandwidths(&self) -> &'static [f32] {
+        match self {
+            Mode::Wfm => &[200_000.0],
+            Mode::Nfm => &[12_500.0, 25_000.0],
+            Mode::Am | Mode::Sam => &[10_000.0, 5_000.0],
+            Mode::Usb | Mode::Lsb => &[2_400.0],
+            Mode::Cw => &[500.0, 250.0, 1_000.0],
+        }
+    }
+
+    /// Default AGC. FM has the constant envelope and its audio level doesn't depend on the signal.
+    pub fn agc(&self) -> AgcPreset {
+        match self {
+            Mode::Wfm | Mode::Nfm => AgcPreset::Off,
+            Mode::Am | Mode::Sam | Mode::Usb | Mode::Lsb => AgcPreset::Slow,
+            Mode::Cw => AgcPreset::Fast,
+        }
+    }
+
+    /// Part of the spectrum in Hz, relative to the tuned frequency, which is received in this mode
+    pub fn passband(&self, settings: &DemodSettings) -> (f32, f32) {
+        let (low, high) = settings.passband;
+        match self {
+            Mode::Usb => (low, high),
+            Mode::Lsb => (-high, -low),
+            _ => (-settings.bandwidth / 2.0, settings.bandwidth / 2.0),
+        }
+    }
+
+    /// Build demodulator for IQ at `rate` which outputs audio at `rate / audio_decim`.
+    /// The audio goes through the AGC and it is gated when the squelch is enabled.
+    pub fn demodulator(&self, rate: f32, audio_decim: usize, settings: &DemodSettings) -> Box<dyn Demodulator> {
+        let audio_rate = rate / audio_decim as f32;
+        let mut demodulator = self.raw_demodulator(rate, audio_decim, settings);
+        if let Some(times) = settings.agc_times() {
+            demodulator = Box::new(agc::AudioAgc::new(demodulator, times, audio_rate));
+        }
+        if settings.squelch.mode == SquelchMode::Off {
+            return demodulator;
+        }
+        let squelch = squelch::Squelch::new(rate, *self, self.passband(settings), settings.squelch);
+        Box::new(squelch::Squelched::new(demodulator, squelch, audio_rate))
+    }
+
+    fn raw_demodulator(&self, rate: f32, audio_decim: usize, settings: &DemodSettings) -> Box<dyn Demodulator> {
+        let bandwidth = settings.bandwidth;
+        let (low, high) = settings.passband;
+        let agc = settings.agc_times().map(|times| agc::iq_agc(times, rate / audio_decim as f32));
+        match self {
+            Mode::Wfm => Box::new(fm::Wfm::new(rate, audio_decim, settings.region.deemphasis())),
+            Mode::Nfm => Box::new(fm::Nfm::new(rate, audio_decim, bandwidth, settings.nfm_deemphasis)),
+            Mode::Am => Box::new(am::Am::new(rate, audio_decim, bandwidth).with_agc(agc)),
+            Mode::Sam => Box::new(am::Sam::new(rate, audio_decim, bandwidth).with_agc(agc)),
+            Mode::Usb => Box::new(ssb::Ssb::new(rate, audio_decim, low, high).with_agc(agc)),
+            Mode::Lsb => Box::new(ssb::Ssb::new(rate, audio_decim, -high, -low).with_agc(agc)),
+            Mode::Cw => Box::new(ssb::Ssb::cw(rate, audio_decim, bandwidth, settings.cw_pitch).with_agc(agc)),
+        }
+    }
+}
+
+impl DemodSettings {
+    /// Default settings for the given mode
+    pub fn new(mode: Mode) -> Self {
+        Self {
+            bandwidth: mode.bandwidth(),
+            passband: (300.0, 2_700.0),
+            cw_pitch: 700.0,
+            nfm_deemphasis: false,
+            region: Region::default(),
+            squelch: SquelchSettings::default(),
+            agc: mode.agc(),
+            custom_agc: agc::SLOW,
+        }
+    }
+
+    /// Times of the selected AGC, none when it is off
+    pub fn agc_times(&self) -> Option<AgcTimes> {
+        self.agc.times(self.custom_agc)
+    }
+}
+
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audio_rate() {
+        let input = vec![Complex32::new(0.5, 0.1); 2400];
+        for mode in Mode::ALL {
+            let mut demod = mode.demodulator(240_000.0, 5, &DemodSettings::new(mode));
+            let mut output = vec![];
+            demod.process(&input, &mut output);
+            assert_eq!(output.len(), 480 * demod.channels(), "{}", mode.name());
+
+            let mut settings = DemodSettings::new(mode);
+            settings.squelch.mode = SquelchMode::Power;
+            let mut demod = mode.demodulator(240_000.0, 5, &settings);
+            let mut output = vec![];
+            demod.process(&input, &mut output);
+            assert_eq!(output.len(), 480 * demod.channels(), "{}", mode.name());
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! Synthetic signals for the demodulator tests.
+//!
+//! IQ is at the channel rate of 240 kHz and the audio is measured at 48 kHz.
+
+use std::f32::consts::PI;
+
+use futuresdr::num_complex::Complex32;
+
+
+pub const RATE: f32 = 240_000.0;
+const AUDIO_RATE: f32 = 48_000.0;
+
+/// AM carrier `offset` Hz off the center, modulated to 50 % with 1 kHz tone
+pub fn am(len: usize, amplitude: f32, offset: f32) -> Vec<Complex32> {
+    (0..len)
+        .map(|i| {
+            let t = i as f32 / RATE;
+            let envelope = 1.0 + 0.5 * (2.0 * PI * 1_000.0 * t).sin();
+            Complex32::from_polar(amplitude * envelope, 2.0 * PI * offset * t + 0.7)
+        })
+        .collect()
+}
+
+/// FM carrier modulated with the tones given as (frequency, deviation) in Hz
+pub fn fm(len: usize, amplitude: f32, tones: &[(f32, f32)]) -> Vec<Complex32> {
+    let mut phase = 0.0;
+    (0..len)
+        .map(|i| {
+            let t = i as f32 / RATE;
+            let deviation: f32 = tones.iter().map(|&(freq, dev)| dev * (2.0 * PI * freq * t).sin()).sum();
+            phase += 2.0 * PI * deviation / RATE;
+            Complex32::from_polar(amplitude, phase)
+        })
+        .collect()
+}
+
+/// Complex gaussian noise with the given power
+pub fn noise(len: usize, power: f32) -> Vec<Complex32> {
+    let mut seed: u32 = 12345;
+    let mut uniform = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        seed as f32 / u32::MAX as f32 - 0.5
+    };
+    // Sum of 12 uniform samples has the unit variance
+    let mut gauss = move || (0..12).map(|_| uniform()).sum::<f32>();
+    let sigma = (power / 2.0).sqrt();
+    (0..len).map(|_| Complex32::new(sigma * gauss(), sigma * gauss())).collect()
+}
+
+/// Amplitude of the tone in the audio
+pub fn tone_level(audio: &[f32], freq: f32) -> f32 {
+    let c: Complex32 = audio.iter()
+        .enumerate()
+        .map(|(i, &x)| x * Complex32::from_polar(1.0, -2.0 * PI * freq * i as f32 / AUDIO_RATE))
+        .sum();
+    2.0 * c.norm() / audio.len() as f32
+}
+
+pub fn peak(audio: &[f32]) -> f32 {
+    audio.iter().fold(0.0, |acc: f32, x| acc.max(x.abs()))
+}
@@ -0,0 +1,269 @@
+//! Squelch which mutes the audio when there is no signal.
+//!
+//! Power squelch measures the power of the channel filtered IQ.
+//! Noise squelch demodulates FM and measures the noise above the modulation,
+//! which is high when there is no carrier and drops quickly when the signal is received.
+
+use futuresdr::num_complex::Complex32;
+use serde::{Deserialize, Serialize};
+
+use super::{Demodulator, Mode, RdsInfo};
+use crate::backend::dsp::{cascade, complex_bandpass, num_taps, Biquad, Fir, QuadratureDetector};
+
+
+// Time constant of the level measurement in seconds
+const AVERAGE_TIME: f32 = 0.05;
+// Length of the audio fade in and out in seconds
+const FADE_TIME: f32 = 0.005;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SquelchMode {
+    Off,
+    Power,
+    /// Only for FM. Other modes use the power squelch.
+    Noise,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SquelchSettings {
+    pub mode: SquelchMode,
+    /// Power squelch opens above this channel power in dBFS
+    pub power_threshold: f32,
+    /// Noise squelch opens below this noise level in dB
+    pub noise_threshold: f32,
+    /// Difference between the open and close levels in dB
+    pub hysteresis: f32,
+}
+
+/// State of the squelch shown to the user
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SquelchState {
+    pub open: bool,
+    /// Measured power in dBFS or noise in dB
+    pub level: f32,
+}
+
+pub struct Squelch {
+    settings: SquelchSettings,
+    channel_filter: Fir<Complex32, Complex32>,
+    // Demodulator and high pass filter of the noise squelch
+    noise: Option<(QuadratureDetector, Vec<Biquad>)>,
+    // Discriminator output which gives the full channel deviation
+    noise_gain: f32,
+    alpha: f32,
+    level: f32,
+    open: bool,
+}
+
+/// Demodulator with the audio gated by the squelch
+pub struct Squelched {
+    demodulator: Box<dyn Demodulator>,
+    squelch: Squelch,
+    gain: f32,
+    fade_step: f32,
+}
+
+impl Default for SquelchSettings {
+    fn default() -> Self {
+        Self {
+            mode: SquelchMode::Off,
+            power_threshold: -60.0,
+            noise_threshold: -20.0,
+            hysteresis: 3.0,
+        }
+    }
+}
+
+impl SquelchMode {
+    pub const ALL: [SquelchMode; 3] = [SquelchMode::Off, SquelchMode::Power, SquelchMode::Noise];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SquelchMode::Off => "Off",
+            SquelchMode::Power => "Power",
+            SquelchMode::Noise => "Noise",
+        }
+    }
+}
+
+impl Squelch {
+    /// Squelch for the channel of the given mode. `passband` is the channel in Hz relative to the tuned frequency.
+    pub fn new(rate: f32, mode: Mode, passband: (f32, f32), settings: SquelchSettings) -> Self {
+        let (low, high) = passband;
+        let width = high - low;
+        // Decimate as much as the passband allows
+        let decim = ((rate / (2.0 * low.abs().max(high.abs()))) as usize).max(1);
+        let channel_rate = rate / decim as f32;
+        let taps = complex_bandpass(low / rate, high / rate, num_taps((width / 4.0).max(100.0) / rate));
+        let is_fm = mode == Mode::Wfm || mode == Mode::Nfm;
+        let noise = (settings.mode == SquelchMode::Noise && is_fm)
+            .then(|| (QuadratureDetector::new(), Biquad::butterworth_highpass(0.4 * width / channel_rate, 4)));
+        Self {
+            settings,
+            channel_filter: Fir::with_decimation(taps, decim),
+            noise,
+            noise_gain: channel_rate / (std::f32::consts::PI * width),
+            alpha: 1.0 - (-1.0 / (AVERAGE_TIME * channel_rate)).exp(),
+            level: 0.0,
+            open: false,
+        }
+    }
+
+    pub fn state(&self) -> SquelchState {
+        SquelchState { open: self.open, level: self.level_db() }
+    }
+
+    /// Measure the channel IQ and update the state. Returns true if the squelch is open.
+    pub fn process(&mut self, input: &[Complex32]) -> bool {
+        for &v in input {
+            if let Some(x) = self.channel_filter.process_sample(v) {
+                let energy = match &mut self.noise {
+                    Some((detector, filter)) => {
+                        let noise = cascade(filter, self.noise_gain * detector.process_sample(x));
+                        noise * noise
+                    }
+                    None => x.norm_sqr(),
+                };
+                self.level += self.alpha * (energy - self.level);
+            }
+        }
+
+        let level = self.level_db();
+        let SquelchSettings { power_threshold, noise_threshold, hysteresis, .. } = self.settings;
+        self.open = match self.noise {
+            Some(_) if self.open => level < noise_threshold + hysteresis,
+            Some(_) => level < noise_threshold,
+            None if self.open => level > power_threshold - hysteresis,
+            None => level > power_threshold,
+        };
+        self.open
+    }
+
+    fn level_db(&self) -> f32 {
+        10.0 * self.level.max(1e-12).log10()
+    }
+}
+
+impl Squelched {
+    pub fn new(demodulator: Box<dyn Demodulator>, squelch: Squelch, audio_rate: f32) -> Self {
+        Self {
+            demodulator,
+            squelch,
+            gain: 0.0,
+            fade_step: 1.0 / (FADE_TIME * audio_rate),
+        }
+    }
+}
+
+impl Demodulator for Squelched {
+    fn process(&mut self, input: &[Complex32], output: &mut Vec<f32>) {
+        let target = if self.squelch.process(input) { 1.0 } else { 0.0 };
+        let start = output.len();
+        self.demodulator.process(input, output);
+        let channels = self.demodulator.channels();
+        for frame in output[start..].chunks_mut(channels) {
+            self.gain = if target > self.gain {
+                (self.gain + self.fade_step).min(target)
+            } else {
+                (self.gain - self.fade_step).max(target)
+            };
+            frame.iter_mut().for_each(|x| *x *= self.gain);
+        }
+    }
+
+    fn channels(&self) -> usize {
+        self.demodulator.channels()
+    }
+
+    fn is_stereo(&self) -> bool {
+        self.demodulator.is_stereo()
+    }
+
+    fn rds(&self) -> Option<RdsInfo> {
+        self.demodulator.rds()
+    }
+
+    fn squelch(&self) -> Option<SquelchState> {
+        Some(self.squelch.state())
+    }
+}
+
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::demod::DemodSettings;
+    use crate::backend::demod::signal::{self, noise, RATE};
+
+    // NFM voice tone with 2 kHz deviation
+    fn nfm(len: usize, amplitude: f32) -> Vec<Complex32> {
+        signal::fm(len, amplitude, &[(1_000.0, 2_000.0)])
+    }
+
+    fn add(a: &[Complex32], b: &[Complex32]) -> Vec<Complex32> {
+        a.iter().zip(b).map(|(x, y)| x + y).collect()
+    }
+
+    fn settings(mode: SquelchMode) -> SquelchSettings {
+        SquelchSettings { mode, ..Default::default() }
+    }
+
+    #[test]
+    fn test_power_squelch() {
+        let mut squelch = Squelch::new(RATE, Mode::Am, (-5_000.0, 5_000.0), settings(SquelchMode::Power));
+        // Noise at -60 dBFS in 240 kHz gives about -74 dBFS in the channel
+        assert!(!squelch.process(&noise(24_000, 1e-6)));
+        assert!((squelch.state().level + 74.0).abs() < 2.0);
+
+        // Carrier at -40 dBFS
+        let carrier = vec![Complex32::new(0.01, 0.0); 24_000];
+        assert!(squelch.process(&carrier));
+        assert!((squelch.state().level + 40.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_hysteresis() {
+        let mut squelch = Squelch::new(RATE, Mode::Am, (-5_000.0, 5_000.0), settings(SquelchMode::Power));
+        // -61 dBFS is between the close and open levels
+        let weak = vec![Complex32::new(10f32.powf(-61.0 / 20.0), 0.0); 96_000];
+        assert!(!squelch.process(&weak));
+        squelch.process(&vec![Complex32::new(0.01, 0.0); 96_000]);
+        assert!(squelch.process(&weak));
+        // -65 dBFS closes it
+        assert!(!squelch.process(&vec![Complex32::new(10f32.powf(-65.0 / 20.0), 0.0); 96_000]));
+    }
+
+    #[test]
+    fn test_noise_squelch() {
+        let mut squelch = Squelch::new(RATE, Mode::Nfm, (-6_250.0, 6_250.0), settings(SquelchMode::Noise));
+        let len = 48_000;
+        assert!(!squelch.process(&noise(len, 1e-4)));
+
+        // Signal 20 dB above the noise in the channel
+        assert!(squelch.process(&add(&nfm(len, 0.05), &noise(len, 1e-4))));
+        assert!(!squelch.process(&noise(len, 1e-4)));
+    }
+
+    #[test]
+    fn test_gated_audio() {
+        let mode = Mode::Nfm;
+        let demodulator = mode.demodulator(RATE, 5, &DemodSettings::new(mode));
+        let squelch = Squelch::new(RATE, mode, (-6_250.0, 6_250.0), settings(SquelchMode::Power));
+        let mut demod = Squelched::new(demodulator, squelch, RATE / 5.0);
+
+        let mut audio = vec![];
+        demod.process(&noise(24_000, 1e-6), &mut audio);
+        assert!(audio.iter().all(|&x| x == 0.0));
+        assert_eq!(demod.squelch().map(|s| s.open), Some(false));
+
+        audio.clear();
+        demod.process(&nfm(24_000, 0.1), &mut audio);
+        assert!(audio.iter().any(|&x| x != 0.0));
+        assert_eq!(demod.squelch().map(|s| s.open), Some(true));
+    }
+}
@@ -0,0 +1,107 @@
+//! Single sideband and CW
+//!
+//! Weaver method: the middle of the audio passband is shifted to 0 Hz, filtered with the low pass
+//! filter and shifted back. The real part of the result is the audio.
+
+use futuresdr::num_complex::Complex32;
+
+use super::am::apply_agc;
+use super::Demodulator;
+use crate::backend::dsp::{lowpass, num_taps, Agc, Fir, Nco};
+
+
+pub struct Ssb {
+    decimator: Fir<Complex32, f32>,
+    down: Nco,
+    filter: Fir<Complex32, f32>,
+    agc: Option<Agc>,
+    up: Nco,
+}
+
+impl Ssb {
+    /// `low` and `high` are the edges of the audio passband in Hz.
+    /// Positive passband gives USB, negative gives LSB.
+    pub fn new(rate: f32, audio_decim: usize, low: f32, high: f32) -> Self {
+        let center = (low + high) / 2.0;
+        Self::with_shift(rate, audio_decim, -center, (high - low).abs(), center)
+    }
+
+    /// CW receiver with the narrow filter around the tuned frequency.
+    /// Carrier at the tuned frequency gives the tone at `pitch` Hz.
+    pub fn cw(rate: f32, audio_decim: usize, bandwidth: f32, pitch: f32) -> Self {
+        Self::with_shift(rate, audio_decim, 0.0, bandwidth, pitch)
+    }
+
+    /// Normalize the filtered signal before it is shifted back to audio
+    pub fn with_agc(mut self, agc: Option<Agc>) -> Self {
+        self.agc = agc;
+        self
+    }
+
+    fn with_shift(rate: f32, audio_decim: usize, down: f32, bandwidth: f32, up: f32) -> Self {
+        let audio_rate = rate / audio_decim as f32;
+        let decim_taps = lowpass(0.4 * audio_rate / rate, num_taps(0.1 * audio_rate / rate));
+        let transition = (bandwidth / 4.0).clamp(50.0, 300.0);
+        let filter_taps = lowpass(bandwidth / 2.0 / audio_rate, num_taps(transition / audio_rate));
+        Self {
+            decimator: Fir::with_decimation(decim_taps, audio_decim),
+            down: Nco::new(down / audio_rate),
+            filter: Fir::new(filter_taps),
+            agc: None,
+            up: Nco::new(up / audio_rate),
+        }
+    }
+}
+
+impl Demodulator for Ssb {
+    fn process(&mut self, input: &[Complex32], output: &mut Vec<f32>) {
+        for &v in input {
+            if let Some(x) = self.decimator.process_sample(v) {
+                let baseband = x * self.down.next_sample();
+                if let Some(y) = self.filter.process_sample(baseband) {
+                    let y = apply_agc(&mut self.agc, y);
+                    output.push((y * self.up.next_sample()).re);
+                }
+            }
+        }
+    }
+}
+
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    // Level of the audio after the filter settles, when the tone at `freq` Hz (from the tuned frequency) is received
+    fn level(demod: &mut Ssb, freq: f32) -> f32 {
+        let input: Vec<Complex32> = (0..48_000)
+            .map(|i| Complex32::from_polar(1.0, 2.0 * PI * freq * i as f32 / 240_000.0))
+            .collect();
+        let mut audio = vec![];
+        demod.process(&input, &mut audio);
+        audio[4_800..].iter().fold(0.0, |acc: f32, x| acc.max(x.abs()))
+    }
+
+    #[test]
+    fn test_usb() {
+        assert!(level(&mut Ssb::new(240_000.0, 5, 300.0, 2_700.0), 1_000.0) > 0.9);
+        assert!(level(&mut Ssb::new(240_000.0, 5, 300.0, 2_700.0), -1_000.0) < 0.01);
+        assert!(level(&mut Ssb::new(240_000.0, 5, 300.0, 2_700.0), 3_500.0) < 0.01);
+    }
+
+    #[test]
+    fn test_lsb() {
+        assert!(level(&mut Ssb::new(240_000.0, 5, -2_700.0, -300.0), -1_000.0) > 0.9);
+        assert!(level(&mut Ssb::new(240_000.0, 5, -2_700.0, -300.0), 1_000.0) < 0.01);
+    }
+
+    #[test]
+    fn test_cw() {
+        assert!(level(&mut Ssb::cw(240_000.0, 5, 500.0, 700.0), 100.0) > 0.9);
+        assert!(level(&mut Ssb::cw(240_000.0, 5, 500.0, 700.0), 600.0) < 0.01);
+    }
+}
@@ -0,0 +1,111 @@
+//! Discovery of the SDR devices and their capabilities
+
+use std::collections::BTreeMap;
+
+use futuresdr::anyhow::Result;
+use futuresdr::seify::{self, Device, Direction, Range, RangeItem};
+
+
+// Sample rates offered when the device supports them
+const COMMON_RATES: [f64; 13] = [
+    250e3, 1e6, 1.024e6, 1.8e6, 2e6, 2.048e6, 2.4e6, 2.56e6, 3.2e6, 5e6, 8e6, 10e6, 20e6,
+];
+
+/// Device and the receiver settings chosen by the user
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceSettings {
+    /// Seify args, e.g. "driver=rtlsdr"
+    pub args: String,
+    pub rate: f64,
+    /// Overall gain in dB
+    pub gain: f64,
+    /// Gain of the individual stages in dB, set after the overall gain
+    pub gain_elements: BTreeMap<String, f64>,
+    /// Hardware AGC
+    pub agc: bool,
+    /// Frequency error of the device in ppm
+    pub ppm: f64,
+    /// Antenna port, the device default if not set
+    pub antenna: Option<String>,
+}
+
+impl DeviceSettings {
+    /// Frequency the device must be tuned to, so that it receives `frequency` despite its error
+    pub fn corrected(&self, frequency: f64) -> f64 {
+        frequency / (1.0 + self.ppm * 1e-6)
+    }
+}
+
+/// Device found by Seify
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceInfo {
+    /// Seify args which open this device
+    pub args: String,
+    pub label: String,
+}
+
+/// What the receiver of the device supports
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    pub sample_rates: Vec<f64>,
+    /// Overall gain range in dB
+    pub gain: (f64, f64),
+    /// Names of the gain stages and their ranges in dB
+    pub gain_elements: Vec<(String, (f64, f64))>,
+    pub antennas: Vec<String>,
+    pub supports_agc: bool,
+    pub frequency_range: Range,
+}
+
+/// List devices connected to the computer
+pub fn enumerate() -> Result<Vec<DeviceInfo>> {
+    let devices = seify::enumerate()?;
+    Ok(devices
+        .into_iter()
+        .map(|args| {
+            let label = args.get::<String>("label")
+                .or_else(|_| args.get::<String>("driver"))
+                .unwrap_or_else(|_| args.to_string());
+            DeviceInfo { args: args.to_string(), label }
+        })
+        .collect())
+}
+
+impl Capabilities {
+    /// Open the device and ask for its capabilities. The device must not be used by the flowgraph.
+    pub fn query(args: &str) -> Result<Self> {
+        let device = Device::from_args(args)?;
+        let rate_range = device.get_sample_rate_range(Direction::Rx, 0)?;
+        let mut sample_rates: Vec<f64> = COMMON_RATES.into_iter().filter(|&r| rate_range.contains(r)).collect();
+        if sample_rates.is_empty() {
+            sample_rates.push(bounds(&rate_range).1);
+        }
+        let gain_elements = device.gain_elements(Direction::Rx, 0)?
+            .into_iter()
+            .map(|name| {
+                let range = device.gain_element_range(Direction::Rx, 0, &name).map(|r| bounds(&r)).unwrap_or((0.0, 0.0));
+                (name, range)
+            })
+            .collect();
+
+        Ok(Self {
+            sample_rates,
+            gain: bounds(&device.gain_range(Direction::Rx, 0)?),
+            gain_elements,
+            antennas: device.antennas(Direction::Rx, 0)?,
+            supports_agc: device.supports_agc(Direction::Rx, 0)?,
+            frequency_range: device.frequency_range(Direction::Rx, 0)?,
+        })
+    }
+}
+
+// Lowest and highest value in the range
+fn bounds(range: &Range) -> (f64, f64) {
+    range.items.iter()
+        .map(|item| match *item {
+            RangeItem::Interval(min, max) => (min, max),
+            RangeItem::Value(v) => (v, v),
+            RangeItem::Step(min, max, _) => (min, max),
+        })
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), (min, max)| (lo.min(min), hi.max(max)))
+}
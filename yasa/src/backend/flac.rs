@@ -0,0 +1,396 @@
+//! Streaming FLAC encoder for the 16 bit audio.
+//!
+//! Every block is predicted with the best of the fixed predictors and the residual is Rice coded.
+//! Stereo is coded as the left and side channel, so the mono audio sent to both channels costs
+//! almost nothing. Stream info is written again with the number of samples when the file is finished.
+//! See <https://xiph.org/flac/format.html>
+//!
+//! flacenc encodes a whole source held in memory, while the recordings run for hours and every
+//! block must reach the disk as it is received, so the encoder is kept here. Recordings are
+//! 16 bit only. Tests decode the streams with claxon.
+
+use std::io::{self, Seek, SeekFrom, Write};
+
+
+// Samples of every channel in one frame
+const BLOCK_SIZE: usize = 4096;
+const BITS_PER_SAMPLE: u32 = 16;
+// Largest parameter of the 4 bit Rice coding
+const MAX_RICE_PARAMETER: u32 = 14;
+
+pub struct FlacWriter<W: Write + Seek> {
+    out: W,
+    rate: u32,
+    channels: usize,
+    // Interleaved samples of the unfinished block
+    block: Vec<i32>,
+    frames: u64,
+    // Samples of every channel
+    samples: u64,
+    // Sizes of the frames in bytes
+    min_frame: usize,
+    max_frame: usize,
+}
+
+// Bits are written from the most significant one
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u8,
+    len: u32,
+}
+
+impl<W: Write + Seek> FlacWriter<W> {
+    /// Start the stream of 16 bit audio. Channels go from 1 to 8.
+    pub fn new(out: W, rate: u32, channels: usize) -> io::Result<Self> {
+        let mut writer = Self {
+            out,
+            rate,
+            channels,
+            block: Vec::with_capacity(BLOCK_SIZE * channels),
+            frames: 0,
+            samples: 0,
+            min_frame: 0,
+            max_frame: 0,
+        };
+        writer.out.write_all(b"fLaC")?;
+        let stream_info = writer.stream_info();
+        writer.out.write_all(&stream_info)?;
+        Ok(writer)
+    }
+
+    /// Write interleaved samples
+    pub fn write(&mut self, samples: &[i16]) -> io::Result<()> {
+        for &sample in samples {
+            self.block.push(sample as i32);
+            if self.block.len() == BLOCK_SIZE * self.channels {
+                self.write_frame()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the last block and update the stream info
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.block.len() >= self.channels {
+            self.write_frame()?;
+        }
+        self.out.seek(SeekFrom::Start(4))?;
+        let stream_info = self.stream_info();
+        self.out.write_all(&stream_info)?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    // The only metadata block
+    fn stream_info(&self) -> Vec<u8> {
+        let mut bits = BitWriter::new();
+        // Last block, STREAMINFO, length
+        bits.write(1, 1);
+        bits.write(0, 7);
+        bits.write(34, 24);
+        bits.write(BLOCK_SIZE as u64, 16);
+        bits.write(BLOCK_SIZE as u64, 16);
+        bits.write(self.min_frame as u64, 24);
+        bits.write(self.max_frame as u64, 24);
+        bits.write(self.rate as u64, 20);
+        bits.write(self.channels as u64 - 1, 3);
+        bits.write(BITS_PER_SAMPLE as u64 - 1, 5);
+        bits.write(self.samples, 36);
+        // MD5 of the audio is unknown
+        bits.write(0, 64);
+        bits.write(0, 64);
+        bits.bytes
+    }
+
+    fn write_frame(&mut self) -> io::Result<()> {
+        let size = self.block.len() / self.channels;
+        let channel = |c: usize| -> Vec<i32> { self.block.iter().skip(c).step_by(self.channels).copied().collect() };
+        let (assignment, subframes) = if self.channels == 2 {
+            let left = channel(0);
+            let side = left.iter().zip(channel(1)).map(|(l, r)| l - r).collect();
+            // Side channel needs one more bit
+            (0b1000, vec![(left, BITS_PER_SAMPLE), (side, BITS_PER_SAMPLE + 1)])
+        } else {
+            (self.channels as u64 - 1, (0..self.channels).map(|c| (channel(c), BITS_PER_SAMPLE)).collect())
+        };
+
+        let mut bits = BitWriter::new();
+        // Sync code and the fixed block size
+        bits.write(0b11_1111_1111_1110, 14);
+        bits.write(0, 2);
+        // Size of the last block is at the end of the header, the rate is in the stream info
+        bits.write(if size == BLOCK_SIZE { 0b1100 } else { 0b0111 }, 4);
+        bits.write(0, 4);
+        bits.write(assignment, 4);
+        bits.write(0b100, 3);
+        bits.write(0, 1);
+        bits.write_utf8(self.frames);
+        if size != BLOCK_SIZE {
+            bits.write(size as u64 - 1, 16);
+        }
+        let crc = crc8(&bits.bytes);
+        bits.write(crc as u64, 8);
+
+        for (samples, bps) in &subframes {
+            write_subframe(&mut bits, samples, *bps);
+        }
+        bits.align();
+        let crc = crc16(&bits.bytes);
+        bits.write(crc as u64, 16);
+
+        let frame = bits.bytes;
+        self.out.write_all(&frame)?;
+        self.min_frame = if self.frames == 0 { frame.len() } else { self.min_frame.min(frame.len()) };
+        self.max_frame = self.max_frame.max(frame.len());
+        self.frames += 1;
+        self.samples += size as u64;
+        self.block.clear();
+        Ok(())
+    }
+}
+
+// Constant, fixed prediction or verbatim, whichever is the smallest
+fn write_subframe(bits: &mut BitWriter, samples: &[i32], bps: u32) {
+    if samples.iter().all(|&s| s == samples[0]) {
+        bits.write(0b0000_0000, 8);
+        bits.write_signed(samples[0] as i64, bps);
+        return;
+    }
+
+    let (order, residual) = (0..=4.min(samples.len()))
+        .map(|order| (order, fixed_residual(samples, order)))
+        .min_by_key(|(_, residual)| residual.iter().map(|r| r.unsigned_abs()).sum::<u64>())
+        .unwrap();
+    let (parameter, rice_bits) = (0..=MAX_RICE_PARAMETER)
+        .map(|k| (k, residual.iter().map(|&r| (zigzag(r) >> k) + 1 + k as u64).sum::<u64>()))
+        .min_by_key(|(_, size)| *size)
+        .unwrap();
+
+    let fixed_bits = order as u64 * bps as u64 + 10 + rice_bits;
+    if fixed_bits >= samples.len() as u64 * bps as u64 {
+        bits.write(0b0000_0010, 8);
+        for &sample in samples {
+            bits.write_signed(sample as i64, bps);
+        }
+        return;
+    }
+
+    bits.write(0b0001_0000 | (order as u64) << 1, 8);
+    for &sample in &samples[..order] {
+        bits.write_signed(sample as i64, bps);
+    }
+    // Rice coding with the 4 bit parameter in one partition
+    bits.write(0, 2);
+    bits.write(0, 4);
+    bits.write(parameter as u64, 4);
+    for &r in &residual {
+        let u = zigzag(r);
+        bits.write_unary(u >> parameter);
+        bits.write(u, parameter);
+    }
+}
+
+// Prediction error of the fixed polynomial predictor
+fn fixed_residual(samples: &[i32], order: usize) -> Vec<i64> {
+    let s = |i: usize| samples[i] as i64;
+    (order..samples.len())
+        .map(|i| match order {
+            0 => s(i),
+            1 => s(i) - s(i - 1),
+            2 => s(i) - 2 * s(i - 1) + s(i - 2),
+            3 => s(i) - 3 * s(i - 1) + 3 * s(i - 2) - s(i - 3),
+            _ => s(i) - 4 * s(i - 1) + 6 * s(i - 2) - 4 * s(i - 3) + s(i - 4),
+        })
+        .collect()
+}
+
+// Signed value folded to unsigned, 0, -1, 1, -2... go to 0, 1, 2, 3...
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 })
+    })
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ (byte as u16) << 8, |crc, _| if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 })
+    })
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self { bytes: vec![], acc: 0, len: 0 }
+    }
+
+    fn bit(&mut self, bit: bool) {
+        self.acc = (self.acc << 1) | bit as u8;
+        self.len += 1;
+        if self.len == 8 {
+            self.bytes.push(self.acc);
+            self.acc = 0;
+            self.len = 0;
+        }
+    }
+
+    // Lowest `n` bits of the value
+    fn write(&mut self, value: u64, n: u32) {
+        for i in (0..n).rev() {
+            self.bit((value >> i) & 1 == 1);
+        }
+    }
+
+    fn write_signed(&mut self, value: i64, n: u32) {
+        self.write(value as u64, n);
+    }
+
+    // Zeros followed by one
+    fn write_unary(&mut self, value: u64) {
+        for _ in 0..value {
+            self.bit(false);
+        }
+        self.bit(true);
+    }
+
+    // Frame number coded like the UTF-8 characters
+    fn write_utf8(&mut self, value: u64) {
+        if value < 0x80 {
+            self.write(value, 8);
+            return;
+        }
+        // Bytes needed, every one holds 5 bits more
+        let mut n = 2;
+        while value >> (5 * n + 1) != 0 {
+            n += 1;
+        }
+        self.write(((0xFF00 >> n) & 0xFF) | (value >> (6 * (n - 1))), 8);
+        for i in (0..n - 1).rev() {
+            self.write(0x80 | ((value >> (6 * i)) & 0x3F), 8);
+        }
+    }
+
+    // Fill the byte with zeros
+    fn align(&mut self) {
+        while self.len != 0 {
+            self.bit(false);
+        }
+    }
+}
+
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_crc() {
+        assert_eq!(crc8(b"123456789"), 0xF4);
+        assert_eq!(crc16(b"123456789"), 0xFEE8);
+    }
+
+    #[test]
+    fn test_utf8() {
+        let utf8 = |value| {
+            let mut bits = BitWriter::new();
+            bits.write_utf8(value);
+            bits.bytes
+        };
+        assert_eq!(utf8(0x41), vec![0x41]);
+        assert_eq!(utf8(0xE9), "é".as_bytes());
+        assert_eq!(utf8(0x20AC), "€".as_bytes());
+        assert_eq!(utf8(0x1F600), "😀".as_bytes());
+    }
+
+    #[test]
+    fn test_residual() {
+        let ramp = [1, 3, 5, 7, 9];
+        assert_eq!(fixed_residual(&ramp, 1), vec![2, 2, 2, 2]);
+        assert_eq!(fixed_residual(&ramp, 2), vec![0, 0, 0]);
+        assert_eq!([0, -1, 1, -2].map(zigzag), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_stream() {
+        let mut writer = FlacWriter::new(Cursor::new(vec![]), 48_000, 2).unwrap();
+        // Mono in both channels, so the side is constant
+        let audio: Vec<i16> = (0..5000).flat_map(|i| {
+            let s = (1000.0 * (i as f32 * 0.05).sin()) as i16;
+            [s, s]
+        }).collect();
+        writer.write(&audio).unwrap();
+        let flac = writer.finish().unwrap().into_inner();
+
+        assert_eq!(&flac[..4], b"fLaC");
+        // Stream info: 48 kHz, 2 channels, 16 bits, 5000 samples
+        assert_eq!(&flac[18..22], &[0x0B, 0xB8, 0x02, 0xF0]);
+        assert_eq!(&flac[22..26], &[0x00, 0x00, 0x13, 0x88]);
+        // First frame with the left and side channel
+        assert_eq!(&flac[42..46], &[0xFF, 0xF8, 0xC0, 0x88]);
+        // Smaller than the half of the raw audio
+        assert!(flac.len() < audio.len());
+    }
+
+    // Encode in the uneven writes and check that claxon decodes the same samples
+    fn round_trip(audio: &[i16], channels: usize) {
+        let mut writer = FlacWriter::new(Cursor::new(vec![]), 44_100, channels).unwrap();
+        for chunk in audio.chunks(1001 * channels) {
+            writer.write(chunk).unwrap();
+        }
+        let flac = writer.finish().unwrap().into_inner();
+
+        let mut reader = claxon::FlacReader::new(Cursor::new(flac)).unwrap();
+        let info = reader.streaminfo();
+        assert_eq!(info.sample_rate, 44_100);
+        assert_eq!(info.channels as usize, channels);
+        assert_eq!(info.bits_per_sample, BITS_PER_SAMPLE);
+        assert_eq!(info.samples, Some((audio.len() / channels) as u64));
+        let decoded: Vec<i16> = reader.samples().map(|s| s.unwrap() as i16).collect();
+        assert_eq!(decoded, audio, "{channels} channels, {} samples", audio.len() / channels);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // Random numbers from the linear congruential generator
+        let mut seed = 1u32;
+        let mut noise = move || {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (seed >> 16) as i16
+        };
+        // Silence, full scale square, sine of the different frequency in every channel and noise
+        let signals: [&mut dyn FnMut(usize, usize) -> i16; 4] = [
+            &mut |_, _| 0,
+            &mut |i, c| if (i + c) % 2 == 0 { i16::MAX } else { i16::MIN },
+            &mut |i, c| (20_000.0 * (i as f32 * 0.01 * (c + 1) as f32).sin()) as i16,
+            &mut |_, _| noise(),
+        ];
+        for signal in signals {
+            // Single sample, around the block size and the short last block
+            for size in [1, 5, BLOCK_SIZE - 1, BLOCK_SIZE, BLOCK_SIZE + 1, 3 * BLOCK_SIZE + 7] {
+                for channels in 1..=8 {
+                    let audio: Vec<i16> = (0..size).flat_map(|i| (0..channels).map(move |c| (i, c)))
+                        .map(|(i, c)| signal(i, c))
+                        .collect();
+                    round_trip(&audio, channels);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        let writer = FlacWriter::new(Cursor::new(vec![]), 8_000, 1).unwrap();
+        let flac = writer.finish().unwrap().into_inner();
+        let mut reader = claxon::FlacReader::new(Cursor::new(flac)).unwrap();
+        // Zero samples in the stream info stands for the unknown length
+        assert_eq!(reader.streaminfo().samples, None);
+        assert_eq!(reader.samples().count(), 0);
+    }
+}
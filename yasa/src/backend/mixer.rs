@@ -0,0 +1,84 @@
+use std::sync::{Arc, Mutex};
+
+use futuresdr::anyhow::Result;
+use futuresdr::macros::async_trait;
+use futuresdr::runtime::BlockMeta;
+use futuresdr::runtime::BlockMetaBuilder;
+use futuresdr::runtime::Kernel;
+use futuresdr::runtime::MessageIo;
+use futuresdr::runtime::MessageIoBuilder;
+use futuresdr::runtime::StreamIo;
+use futuresdr::runtime::StreamIoBuilder;
+use futuresdr::runtime::WorkIo;
+use futuresdr::runtime::{Block, TypedBlock};
+
+use super::channels::mix;
+
+/// Gains of the mixer inputs, which the UI changes for the mute and solo
+pub type SharedGains = Arc<Mutex<Vec<f32>>>;
+
+/// Mix the audio of the VFO and the channels.
+///
+/// # Inputs
+///
+/// `in0`, `in1`...: Interleaved stereo audio, all at the same rate
+///
+/// # Outputs
+///
+/// `out`: Sum of the inputs with their gains
+pub struct Mixer {
+    gains: SharedGains,
+    output: Vec<f32>,
+}
+
+impl Mixer {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(inputs: usize, gains: SharedGains) -> Block {
+        Block::from_typed(Self::new_typed(inputs, gains))
+    }
+
+    pub fn new_typed(inputs: usize, gains: SharedGains) -> TypedBlock<Self> {
+        let mut sio = StreamIoBuilder::new();
+        for i in 0..inputs {
+            sio = sio.add_input::<f32>(&format!("in{i}"));
+        }
+        TypedBlock::new(
+            BlockMetaBuilder::new("Mixer").build(),
+            sio.add_output::<f32>("out").build(),
+            MessageIoBuilder::<Self>::new().build(),
+            Mixer { gains, output: vec![] },
+        )
+    }
+}
+
+#[doc(hidden)]
+#[async_trait]
+impl Kernel for Mixer {
+    async fn work(
+        &mut self,
+        io: &mut WorkIo,
+        sio: &mut StreamIo,
+        _mio: &mut MessageIo<Self>,
+        _meta: &mut BlockMeta,
+    ) -> Result<()> {
+        let inputs: Vec<&[f32]> = (0..sio.inputs().len()).map(|i| sio.input(i).slice::<f32>()).collect();
+        let o = sio.output(0).slice::<f32>();
+        let n = inputs.iter().map(|i| i.len()).min().unwrap_or(0).min(o.len());
+
+        let slices: Vec<&[f32]> = inputs.iter().map(|i| &i[..n]).collect();
+        mix(&slices, &self.gains.lock().unwrap(), &mut self.output);
+        o[..n].copy_from_slice(&self.output);
+
+        // One finished input ends the mix, the others are fed by the same source
+        let finished = inputs.iter().enumerate().any(|(i, input)| sio.input(i).finished() && input.len() == n);
+        for i in 0..inputs.len() {
+            sio.input(i).consume(n);
+        }
+        sio.output(0).produce(n);
+        if finished {
+            io.finished = true;
+        }
+
+        Ok(())
+    }
+}
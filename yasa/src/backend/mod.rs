@@ -1 +1,14 @@
+pub mod audio_file;
+mod audio_recorder;
+pub mod channels;
+pub mod demod;
+pub mod device;
+mod flac;
+mod mixer;
 pub mod radio;
+pub mod scanner;
+pub mod spectrum;
+mod spectrum_sink;
+pub mod vfo;
+
+pub use sdr_common::{dsp, iq_file, sigmf};
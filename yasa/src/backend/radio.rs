@@ -1,25 +1,35 @@
-//! A simple FM receiver that you can tune to nearby radio stations
+//! A simple receiver that you can tune to nearby radio stations
 //!
 //! When you run the example, it will build a flowgraph consisting of the following blocks:
-//! * SeifySource: Gets data from your SDR
-//! * Demodulator: Demodulates the FM signal
+//! * SeifySource: Gets data from your SDR, or FileSource plays the IQ file in its place
+//! * Demodulator: Demodulates the signal in the selected mode
+//! * Shift, resampler and Demodulator of every extra channel in the band
+//! * Mixer: Mixes the audio of the VFO and the channels
 //! * AudioSink: Plays the demodulated signal on your device
+//! * Fft and SpectrumSink: Send the spectrum of the received band to the UI
+//! * IqRecorder: Record the band or the channel in the SigMF format
+//! * AudioRecorder: Record the demodulated audio to WAV or FLAC files
 //!
-//! After giving it some time to start up the SDR, it enters a loop where you will
-//! be periodically asked to enter a new frequency that the SDR will be tuned to.
-//! **Watch out** though: Some frequencies (very high or very low) might be unsupported
-//! by your SDR and may cause a crash.
+//! The VFO is tuned inside the received band by shifting it to zero frequency,
+//! the SDR is retuned only when the VFO leaves the band.
 
 
-use futuresdr::anyhow::Result;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use futuresdr::anyhow::{bail, Result};
 use futuresdr::async_io;
 use futuresdr::blocks::audio::AudioSink;
 use futuresdr::blocks::seify::SourceBuilder;
 use futuresdr::blocks::Apply;
-use futuresdr::blocks::Fft;
+use futuresdr::blocks::{Fft, FftDirection};
 use futuresdr::blocks::FirBuilder;
-use futuresdr::futuredsp::firdes;
-use futuresdr::blocks::Sink;
+use futuresdr::futures::FutureExt;
 use futuresdr::log;
 use futuresdr::macros::connect;
 use futuresdr::num_complex::Complex32;
@@ -28,172 +38,810 @@ use futuresdr::runtime::scheduler::SmolScheduler;
 use futuresdr::runtime::Block;
 use futuresdr::runtime::Pmt;
 use futuresdr::runtime::{Flowgraph, FlowgraphHandle, Runtime};
-use ringbuffer::{AllocRingBuffer, RingBuffer};
+use futuresdr::seify::{Device, Direction, GenericDevice};
+use sdr_common::file_source::{self, FileSource, SharedReader};
+use sdr_common::iq_recorder::{IqRecorder, SharedRecorder};
+use sdr_common::shift::{SharedOffset, Shift};
+
+use super::audio_file::{AudioFormat, AudioRecording};
+use super::audio_recorder::{AudioRecorder, SharedAudioRecording};
+use super::channels::{mixer_gains, Channel};
+use super::iq_file::FileArgs;
+use super::mixer::{Mixer, SharedGains};
+use super::sigmf::{self, Recording, SampleFormat, SigmfWriter};
+use super::spectrum::{self, SpectrumAverager, FFT_SIZE, UPDATE_RATE};
+use super::spectrum_sink::SpectrumSink;
+use super::vfo::Vfo;
+use super::device::{Capabilities, DeviceSettings};
+use super::demod::{AgcPreset, DemodSettings, DemodulatorBlock, Mode, RdsInfo, SharedDemodulator, SquelchState};
 
 
-pub struct FMRadio<'a> {
+pub struct Radio<'a> {
     runtime: Runtime<'a, SmolScheduler>,
-    gain: f64,
-    rate: f64,
-    args: String,
+    device: DeviceSettings,
     handle: Option<FlowgraphHandle>,
+    task: Option<FlowgraphTask<'a>>,
     source: Option<SourceBlock>,
+    mode: Mode,
+    settings: DemodSettings,
+    // AGC chosen by the user in every mode
+    agc_presets: HashMap<Mode, AgcPreset>,
+    demodulator: Option<ActiveDemodulator>,
+    spectrum: Option<Receiver<Vec<f32>>>,
+    vfo: Option<Vfo>,
+    vfo_offset: SharedOffset,
+    // Unknown when the device couldn't be opened
+    capabilities: Option<Capabilities>,
+    raw_recorder: SharedRecorder,
+    channel_recorder: SharedRecorder,
+    audio_recording: SharedAudioRecording,
+    channels: Vec<Channel>,
+    // Audio recording of every channel, they continue when the flowgraph is rebuilt
+    channel_recordings: Vec<SharedAudioRecording>,
+    // Blocks of the channels, when running
+    active_channels: Vec<ActiveChannel>,
+    mixer_gains: SharedGains,
+    vfo_mute: bool,
+}
+
+/// Signal written to the IQ recording
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordSource {
+    /// Whole band received by the device
+    Raw,
+    /// Decimated channel around the VFO, as it goes to the demodulator
+    Channel,
+}
+
+/// Progress of the audio recording
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioRecordingStatus {
+    /// File which is written now, none while waiting for the squelch
+    pub path: Option<PathBuf>,
+    pub seconds: f64,
+    /// Number of the recorded files
+    pub files: usize,
 }
 
+/// State of the channel demodulated next to the VFO
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelStatus {
+    pub squelch: Option<SquelchState>,
+    pub recording: Option<AudioRecordingStatus>,
+    /// False when the running radio doesn't receive the channel, then it is silent
+    pub in_band: bool,
+}
+
+/// Progress of the IQ recording
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordingStatus {
+    /// Path without the SigMF extensions
+    pub path: PathBuf,
+    pub seconds: f64,
+    /// Size of the data file in bytes
+    pub size: u64,
+}
+
+// Running flowgraph, which ends with the error when a block fails
+type FlowgraphTask<'a> = Pin<Box<dyn Future<Output = Result<Flowgraph>> + 'a>>;
+
 enum SourceBlock {
-    Seify { id: usize, freq_offset: f64, freq_port_id: usize },
+    // Device is shared with the block. Frequency goes through the port, so the block knows it.
+    // All gain settings are written to the device, so they are applied in one place.
+    Seify { id: usize, freq_port_id: usize, device: Device<GenericDevice> },
+    // File can't be retuned, the VFO moves only inside its band
+    File { reader: SharedReader },
 }
 
-impl FMRadio<'_> {
-    pub fn init(gain: f64, rate: f64, args: &str) -> Self {
+// Demodulator running in the flowgraph and the rates it was built for
+struct ActiveDemodulator {
+    shared: SharedDemodulator,
+    channel_rate: f32,
+    audio_decim: usize,
+    audio_rate: u32,
+}
+
+// Blocks of the channel running in the flowgraph
+struct ActiveChannel {
+    offset: SharedOffset,
+    demodulator: SharedDemodulator,
+}
+
+impl Radio<'_> {
+    pub fn init(device: DeviceSettings) -> Self {
         let runtime = Runtime::new();
+        let capabilities = Self::capabilities(&device.args);
 
         Self { 
             runtime, 
-            gain,
-            rate,
-            args: args.to_owned(), 
+            device,
             handle: None, 
+            task: None,
             source: None,
+            mode: Mode::Wfm,
+            settings: DemodSettings::new(Mode::Wfm),
+            agc_presets: HashMap::new(),
+            demodulator: None,
+            spectrum: None,
+            vfo: None,
+            vfo_offset: Arc::new(Mutex::new(0.0)),
+            capabilities,
+            raw_recorder: Arc::new(Mutex::new(None)),
+            channel_recorder: Arc::new(Mutex::new(None)),
+            audio_recording: Arc::new(Mutex::new(None)),
+            channels: vec![],
+            channel_recordings: vec![],
+            active_channels: vec![],
+            mixer_gains: Arc::new(Mutex::new(vec![])),
+            vfo_mute: false,
         }
     }
 
     pub fn start(&mut self, frequency: f64) -> Result<()> {
-        let freq_offset = self.rate / 4.0;
+        self.start_at(frequency, None)
+    }
+
+    // Start with the hardware tuned to `center`, or to the frequency picked by the VFO
+    fn start_at(&mut self, frequency: f64, center: Option<f64>) -> Result<()> {
+        // File replaces the device and decides the sample rate
+        let file = match FileArgs::parse(&self.device.args) {
+            Some(args) => Some(file_source::open(&args?)?),
+            None => None,
+        };
+        // Rate of the file isn't a device setting, so it isn't saved
+        let rate = file.as_ref().map_or(self.device.rate, |(reader, _)| reader.lock().unwrap().info().sample_rate);
+
+        let freq_offset = rate / 4.0;
 
         let mut audio_rates = AudioSink::supported_sample_rates();
-        assert!(!audio_rates.is_empty());
-        audio_rates.sort_by_key(|a| std::cmp::Reverse(gcd(*a, self.rate as u32)));
+        if audio_rates.is_empty() {
+            bail!("Audio output supports no sample rate");
+        }
+        audio_rates.sort_by_key(|a| std::cmp::Reverse(gcd(*a, rate as u32)));
         let audio_rate = audio_rates[0];
         log::info!("Selected Audio Rate {audio_rate:?} from supported {audio_rates:?}");
 
-        // Create a new Seify SDR block with the given parameters
-        let src = self.seify(frequency + freq_offset).expect("Can't init Seify");
-
-        // Downsample before demodulation
-        // why do we need this?
+        // Downsample before demodulation. Channel rate is `audio_mult` times the audio rate, so the
+        // demodulator decimates to the audio by an integer. Five times carries the WFM stereo and RDS,
+        // at low sample rates the multiple is smaller so the channel fits into the band.
         let mut audio_mult = 5;
         while (audio_mult * audio_rate) as f64 > freq_offset + 100e3 {
             audio_mult -= 1;
         }
         log::info!("Audio Mult {audio_mult:?}");
 
-        let shift = self.shift(freq_offset);
-
         let interp = (audio_rate * audio_mult) as usize;
-        let decim = self.rate as usize;
+        let decim = rate as usize;
         log::info!("interp {interp}   decim {decim}");
         let resamp1 = FirBuilder::new_resampling::<Complex32, Complex32>(interp, decim);
 
-        let demod = self.fm_demodulation();
+        // Demodulator is shared, so the mode can be changed without restarting the source
+        let channel_rate = (audio_rate * audio_mult) as f32;
+        let audio_decim = audio_mult as usize;
+        let shared: SharedDemodulator = Arc::new(Mutex::new(self.mode.demodulator(channel_rate, audio_decim, &self.settings)));
+        let demod = DemodulatorBlock::new(shared.clone());
+
+        // Create the source so that the VFO is inside the band
+        let (src, vfo, mut source_block) = match file {
+            Some((reader, throttle)) => {
+                let center = reader.lock().unwrap().info().frequency;
+                let vfo = Vfo::with_center(center, frequency, rate, channel_rate as f64);
+                (FileSource::new(reader.clone(), throttle), vfo, SourceBlock::File { reader })
+            }
+            None => {
+                let vfo = match center {
+                    Some(center) => Vfo::with_center(center, frequency, rate, channel_rate as f64),
+                    None => Vfo::new(frequency, rate, channel_rate as f64),
+                };
+                let (src, device) = self.seify(self.device.corrected(vfo.center()))?;
+                // Save the port for tuning. Id is known when the block is in the flowgraph.
+                let Some(freq_port_id) = src.message_input_name_to_id("freq") else {
+                    bail!("Source has no frequency port");
+                };
+                (src, vfo, SourceBlock::Seify { id: 0, freq_port_id, device })
+            }
+        };
+        *self.vfo_offset.lock().unwrap() = vfo.offset();
+        let shift = Shift::new(self.vfo_offset.clone(), rate);
+
+        // Stereo `AudioSink` with the downsampled rate (sample_rate / (8*5) = 48_000)
+        let snk = AudioSink::new(audio_rate, 2);
 
-        let audio_filter = self.audio_filter(audio_rate, audio_mult);
 
-        // Single-channel `AudioSink` with the downsampled rate (sample_rate / (8*5) = 48_000)
-        let snk = AudioSink::new(audio_rate, 1);
-                
-        // Save ports for connectiong to the blocks
-        let freq_port_id = src.message_input_name_to_id("freq").unwrap();
+        // Audio of the VFO and the channels goes through the mixer
+        let mixer = Mixer::new(1 + self.channels.len(), self.mixer_gains.clone());
 
         // Create the `Flowgraph` and add `Block`s
         let mut fg = Flowgraph::new();
-        connect!(fg, src > shift > resamp1 > demod > audio_filter > snk.in;);
+        connect!(fg, src > shift > resamp1 > demod > mixer.in0; mixer > snk.in;);
+
+        // Spectrum of the whole band centered on the hardware frequency
+        let (spectrum_tx, spectrum_rx) = mpsc::sync_channel(4);
+        let window = spectrum::window(FFT_SIZE);
+        let frames_per_update = (rate as f32 / FFT_SIZE as f32 / UPDATE_RATE) as usize;
+        let averager = SpectrumAverager::new(FFT_SIZE, window.iter().sum(), 0.3, frames_per_update);
+        let mut pos = 0;
+        let apply_window = Apply::new(move |v: &Complex32| -> Complex32 {
+            let w = window[pos];
+            pos = (pos + 1) % FFT_SIZE;
+            v * w
+        });
+        let fft = Fft::with_options(FFT_SIZE, FftDirection::Forward, true, None);
+        let spectrum_sink = SpectrumSink::new(FFT_SIZE, averager, spectrum_tx);
+        connect!(fg, src > apply_window > fft > spectrum_sink);
+
+        // Recorders write only while there is a recording
+        let raw_recorder = IqRecorder::new(self.raw_recorder.clone());
+        let channel_recorder = IqRecorder::new(self.channel_recorder.clone());
+        connect!(fg, src > raw_recorder; resamp1 > channel_recorder);
+        let audio_recorder = AudioRecorder::new(self.audio_recording.clone(), shared.clone());
+        connect!(fg, demod > audio_recorder);
 
-        // !TEST
-        let mut buffer: AllocRingBuffer<f32> = AllocRingBuffer::new(1024);
-        let fft = Fft::new(1024);
-        let norm = Sink::new(move |v: &Complex32| buffer.push(v.norm()));
-        connect!(fg, shift > fft > norm);
-        // !TEST
+        // Every channel is shifted to zero and resampled like the VFO, then it has its own demodulator
+        let mut active_channels = vec![];
+        for (i, channel) in self.channels.iter().enumerate() {
+            let offset: SharedOffset = Arc::new(Mutex::new(channel.frequency as f64 - vfo.center()));
+            let ddc_shift = Shift::new(offset.clone(), rate);
+            let ddc_resamp = FirBuilder::new_resampling::<Complex32, Complex32>(interp, decim);
+            let settings = channel.demod_settings(self.settings.region);
+            let demodulator: SharedDemodulator = Arc::new(Mutex::new(channel.mode.demodulator(channel_rate, audio_decim, &settings)));
+            let ddc_demod = DemodulatorBlock::new(demodulator.clone());
+            let ddc_recorder = AudioRecorder::new(self.channel_recordings[i].clone(), demodulator.clone());
+            connect!(fg, src > ddc_shift > ddc_resamp > ddc_demod > ddc_recorder);
+            fg.connect_stream(ddc_demod, "out", mixer, &format!("in{}", i + 1))?;
+            active_channels.push(ActiveChannel { offset, demodulator });
+        }
+        self.active_channels = active_channels;
+        self.vfo = Some(vfo);
+        self.update_mixer();
 
         // Start the flowgraph and save the handle
-        let (_res, handle) = self.runtime.start_sync(fg);
-        let source_block = SourceBlock::Seify { id: src, freq_offset, freq_port_id };
+        let (task, handle) = self.runtime.start_sync(fg);
+        self.task = Some(Box::pin(task));
+        if let SourceBlock::Seify { id, .. } = &mut source_block {
+            *id = src;
+        }
         self.source = Some(source_block);
+        // Source block sets the overall gain when it starts, so the rest goes after it
+        if let Err(e) = self.apply_gain_settings() {
+            log::warn!("Can't set gain: {e}");
+        }
         self.handle = Some(handle);
-        
-        Ok(())
+        self.demodulator = Some(ActiveDemodulator { shared, channel_rate, audio_decim, audio_rate });
+        self.spectrum = Some(spectrum_rx);
 
+        // Blocks are initialized when the flowgraph starts. The one which failed ends it.
+        self.check()
     }
 
     pub fn stop(&mut self) -> Result<()> {
-        if let Some(handle) = self.handle.as_mut() {
+        // Flowgraph which ended by itself can't be terminated
+        if let (Some(handle), None) = (self.handle.as_mut(), Self::finished(&mut self.task)) {
             async_io::block_on(handle.terminate_and_wait())?;
         }
+        self.finish()
+    }
+
+    /// Stop the radio when the flowgraph ended by itself, because a block failed or the file
+    /// ended. The error of the flowgraph is returned.
+    pub fn check(&mut self) -> Result<()> {
+        match Self::finished(&mut self.task) {
+            Some(result) => {
+                self.finish()?;
+                result.map(|_| ())
+            }
+            None => Ok(()),
+        }
+    }
+
+    // Result of the flowgraph, when it ended
+    fn finished(task: &mut Option<FlowgraphTask<'_>>) -> Option<Result<Flowgraph>> {
+        let result = task.as_mut()?.now_or_never();
+        if result.is_some() {
+            *task = None;
+        }
+        result
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        // After the flowgraph, so that all samples are written
+        self.stop_recording()?;
+        self.stop_audio_recording()?;
+        for index in 0..self.channel_recordings.len() {
+            self.stop_channel_recording(index)?;
+        }
+        self.active_channels.clear();
         self.handle = None;
+        self.task = None;
+        self.source = None;
+        self.demodulator = None;
+        self.spectrum = None;
+        self.vfo = None;
+
+        Ok(())
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.handle.is_some()
+    }
+
+    pub fn device(&self) -> &DeviceSettings {
+        &self.device
+    }
 
+    pub fn capabilities(&self) -> Option<&Capabilities> {
+        self.capabilities.as_ref()
+    }
+
+    /// Switch to other device or change its settings. Running flowgraph is rebuilt.
+    pub fn set_device(&mut self, device: DeviceSettings) -> Result<()> {
+        let frequency = self.vfo.map(|vfo| vfo.frequency());
+        self.stop()?;
+        if device.args != self.device.args {
+            self.capabilities = Self::capabilities(&device.args);
+        }
+        self.device = device;
+        if let Some(frequency) = frequency {
+            self.start(frequency)?;
+        }
         Ok(())
     }
 
+    /// Sample rate of the source, which is the span of the spectrum
+    pub fn rate(&self) -> f64 {
+        match &self.source {
+            Some(SourceBlock::File { reader }) => reader.lock().unwrap().info().sample_rate,
+            _ => self.device.rate,
+        }
+    }
+
+    /// Check if the device can be tuned to the frequency
+    pub fn is_valid_frequency(&self, frequency: f64) -> bool {
+        if let (Some(SourceBlock::File { .. }), Some(vfo)) = (&self.source, &self.vfo) {
+            return vfo.contains(frequency);
+        }
+        match &self.capabilities {
+            Some(capabilities) => capabilities.frequency_range.contains(frequency),
+            None => frequency > 0.0,
+        }
+    }
+
+    /// Frequency of the VFO, when running
+    pub fn frequency(&self) -> Option<f64> {
+        self.vfo.map(|vfo| vfo.frequency())
+    }
+
+    /// Hardware frequency in the middle of the spectrum, when running
+    pub fn center_freq(&self) -> Option<f64> {
+        self.vfo.map(|vfo| vfo.center())
+    }
+
+    /// Spectra in dBFS received since the last call, the oldest first
+    pub fn spectrum(&self) -> Vec<Vec<f32>> {
+        self.spectrum.as_ref()
+            .map(|rx| rx.try_iter().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn settings(&self) -> DemodSettings {
+        self.settings
+    }
+
+    // Switch demodulator. The source keeps running.
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.agc_presets.insert(self.mode, self.settings.agc);
+        self.mode = mode;
+        self.settings.bandwidth = mode.bandwidth();
+        self.settings.agc = self.agc_presets.get(&mode).copied().unwrap_or(mode.agc());
+        self.update_demodulator();
+    }
+
+    pub fn is_stereo(&self) -> bool {
+        self.demodulator.as_ref()
+            .map(|d| d.shared.lock().unwrap().is_stereo())
+            .unwrap_or(false)
+    }
+
+    /// Data decoded from RDS in the WFM mode
+    pub fn rds(&self) -> Option<RdsInfo> {
+        self.demodulator.as_ref()
+            .and_then(|d| d.shared.lock().unwrap().rds())
+    }
+
+    /// State of the squelch, if it is enabled
+    pub fn squelch(&self) -> Option<SquelchState> {
+        self.demodulator.as_ref()
+            .and_then(|d| d.shared.lock().unwrap().squelch())
+    }
+
+    // Change the filters of the current mode
+    pub fn set_settings(&mut self, settings: DemodSettings) {
+        self.settings = settings;
+        self.update_demodulator();
+    }
+
+    fn update_demodulator(&self) {
+        if let Some(demodulator) = &self.demodulator {
+            log::info!("Mode: {}  settings: {:?}", self.mode.name(), self.settings);
+            let new_demod = self.mode.demodulator(demodulator.channel_rate, demodulator.audio_decim, &self.settings);
+            *demodulator.shared.lock().unwrap() = new_demod;
+        }
+    }
+
+    /// Move the VFO. The hardware is retuned only when the VFO leaves the usable band.
     pub fn tune_to(&mut self, new_freq: f64) -> Result<()> {
-        if let Some(handle) = &mut self.handle {
-            if let Some(source) = &self.source {
-                match source {
-                    SourceBlock::Seify { id, freq_offset, freq_port_id } => {
-                        log::info!("Tune to: {}", new_freq);
-                        async_io::block_on(handle.call(
-                            *id,
-                            *freq_port_id,
-                            Pmt::F64(new_freq + freq_offset),
-                        ))?;
-                    }
+        let Some(vfo) = &mut self.vfo else {
+            return Ok(());
+        };
+        if matches!(self.source, Some(SourceBlock::File { .. })) && !vfo.contains(new_freq) {
+            bail!("{new_freq} Hz is outside of the played file");
+        }
+        log::info!("Tune to: {}", new_freq);
+        let retune = vfo.tune(new_freq);
+        *self.vfo_offset.lock().unwrap() = vfo.offset();
+        if let Some(writer) = self.channel_recorder.lock().unwrap().as_mut() {
+            writer.retune(new_freq)?;
+        }
+        if let Some(recording) = self.audio_recording.lock().unwrap().as_mut() {
+            recording.retune(new_freq)?;
+        }
+        match retune {
+            Some(center) => {
+                if let Some(writer) = self.raw_recorder.lock().unwrap().as_mut() {
+                    writer.retune(center)?;
+                }
+                // Channels stay where they are, so they move in the new band
+                for (channel, active) in self.channels.iter().zip(&self.active_channels) {
+                    *active.offset.lock().unwrap() = channel.frequency as f64 - center;
                 }
+                self.update_mixer();
+                self.tune_hardware(center)
             }
+            None => Ok(()),
+        }
+    }
+
+    /// Position and length in seconds of the played file
+    pub fn playback(&self) -> Option<(f64, f64)> {
+        let Some(SourceBlock::File { reader }) = &self.source else {
+            return None;
+        };
+        let reader = reader.lock().unwrap();
+        let rate = reader.info().sample_rate;
+        Some((reader.position() as f64 / rate, reader.info().duration()))
+    }
+
+    /// Continue playing the file from the position in seconds
+    pub fn seek(&self, seconds: f64) -> Result<()> {
+        if let Some(SourceBlock::File { reader }) = &self.source {
+            let mut reader = reader.lock().unwrap();
+            let sample = (seconds.max(0.0) * reader.info().sample_rate) as u64;
+            reader.seek(sample)?;
         }
         Ok(())
     }
 
-    // Build Seify block.
-    fn seify(&self, frequency: f64) -> Result<Block> {
-        SourceBuilder::new()
-            .args(&self.args)?
-            .frequency(frequency)
-            .sample_rate(self.rate)
-            .gain(self.gain)
-            .build()
-    } 
-
-    // Shift signal by a given offset
-    fn shift(&self, freq_offset: f64) -> Block {
-        let mut last = Complex32::new(1.0, 0.0);
-        let add = Complex32::from_polar(
-            1.0,
-            (2.0 * std::f64::consts::PI * freq_offset / self.rate) as f32,
-        );
-        let shift = Apply::new(move |v: &Complex32| -> Complex32 {
-            last *= add;
-            last * v
-        });
-        
-        shift
-    }
-
-    // Demodulation block using the conjugate delay method
-    // See https://en.wikipedia.org/wiki/Detector_(radio)#Quadrature_detector
-    fn fm_demodulation(&self) -> Block {
-        let mut last = Complex32::new(0.0, 0.0); // store sample x[n-1]
-        let demod = Apply::new(move |v: &Complex32| -> f32 {
-            let arg = (v * last.conj()).arg(); // Obtain phase of x[n] * conj(x[n-1])
-            last = *v;
-            arg
+    /// Start recording IQ samples to a new SigMF file in the directory.
+    /// Returns the path of the recording without the extensions.
+    pub fn start_recording(&mut self, directory: &Path, format: SampleFormat, source: RecordSource) -> Result<PathBuf> {
+        let (Some(vfo), Some(channel_rate)) = (self.vfo, self.demodulator.as_ref().map(|d| d.channel_rate)) else {
+            bail!("Radio is not running");
+        };
+        self.stop_recording()?;
+
+        let (frequency, sample_rate, recorder) = match source {
+            RecordSource::Raw => (vfo.center(), self.rate(), &self.raw_recorder),
+            RecordSource::Channel => (vfo.frequency(), channel_rate as f64, &self.channel_recorder),
+        };
+        let description = if self.device.agc {
+            "Hardware AGC".to_string()
+        } else {
+            let elements: Vec<String> = self.device.gain_elements.iter()
+                .map(|(name, gain)| format!("{name} {gain} dB"))
+                .collect();
+            format!("Gain {} dB {}", self.device.gain, elements.join(" ")).trim_end().to_string()
+        };
+        let recording = Recording {
+            sample_rate,
+            hw: self.device.args.clone(),
+            description,
+            recorder: "yasa".to_string(),
+        };
+        let path = directory.join(sigmf::file_name("yasa", SystemTime::now(), frequency));
+        log::info!("Record {:?} IQ to {}", source, path.display());
+        let writer = SigmfWriter::create(&path, format, recording, frequency)?;
+        *recorder.lock().unwrap() = Some(writer);
+        Ok(path)
+    }
+
+    /// Finish the IQ recording, if there is one
+    pub fn stop_recording(&mut self) -> Result<()> {
+        for recorder in [&self.raw_recorder, &self.channel_recorder] {
+            if let Some(writer) = recorder.lock().unwrap().take() {
+                writer.finish()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Start recording the demodulated audio to the directory.
+    /// With `squelch_triggered` every transmission goes to its own file.
+    pub fn start_audio_recording(&mut self, directory: &Path, format: AudioFormat, squelch_triggered: bool) -> Result<()> {
+        let (Some(vfo), Some(audio_rate)) = (self.vfo, self.demodulator.as_ref().map(|d| d.audio_rate)) else {
+            bail!("Radio is not running");
+        };
+        self.stop_audio_recording()?;
+        log::info!("Record audio to {}", directory.display());
+        // Demodulator block always sends stereo
+        let recording = AudioRecording::new(directory, format, audio_rate, 2, vfo.frequency(), squelch_triggered);
+        *self.audio_recording.lock().unwrap() = Some(recording);
+        Ok(())
+    }
+
+    /// Finish the audio recording, if there is one
+    pub fn stop_audio_recording(&mut self) -> Result<()> {
+        if let Some(recording) = self.audio_recording.lock().unwrap().take() {
+            recording.finish()?;
+        }
+        Ok(())
+    }
+
+    /// Progress of the audio recording, none when not recording
+    pub fn audio_recording(&self) -> Option<AudioRecordingStatus> {
+        Self::audio_status(&self.audio_recording)
+    }
+
+    /// Channels demodulated at the same time as the VFO
+    pub fn channels(&self) -> &[Channel] {
+        &self.channels
+    }
+
+    /// Add the channel in the received band. Running flowgraph is rebuilt, the recordings continue
+    /// after a short gap.
+    pub fn add_channel(&mut self, channel: Channel) -> Result<()> {
+        if self.vfo.is_some_and(|vfo| !vfo.contains(channel.frequency as f64)) {
+            bail!("{} Hz is outside of the received band", channel.frequency);
+        }
+        self.channels.push(channel);
+        self.channel_recordings.push(Arc::new(Mutex::new(None)));
+        self.restart()
+    }
+
+    /// Remove the channel and finish its recording. Running flowgraph is rebuilt, the other
+    /// recordings continue after a short gap.
+    pub fn remove_channel(&mut self, index: usize) -> Result<()> {
+        self.channels.remove(index);
+        let recording = self.channel_recordings.remove(index);
+        let result = self.restart();
+        // After the old flowgraph, so that all samples are written
+        let removed = recording.lock().unwrap().take();
+        if let Some(removed) = removed {
+            removed.finish()?;
+        }
+        result
+    }
+
+    /// Change the frequency, demodulator, mute or solo of the channel while it runs
+    pub fn set_channel(&mut self, index: usize, channel: Channel) -> Result<()> {
+        let Some(vfo) = self.vfo else {
+            self.channels[index] = channel;
+            return Ok(());
+        };
+        if !vfo.contains(channel.frequency as f64) {
+            bail!("{} Hz is outside of the received band", channel.frequency);
+        }
+        let old = std::mem::replace(&mut self.channels[index], channel.clone());
+        if let (Some(active), Some(demodulator)) = (self.active_channels.get(index), &self.demodulator) {
+            if channel.frequency != old.frequency {
+                *active.offset.lock().unwrap() = channel.frequency as f64 - vfo.center();
+                if let Some(recording) = self.channel_recordings[index].lock().unwrap().as_mut() {
+                    recording.retune(channel.frequency as f64)?;
+                }
+            }
+            if (channel.mode, channel.bandwidth, channel.squelch) != (old.mode, old.bandwidth, old.squelch) {
+                let settings = channel.demod_settings(self.settings.region);
+                let new_demod = channel.mode.demodulator(demodulator.channel_rate, demodulator.audio_decim, &settings);
+                *active.demodulator.lock().unwrap() = new_demod;
+            }
+        }
+        self.update_mixer();
+        Ok(())
+    }
+
+    pub fn channel_status(&self, index: usize) -> ChannelStatus {
+        let active = self.active_channels.get(index);
+        let frequency = self.channels[index].frequency as f64;
+        ChannelStatus {
+            squelch: active.and_then(|a| a.demodulator.lock().unwrap().squelch()),
+            recording: Self::audio_status(&self.channel_recordings[index]),
+            in_band: self.vfo.map(|vfo| vfo.contains(frequency)).unwrap_or(true),
+        }
+    }
+
+    /// Record the audio of the channel to the directory, like the audio of the VFO
+    pub fn start_channel_recording(&mut self, index: usize, directory: &Path, format: AudioFormat, squelch_triggered: bool) -> Result<()> {
+        let Some(audio_rate) = self.demodulator.as_ref().map(|d| d.audio_rate) else {
+            bail!("Radio is not running");
+        };
+        let frequency = self.channels[index].frequency as f64;
+        log::info!("Record audio of {frequency} Hz to {}", directory.display());
+        let recording = AudioRecording::new(directory, format, audio_rate, 2, frequency, squelch_triggered);
+        if let Some(old) = self.channel_recordings[index].lock().unwrap().replace(recording) {
+            old.finish()?;
+        }
+        Ok(())
+    }
+
+    /// Finish the audio recording of the channel, if there is one
+    pub fn stop_channel_recording(&mut self, index: usize) -> Result<()> {
+        let recording = self.channel_recordings[index].lock().unwrap().take();
+        if let Some(recording) = recording {
+            recording.finish()?;
+        }
+        Ok(())
+    }
+
+    /// Silence the VFO, e.g. to listen only to the channels
+    pub fn set_vfo_mute(&mut self, mute: bool) {
+        self.vfo_mute = mute;
+        self.update_mixer();
+    }
+
+    pub fn vfo_mute(&self) -> bool {
+        self.vfo_mute
+    }
+
+    // Gains by the mute and solo. Channels outside of the band are silent.
+    fn update_mixer(&self) {
+        let vfo = self.vfo;
+        let gains = mixer_gains(self.vfo_mute, &self.channels, |c| vfo.is_some_and(|vfo| vfo.contains(c.frequency as f64)));
+        *self.mixer_gains.lock().unwrap() = gains;
+    }
+
+    // Rebuild the running flowgraph for the new list of channels. The band, the position in
+    // the played file and the recordings stay.
+    fn restart(&mut self) -> Result<()> {
+        let center = self.vfo.map(|vfo| vfo.center());
+        let position = self.playback().map(|(seconds, _)| seconds);
+        let (Some(frequency), Some(handle)) = (self.frequency(), self.handle.as_mut()) else {
+            return Ok(());
+        };
+        if Self::finished(&mut self.task).is_none() {
+            async_io::block_on(handle.terminate_and_wait())?;
+        }
+        self.handle = None;
+        self.task = None;
+
+        let result = self.start_at(frequency, center).and_then(|_| match position {
+            Some(seconds) => self.seek(seconds),
+            None => Ok(()),
         });
+        if result.is_err() {
+            self.finish()?;
+        }
+        result
+    }
 
-        demod
+    fn audio_status(recording: &SharedAudioRecording) -> Option<AudioRecordingStatus> {
+        recording.lock().unwrap().as_ref().map(|recording| AudioRecordingStatus {
+            path: recording.current().map(|writer| writer.path().to_path_buf()),
+            seconds: recording.current().map_or(0.0, |writer| writer.seconds()),
+            files: recording.files(),
+        })
     }
 
-    // Design filter for the audio and decimate by 5.
-    // Ideally, this should be a FM de-emphasis filter, but the following works.
-    fn audio_filter(&self, audio_rate: u32, audio_mult: u32) -> Block {
-        let cutoff = 2_000.0 / (audio_rate * audio_mult) as f64;
-        let transition = 10_000.0 / (audio_rate * audio_mult) as f64;
-        log::info!("cutoff {cutoff}   transition {transition}");
-        let audio_filter_taps = firdes::kaiser::lowpass::<f32>(cutoff, transition, 0.1);
-        let resamp = FirBuilder::new_resampling_with_taps::<f32, f32, _, _>(
-            1,
-            audio_mult as usize,
-            audio_filter_taps,
-        );
+    /// Progress of the IQ recording, none when not recording
+    pub fn recording(&self) -> Option<RecordingStatus> {
+        [&self.raw_recorder, &self.channel_recorder].into_iter()
+            .find_map(|recorder| recorder.lock().unwrap().as_ref().map(|writer| RecordingStatus {
+                path: writer.path().to_path_buf(),
+                seconds: writer.samples() as f64 / writer.sample_rate(),
+                size: writer.size(),
+            }))
+    }
+
+    /// Overall gain in dB. It replaces the gain of the individual stages.
+    pub fn set_gain(&mut self, gain: f64) -> Result<()> {
+        self.device.gain = gain;
+        self.device.gain_elements.clear();
+        self.apply_gain_settings()
+    }
 
-        resamp
+    /// Gain of the stage in dB
+    pub fn set_gain_element(&mut self, name: &str, gain: f64) -> Result<()> {
+        self.device.gain_elements.insert(name.to_string(), gain);
+        if let Some(SourceBlock::Seify { device, .. }) = &self.source {
+            device.set_gain_element(Direction::Rx, 0, name, gain)?;
+        }
+        Ok(())
+    }
+
+    /// Current gain of the stage in dB
+    pub fn gain_element(&self, name: &str) -> Option<f64> {
+        match &self.source {
+            Some(SourceBlock::Seify { device, .. }) => device.gain_element(Direction::Rx, 0, name).ok().flatten(),
+            _ => self.device.gain_elements.get(name).copied(),
+        }
+    }
+
+    /// Enable the hardware AGC. The manual gain is restored when it is disabled.
+    pub fn set_agc(&mut self, agc: bool) -> Result<()> {
+        self.device.agc = agc;
+        self.apply_gain_settings()
+    }
+
+    /// Frequency correction in ppm
+    pub fn set_ppm(&mut self, ppm: f64) -> Result<()> {
+        self.device.ppm = ppm;
+        match self.vfo {
+            Some(vfo) => self.tune_hardware(vfo.center()),
+            None => Ok(()),
+        }
+    }
+
+    // Send the corrected frequency to the source
+    fn tune_hardware(&mut self, center: f64) -> Result<()> {
+        let frequency = self.device.corrected(center);
+        if let (Some(handle), Some(SourceBlock::Seify { id, freq_port_id, .. })) = (&mut self.handle, &self.source) {
+            log::info!("Retune hardware to: {}", frequency);
+            async_io::block_on(handle.call(
+                *id,
+                *freq_port_id,
+                Pmt::F64(frequency),
+            ))?;
+        }
+        Ok(())
+    }
+
+    // AGC and the gain of the stages, which the source block doesn't set
+    fn apply_gain_settings(&mut self) -> Result<()> {
+        let Some(SourceBlock::Seify { device, .. }) = &self.source else {
+            return Ok(());
+        };
+        if device.supports_agc(Direction::Rx, 0)? {
+            device.enable_agc(Direction::Rx, 0, self.device.agc)?;
+        }
+        if !self.device.agc {
+            device.set_gain(Direction::Rx, 0, self.device.gain)?;
+            for (name, gain) in &self.device.gain_elements {
+                device.set_gain_element(Direction::Rx, 0, name, *gain)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Ask the device what it supports. Files have no capabilities.
+    fn capabilities(args: &str) -> Option<Capabilities> {
+        if FileArgs::parse(args).is_some() {
+            return None;
+        }
+        match Capabilities::query(args) {
+            Ok(capabilities) => {
+                log::info!("Device capabilities: {capabilities:?}");
+                Some(capabilities)
+            }
+            Err(e) => {
+                log::warn!("Can't query device: {e}");
+                None
+            }
+        }
+    }
+
+    // Build Seify block. The device is returned for the settings without the message port.
+    fn seify(&self, frequency: f64) -> Result<(Block, Device<GenericDevice>)> {
+        let device = Device::from_args(self.device.args.as_str())?;
+        let mut builder = SourceBuilder::new()
+            .device(device.clone())
+            .frequency(frequency)
+            .sample_rate(self.device.rate)
+            .gain(self.device.gain);
+        if let Some(antenna) = &self.device.antenna {
+            builder = builder.antenna(antenna);
+        }
+        Ok((builder.build()?, device))
     }
 }
\ No newline at end of file
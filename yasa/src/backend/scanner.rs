@@ -0,0 +1,406 @@
+//! Scanner which steps through the channels until it finds a signal.
+//!
+//! The scanner only decides where to tune. It is driven by the time and the state of the squelch,
+//! so it works the same with the device, a played file or a synthetic signal.
+//! It listens on every channel for the dwell time, stays while the squelch is open and moves on
+//! when the squelch has been closed for the hang time. Priority channels are checked between
+//! the other channels at the priority interval, locked out channels are skipped.
+
+use futuresdr::anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+
+// Squelch needs some time to measure the new channel, until then it may still show the old one
+const SETTLE_TIME: f64 = 0.1;
+// Range with more channels is most likely a wrong step
+const MAX_CHANNELS: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanSettings {
+    /// How long to listen on every channel in seconds
+    pub dwell: f64,
+    /// How long to wait after the squelch closed in seconds
+    pub hang: f64,
+    /// How often the priority channels are checked in seconds
+    pub priority_interval: f64,
+    /// Frequencies skipped by the scanner
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub lockout: Vec<u64>,
+    /// Frequencies checked at the priority interval
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub priority: Vec<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Channel {
+    pub frequency: u64,
+    pub priority: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanState {
+    /// Listening on the channel for the dwell time
+    Scanning,
+    /// Squelch is open
+    Receiving,
+    /// Squelch closed, waiting for the hang time
+    Hang,
+    /// Every channel is locked out
+    Idle,
+}
+
+pub struct Scanner {
+    channels: Vec<Channel>,
+    settings: ScanSettings,
+    // Index of the current channel
+    current: Option<usize>,
+    // Indexes where the search for the next channel starts
+    next: usize,
+    next_priority: usize,
+    // Current channel was visited as the priority channel
+    is_priority_visit: bool,
+    state: ScanState,
+    // Time when the state was entered
+    since: f64,
+    last_priority: f64,
+    // Leave the current channel on the next update
+    skip: bool,
+}
+
+impl Default for ScanSettings {
+    fn default() -> Self {
+        Self {
+            dwell: 0.3,
+            hang: 2.0,
+            priority_interval: 5.0,
+            lockout: vec![],
+            priority: vec![],
+        }
+    }
+}
+
+impl Channel {
+    pub fn new(frequency: u64) -> Self {
+        Self { frequency, priority: false }
+    }
+}
+
+impl ScanState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScanState::Scanning => "Scanning",
+            ScanState::Receiving => "Receiving",
+            ScanState::Hang => "Waiting",
+            ScanState::Idle => "All channels locked out",
+        }
+    }
+}
+
+/// Channels from `start` to `stop` including both, `step` Hz apart
+pub fn range(start: u64, stop: u64, step: u64) -> Result<Vec<Channel>> {
+    if step == 0 {
+        bail!("Step must be greater than zero");
+    }
+    if stop < start {
+        bail!("Stop frequency is below the start");
+    }
+    let count = (stop - start) / step + 1;
+    if count > MAX_CHANNELS as u64 {
+        bail!("Too many channels ({count}), the limit is {MAX_CHANNELS}");
+    }
+    Ok((start..=stop).step_by(step as usize).map(Channel::new).collect())
+}
+
+impl Scanner {
+    /// Scan the channels. Frequencies in the priority list of the settings become priority channels.
+    pub fn new(mut channels: Vec<Channel>, settings: ScanSettings) -> Self {
+        for channel in &mut channels {
+            channel.priority |= settings.priority.contains(&channel.frequency);
+        }
+        Self {
+            channels,
+            settings,
+            current: None,
+            next: 0,
+            next_priority: 0,
+            is_priority_visit: false,
+            state: ScanState::Scanning,
+            since: 0.0,
+            // Priority channels are checked first
+            last_priority: f64::NEG_INFINITY,
+            skip: false,
+        }
+    }
+
+    /// Advance the scanner to the time `now` in seconds. Returns the frequency to tune to.
+    pub fn update(&mut self, now: f64, squelch_open: bool) -> Option<u64> {
+        if self.current.is_none() || self.skip {
+            self.skip = false;
+            return self.advance(now);
+        }
+        let elapsed = now - self.since;
+        match self.state {
+            ScanState::Scanning if squelch_open && elapsed >= SETTLE_TIME => {
+                self.enter(ScanState::Receiving, now);
+            }
+            ScanState::Scanning if elapsed >= self.settings.dwell.max(SETTLE_TIME) => return self.advance(now),
+            ScanState::Receiving if !squelch_open => self.enter(ScanState::Hang, now),
+            ScanState::Hang if squelch_open => self.enter(ScanState::Receiving, now),
+            ScanState::Hang if elapsed >= self.settings.hang => return self.advance(now),
+            _ => {}
+        }
+        None
+    }
+
+    pub fn state(&self) -> ScanState {
+        self.state
+    }
+
+    /// Frequency of the current channel
+    pub fn current(&self) -> Option<u64> {
+        self.current.map(|i| self.channels[i].frequency)
+    }
+
+    pub fn channels(&self) -> &[Channel] {
+        &self.channels
+    }
+
+    pub fn settings(&self) -> &ScanSettings {
+        &self.settings
+    }
+
+    /// Change the times and the lockouts while scanning
+    pub fn set_settings(&mut self, settings: ScanSettings) {
+        self.settings = settings;
+        if self.current().is_some_and(|f| self.is_locked(f)) {
+            self.skip();
+        }
+    }
+
+    /// Leave the current channel, even if the squelch is open
+    pub fn skip(&mut self) {
+        self.skip = true;
+    }
+
+    pub fn is_locked(&self, frequency: u64) -> bool {
+        self.settings.lockout.contains(&frequency)
+    }
+
+    /// Skip the frequency from now on
+    pub fn lockout(&mut self, frequency: u64) {
+        if !self.is_locked(frequency) {
+            self.settings.lockout.push(frequency);
+            self.settings.lockout.sort();
+        }
+        if self.current() == Some(frequency) {
+            self.skip();
+        }
+    }
+
+    pub fn is_priority(&self, frequency: u64) -> bool {
+        self.channels.iter().any(|c| c.frequency == frequency && c.priority)
+    }
+
+    /// Mark the channels of the frequency as priority ones or the regular ones
+    pub fn set_priority(&mut self, frequency: u64, priority: bool) {
+        for channel in self.channels.iter_mut().filter(|c| c.frequency == frequency) {
+            channel.priority = priority;
+        }
+        self.settings.priority.retain(|&f| f != frequency);
+        if priority {
+            self.settings.priority.push(frequency);
+            self.settings.priority.sort();
+        }
+    }
+
+    // Tune to the next channel which isn't locked out
+    fn advance(&mut self, now: f64) -> Option<u64> {
+        let n = self.channels.len();
+        let mut index = None;
+        // Regular channel always follows the priority one, so both get their time
+        let priority_due = now - self.last_priority >= self.settings.priority_interval;
+        if priority_due && !self.is_priority_visit {
+            index = self.find(self.next_priority, |c| c.priority);
+            if let Some(i) = index {
+                self.next_priority = (i + 1) % n;
+                self.last_priority = now;
+            }
+        }
+        self.is_priority_visit = index.is_some();
+        if index.is_none() {
+            index = self.find(self.next, |_| true);
+            if let Some(i) = index {
+                self.next = (i + 1) % n;
+            }
+        }
+
+        self.current = index;
+        match index {
+            Some(i) => {
+                self.enter(ScanState::Scanning, now);
+                Some(self.channels[i].frequency)
+            }
+            None => {
+                self.enter(ScanState::Idle, now);
+                None
+            }
+        }
+    }
+
+    // First channel from `start` on which isn't locked out
+    fn find(&self, start: usize, filter: impl Fn(&Channel) -> bool) -> Option<usize> {
+        let n = self.channels.len();
+        (0..n)
+            .map(|k| (start + k) % n)
+            .find(|&i| filter(&self.channels[i]) && !self.is_locked(self.channels[i].frequency))
+    }
+
+    fn enter(&mut self, state: ScanState, now: f64) {
+        self.state = state;
+        self.since = now;
+    }
+}
+
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::demod::{DemodSettings, Mode, SquelchMode};
+    use futuresdr::num_complex::Complex32;
+    use std::ops::Range;
+
+    fn settings(dwell: f64, hang: f64) -> ScanSettings {
+        ScanSettings { dwell, hang, priority_interval: 100.0, ..Default::default() }
+    }
+
+    fn channels(frequencies: &[u64]) -> Vec<Channel> {
+        frequencies.iter().map(|&f| Channel::new(f)).collect()
+    }
+
+    // Run the scanner in steps of 0.1 s and record where it tuned
+    fn run(scanner: &mut Scanner, steps: Range<usize>, signal: impl Fn(u64, f64) -> bool) -> Vec<u64> {
+        let mut tuned = vec![];
+        for k in steps {
+            let now = k as f64 * 0.1;
+            let open = scanner.current().is_some_and(|f| signal(f, now));
+            if let Some(f) = scanner.update(now, open) {
+                tuned.push(f);
+            }
+        }
+        tuned
+    }
+
+    #[test]
+    fn test_range() {
+        let frequencies: Vec<u64> = range(100, 130, 10).unwrap().iter().map(|c| c.frequency).collect();
+        assert_eq!(frequencies, vec![100, 110, 120, 130]);
+        assert_eq!(range(100, 125, 10).unwrap().len(), 3);
+        assert!(range(100, 90, 10).is_err());
+        assert!(range(100, 200, 0).is_err());
+        assert!(range(0, 1_000_000, 1).is_err());
+    }
+
+    #[test]
+    fn test_dwell() {
+        let mut scanner = Scanner::new(channels(&[1, 2, 3]), settings(0.25, 1.0));
+        let tuned = run(&mut scanner, 0..10, |_, _| false);
+        assert_eq!(tuned, vec![1, 2, 3, 1]);
+        assert_eq!(scanner.state(), ScanState::Scanning);
+    }
+
+    #[test]
+    fn test_stop_and_hang() {
+        let mut scanner = Scanner::new(channels(&[1, 2, 3]), settings(0.25, 0.95));
+        // Signal on the second channel until 1 s, with a short break
+        let signal = |f, t: f64| f == 2 && t < 0.95 && !(0.55..0.75).contains(&t);
+        let tuned = run(&mut scanner, 0..12, signal);
+        assert_eq!(tuned, vec![1, 2]);
+        assert_eq!(scanner.state(), ScanState::Hang);
+        // Scanning goes on after the hang time
+        let tuned = run(&mut scanner, 12..21, signal);
+        assert_eq!(tuned, vec![3]);
+    }
+
+    #[test]
+    fn test_settle() {
+        // Squelch still open from the previous channel right after the retune
+        let mut scanner = Scanner::new(channels(&[1, 2]), settings(0.25, 1.0));
+        assert_eq!(scanner.update(0.0, false), Some(1));
+        assert_eq!(scanner.update(0.05, true), None);
+        assert_eq!(scanner.state(), ScanState::Scanning);
+        assert_eq!(scanner.update(0.3, false), Some(2));
+    }
+
+    #[test]
+    fn test_lockout() {
+        let mut scanner = Scanner::new(channels(&[1, 2, 3]), settings(0.25, 1.0));
+        scanner.lockout(2);
+        assert_eq!(run(&mut scanner, 0..10, |_, _| false), vec![1, 3, 1, 3]);
+
+        // Locking the current channel leaves it at once
+        let mut scanner = Scanner::new(channels(&[1, 2, 3]), settings(0.25, 1.0));
+        assert_eq!(scanner.update(0.0, false), Some(1));
+        assert_eq!(scanner.update(0.2, true), None);
+        scanner.lockout(1);
+        assert_eq!(scanner.update(0.3, true), Some(2));
+
+        for f in [1, 2, 3] {
+            scanner.lockout(f);
+        }
+        assert_eq!(scanner.update(0.4, false), None);
+        assert_eq!(scanner.state(), ScanState::Idle);
+        scanner.set_settings(ScanSettings { lockout: vec![1, 2], ..scanner.settings().clone() });
+        assert_eq!(scanner.update(0.5, false), Some(3));
+    }
+
+    #[test]
+    fn test_priority() {
+        let settings = ScanSettings { priority_interval: 0.5, priority: vec![4], ..settings(0.25, 1.0) };
+        let mut scanner = Scanner::new(channels(&[1, 2, 3, 4]), settings);
+        let tuned = run(&mut scanner, 0..16, |_, _| false);
+        // Priority channel first and then between the other channels
+        assert_eq!(tuned, vec![4, 1, 4, 2, 4, 3]);
+
+        scanner.set_priority(4, false);
+        scanner.set_priority(2, true);
+        assert!(scanner.is_priority(2) && !scanner.is_priority(4));
+        assert_eq!(scanner.settings().priority, vec![2]);
+    }
+
+    #[test]
+    fn test_synthetic_source() {
+        // Carrier at 103 kHz is received only by the channel tuned to it
+        let rate = 96_000.0;
+        let carrier = 103_000.0;
+        let mut settings = DemodSettings::new(Mode::Nfm);
+        settings.squelch.mode = SquelchMode::Power;
+        settings.squelch.power_threshold = -30.0;
+        let mut scanner = Scanner::new(channels(&[80_000, 90_000, 103_000]), self::settings(0.25, 0.5));
+        let mut demod = Mode::Nfm.demodulator(rate, 1, &settings);
+
+        let mut tuned = vec![];
+        let mut audio = vec![];
+        for k in 0..100 {
+            let now = k as f64 * 0.01;
+            let open = demod.squelch().is_some_and(|s| s.open);
+            if let Some(f) = scanner.update(now, open) {
+                tuned.push(f);
+            }
+            // Channel IQ of the current frequency
+            let offset = carrier - scanner.current().unwrap() as f64;
+            let block: Vec<Complex32> = (0..960)
+                .map(|i| {
+                    let t = (k * 960 + i) as f64 / rate as f64;
+                    Complex32::from_polar(0.5, (2.0 * std::f64::consts::PI * offset * t) as f32)
+                })
+                .collect();
+            demod.process(&block, &mut audio);
+        }
+        assert_eq!(tuned, vec![80_000, 90_000, 103_000]);
+        assert_eq!(scanner.state(), ScanState::Receiving);
+    }
+}
@@ -0,0 +1,109 @@
+//! Power spectrum of the wideband IQ for the panadapter.
+//!
+//! IQ is windowed and transformed by the FFT block in the flowgraph. Power of the FFT frames
+//! is averaged here and a new spectrum in dBFS is sent to the UI a few times per second.
+
+use std::f32::consts::PI;
+
+use futuresdr::num_complex::Complex32;
+
+
+/// Number of the frequency bins
+pub const FFT_SIZE: usize = 2048;
+/// Number of the spectrum updates per second
+pub const UPDATE_RATE: f32 = 25.0;
+
+/// Blackman-Harris window, which keeps the leakage below the noise floor
+pub fn window(size: usize) -> Vec<f32> {
+    let m = size as f32;
+    (0..size)
+        .map(|i| {
+            let x = 2.0 * PI * i as f32 / m;
+            0.35875 - 0.48829 * x.cos() + 0.14128 * (2.0 * x).cos() - 0.01168 * (3.0 * x).cos()
+        })
+        .collect()
+}
+
+/// Exponential average of the FFT frames
+pub struct SpectrumAverager {
+    power: Vec<f32>,
+    alpha: f32,
+    // Power of the full scale sine after the window and FFT
+    scale: f32,
+    frames: usize,
+    frames_per_update: usize,
+}
+
+impl SpectrumAverager {
+    /// `window_sum` is the sum of the window coefficients.
+    /// `alpha` is the weight of the new frame and every `frames_per_update` frame gives the spectrum.
+    pub fn new(size: usize, window_sum: f32, alpha: f32, frames_per_update: usize) -> Self {
+        Self {
+            power: vec![0.0; size],
+            alpha,
+            scale: window_sum * window_sum,
+            frames: 0,
+            frames_per_update: frames_per_update.max(1),
+        }
+    }
+
+    /// Add the FFT frame. Returns the averaged spectrum in dBFS when the update is due.
+    pub fn push_frame(&mut self, frame: &[Complex32]) -> Option<Vec<f32>> {
+        for (p, x) in self.power.iter_mut().zip(frame) {
+            *p += self.alpha * (x.norm_sqr() / self.scale - *p);
+        }
+        self.frames += 1;
+        if self.frames < self.frames_per_update {
+            return None;
+        }
+        self.frames = 0;
+        Some(self.power.iter().map(|p| 10.0 * p.max(1e-20).log10()).collect())
+    }
+}
+
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Windowed DFT with the zero frequency in the middle, like the FFT block with the shift
+    fn spectrum(x: &[Complex32], window: &[f32]) -> Vec<Complex32> {
+        let n = x.len();
+        (0..n)
+            .map(|k| {
+                let freq = k as f32 - (n / 2) as f32;
+                x.iter()
+                    .zip(window)
+                    .enumerate()
+                    .map(|(i, (&v, &w))| v * w * Complex32::from_polar(1.0, -2.0 * PI * freq * i as f32 / n as f32))
+                    .sum()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_full_scale_tone() {
+        let n = 64;
+        let window = window(n);
+        let tone: Vec<Complex32> = (0..n).map(|i| Complex32::from_polar(1.0, 2.0 * PI * 8.0 * i as f32 / n as f32)).collect();
+        let mut averager = SpectrumAverager::new(n, window.iter().sum(), 1.0, 1);
+        let db = averager.push_frame(&spectrum(&tone, &window)).unwrap();
+
+        assert!(db[n / 2 + 8].abs() < 0.01);
+        assert!(db[n / 2] < -90.0);
+        assert!(db[n / 2 - 8] < -90.0);
+    }
+
+    #[test]
+    fn test_average() {
+        let mut averager = SpectrumAverager::new(2, 1.0, 0.5, 2);
+        assert_eq!(averager.push_frame(&[Complex32::new(1.0, 0.0); 2]), None);
+        let db = averager.push_frame(&[Complex32::new(1.0, 0.0); 2]).unwrap();
+
+        // 0.5 + 0.25 of the full power
+        assert!((db[0] - 10.0 * 0.75f32.log10()).abs() < 1e-4);
+    }
+}
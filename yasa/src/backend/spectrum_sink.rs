@@ -0,0 +1,74 @@
+use std::sync::mpsc::SyncSender;
+
+use futuresdr::anyhow::Result;
+use futuresdr::macros::async_trait;
+use futuresdr::num_complex::Complex32;
+use futuresdr::runtime::BlockMeta;
+use futuresdr::runtime::BlockMetaBuilder;
+use futuresdr::runtime::Kernel;
+use futuresdr::runtime::MessageIo;
+use futuresdr::runtime::MessageIoBuilder;
+use futuresdr::runtime::StreamIo;
+use futuresdr::runtime::StreamIoBuilder;
+use futuresdr::runtime::WorkIo;
+use futuresdr::runtime::{Block, TypedBlock};
+
+use super::spectrum::SpectrumAverager;
+
+/// Average FFT frames and send the spectrum to the UI.
+///
+/// Spectrum is dropped when the UI doesn't keep up, so the flowgraph is never blocked.
+///
+/// # Inputs
+///
+/// `in`: FFT frames
+pub struct SpectrumSink {
+    size: usize,
+    averager: SpectrumAverager,
+    sender: SyncSender<Vec<f32>>,
+}
+
+impl SpectrumSink {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(size: usize, averager: SpectrumAverager, sender: SyncSender<Vec<f32>>) -> Block {
+        Block::from_typed(Self::new_typed(size, averager, sender))
+    }
+
+    pub fn new_typed(size: usize, averager: SpectrumAverager, sender: SyncSender<Vec<f32>>) -> TypedBlock<Self> {
+        TypedBlock::new(
+            BlockMetaBuilder::new("SpectrumSink").build(),
+            StreamIoBuilder::new().add_input::<Complex32>("in").build(),
+            MessageIoBuilder::<Self>::new().build(),
+            SpectrumSink { size, averager, sender },
+        )
+    }
+}
+
+#[doc(hidden)]
+#[async_trait]
+impl Kernel for SpectrumSink {
+    async fn work(
+        &mut self,
+        io: &mut WorkIo,
+        sio: &mut StreamIo,
+        _mio: &mut MessageIo<Self>,
+        _meta: &mut BlockMeta,
+    ) -> Result<()> {
+        let i = sio.input(0).slice::<Complex32>();
+
+        // Only the whole frames
+        let frames = i.len() / self.size;
+        for frame in i.chunks_exact(self.size) {
+            if let Some(spectrum) = self.averager.push_frame(frame) {
+                let _ = self.sender.try_send(spectrum);
+            }
+        }
+        sio.input(0).consume(frames * self.size);
+
+        if sio.input(0).finished() {
+            io.finished = true;
+        }
+
+        Ok(())
+    }
+}
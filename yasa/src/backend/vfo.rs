@@ -0,0 +1,113 @@
+//! Digital VFO inside the captured band.
+//!
+//! The receiver is tuned by the NCO, which shifts the channel to zero frequency.
+//! The hardware is retuned only when the channel leaves the usable part of the band.
+
+
+// Part of the sample rate passed by the anti-aliasing filter of the SDR
+const USABLE_BAND: f64 = 0.9;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vfo {
+    rate: f64,
+    // Largest distance of the VFO from the hardware frequency
+    max_offset: f64,
+    center: f64,
+    frequency: f64,
+}
+
+impl Vfo {
+    /// VFO at `frequency` in the band of `rate` samples per second.
+    /// `channel_rate` is the bandwidth of the channel, which must fit into the band.
+    pub fn new(frequency: f64, rate: f64, channel_rate: f64) -> Self {
+        let max_offset = (USABLE_BAND * rate / 2.0 - channel_rate / 2.0).max(rate / 4.0);
+        Self { rate, max_offset, center: Self::center_for(frequency, rate), frequency }
+    }
+
+    /// VFO in the band around the fixed `center`, e.g. of the recording.
+    /// The VFO goes below the center, away from the DC spike, when `frequency` is outside of the band.
+    pub fn with_center(center: f64, frequency: f64, rate: f64, channel_rate: f64) -> Self {
+        let mut vfo = Self { center, frequency: center - rate / 4.0, ..Self::new(center, rate, channel_rate) };
+        if vfo.contains(frequency) {
+            vfo.frequency = frequency;
+        }
+        vfo
+    }
+
+    /// Check if the VFO can be tuned to the frequency without retuning the hardware
+    pub fn contains(&self, frequency: f64) -> bool {
+        (frequency - self.center).abs() <= self.max_offset
+    }
+
+    /// Hardware frequency, which is in the middle of the spectrum
+    pub fn center(&self) -> f64 {
+        self.center
+    }
+
+    /// Tuned frequency
+    pub fn frequency(&self) -> f64 {
+        self.frequency
+    }
+
+    /// Tuned frequency relative to the hardware frequency
+    pub fn offset(&self) -> f64 {
+        self.frequency - self.center
+    }
+
+    /// Move the VFO. Returns the new hardware frequency when the hardware must be retuned.
+    pub fn tune(&mut self, frequency: f64) -> Option<f64> {
+        self.frequency = frequency;
+        if self.contains(frequency) {
+            return None;
+        }
+        self.center = Self::center_for(frequency, self.rate);
+        Some(self.center)
+    }
+
+    // Keep the channel away from the DC spike
+    fn center_for(frequency: f64, rate: f64) -> f64 {
+        frequency + rate / 4.0
+    }
+}
+
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tune_inside_band() {
+        let mut vfo = Vfo::new(100e6, 1e6, 240e3);
+        assert_eq!(vfo.center(), 100.25e6);
+        assert_eq!(vfo.offset(), -250e3);
+
+        assert_eq!(vfo.tune(100.5e6), None);
+        assert_eq!(vfo.offset(), 250e3);
+        assert_eq!(vfo.tune(99.92e6), None);
+        assert_eq!(vfo.center(), 100.25e6);
+    }
+
+    #[test]
+    fn test_retune_hardware() {
+        let mut vfo = Vfo::new(100e6, 1e6, 240e3);
+        // Channel would cross the edge of the band
+        assert_eq!(vfo.tune(100.6e6), Some(100.85e6));
+        assert_eq!(vfo.frequency(), 100.6e6);
+        assert_eq!(vfo.offset(), -250e3);
+        assert_eq!(vfo.tune(99.5e6), Some(99.75e6));
+    }
+
+    #[test]
+    fn test_fixed_center() {
+        let vfo = Vfo::with_center(100e6, 100.2e6, 1e6, 240e3);
+        assert_eq!(vfo.center(), 100e6);
+        assert_eq!(vfo.offset(), 200e3);
+        assert!(!vfo.contains(100.5e6));
+
+        let vfo = Vfo::with_center(100e6, 92e6, 1e6, 240e3);
+        assert_eq!(vfo.frequency(), 99.75e6);
+    }
+}
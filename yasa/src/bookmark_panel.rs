@@ -0,0 +1,168 @@
+//! Bookmark manager shown in the side panel
+
+use std::fs;
+use std::path::Path;
+
+use eframe::egui::*;
+use futuresdr::anyhow;
+
+use crate::bookmarks::{groups, Bookmark, Format};
+
+
+pub enum Action {
+    /// Tune to the bookmark
+    Tune(Bookmark),
+    /// Bookmarks were changed and should be saved
+    Save,
+}
+
+pub struct BookmarkPanel {
+    selected: Option<usize>,
+    // Tags of the selected bookmark separated by commas, while they are edited
+    tags: String,
+    path: String,
+    format: Format,
+    // Result of the last import or export
+    status: String,
+}
+
+impl BookmarkPanel {
+    pub fn new() -> Self {
+        Self {
+            selected: None,
+            tags: String::new(),
+            path: "bookmarks.csv".to_string(),
+            format: Format::Chirp,
+            status: String::new(),
+        }
+    }
+
+    /// Show the bookmarks by group and the editor of the selected one.
+    /// `snapshot` is the bookmark of the current frequency and demodulator settings.
+    pub fn show(&mut self, ui: &mut Ui, bookmarks: &mut Vec<Bookmark>, snapshot: Bookmark) -> Option<Action> {
+        let mut action = None;
+
+        ui.horizontal(|ui| {
+            if ui.button("Add").on_hover_text("Bookmark the current frequency").clicked() {
+                let group = self.selected.map(|i| bookmarks[i].group.clone()).unwrap_or_default();
+                bookmarks.push(Bookmark { group, ..snapshot.clone() });
+                self.select(bookmarks, Some(bookmarks.len() - 1));
+                action = Some(Action::Save);
+            }
+            if let Some(i) = self.selected {
+                if ui.button("Update").on_hover_text("Store the current frequency and settings").clicked() {
+                    let Bookmark { frequency, mode, bandwidth, squelch, .. } = snapshot;
+                    bookmarks[i] = Bookmark { frequency, mode, bandwidth, squelch, ..bookmarks[i].clone() };
+                    action = Some(Action::Save);
+                }
+                if ui.button("Delete").clicked() {
+                    bookmarks.remove(i);
+                    self.select(bookmarks, None);
+                    action = Some(Action::Save);
+                }
+            }
+        });
+        ui.separator();
+
+        ScrollArea::vertical().max_height((ui.available_height() - 200.0).max(100.0)).show(ui, |ui| {
+            for group in groups(bookmarks) {
+                let title = if group.is_empty() { "Ungrouped" } else { &group };
+                CollapsingHeader::new(title).default_open(true).show(ui, |ui| {
+                    for i in (0..bookmarks.len()).filter(|&i| bookmarks[i].group == group) {
+                        let bookmark = &bookmarks[i];
+                        let label = ui.selectable_label(self.selected == Some(i), &bookmark.name)
+                            .on_hover_text(format!("{:.3} MHz {}", bookmark.frequency as f64 / 1e6, bookmark.tags.join(", ")));
+                        if label.clicked() {
+                            action = Some(Action::Tune(bookmark.clone()));
+                            self.select(bookmarks, Some(i));
+                        }
+                    }
+                });
+            }
+        });
+
+        if let Some(i) = self.selected {
+            ui.separator();
+            if self.edit_ui(ui, &mut bookmarks[i]) {
+                action = Some(Action::Save);
+            }
+        }
+
+        ui.separator();
+        if let Some(imported) = self.file_ui(ui, bookmarks) {
+            bookmarks.extend(imported);
+            action = Some(Action::Save);
+        }
+
+        action
+    }
+
+    fn select(&mut self, bookmarks: &[Bookmark], selected: Option<usize>) {
+        self.selected = selected;
+        self.tags = selected.map(|i| bookmarks[i].tags.join(", ")).unwrap_or_default();
+    }
+
+    // Name, group and tags. Returns true when the editing is finished.
+    fn edit_ui(&mut self, ui: &mut Ui, bookmark: &mut Bookmark) -> bool {
+        let mut changed = false;
+        Grid::new("bookmark").num_columns(2).show(ui, |ui| {
+            ui.label("Name");
+            changed |= ui.text_edit_singleline(&mut bookmark.name).lost_focus();
+            ui.end_row();
+            ui.label("Group");
+            changed |= ui.text_edit_singleline(&mut bookmark.group).lost_focus();
+            ui.end_row();
+            ui.label("Tags");
+            if ui.text_edit_singleline(&mut self.tags).lost_focus() {
+                bookmark.tags = self.tags.split(',')
+                    .map(|t| t.trim().to_string())
+                    .filter(|t| !t.is_empty())
+                    .collect();
+                changed = true;
+            }
+            ui.end_row();
+        });
+        changed
+    }
+
+    // Import and export. Returns the imported bookmarks.
+    fn file_ui(&mut self, ui: &mut Ui, bookmarks: &[Bookmark]) -> Option<Vec<Bookmark>> {
+        let mut imported = None;
+        ui.horizontal(|ui| {
+            ui.add(TextEdit::singleline(&mut self.path).desired_width(120.0));
+            ComboBox::from_id_source("bookmark_format")
+                .selected_text(self.format.name())
+                .show_ui(ui, |ui| {
+                    for format in Format::ALL {
+                        ui.selectable_value(&mut self.format, format, format.name());
+                    }
+                });
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Import").clicked() {
+                // Imported bookmarks are grouped by the file name
+                let group = Path::new(&self.path).file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+                let result = fs::read_to_string(&self.path)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|text| self.format.import(&text, &group));
+                match result {
+                    Ok(new_bookmarks) => {
+                        self.status = format!("Imported {} bookmarks", new_bookmarks.len());
+                        imported = Some(new_bookmarks);
+                    }
+                    Err(e) => self.status = format!("Import failed: {e}"),
+                }
+            }
+            if ui.button("Export").clicked() {
+                self.status = match fs::write(&self.path, self.format.export(bookmarks)) {
+                    Ok(_) => format!("Exported {} bookmarks", bookmarks.len()),
+                    Err(e) => format!("Export failed: {e}"),
+                };
+            }
+        });
+        if !self.status.is_empty() {
+            ui.label(&self.status);
+        }
+        imported
+    }
+}
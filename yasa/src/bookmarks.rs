@@ -0,0 +1,314 @@
+//! Bookmarks saved in the config file and their import and export.
+//!
+//! Supported formats are the CHIRP CSV and the gqrx `bookmarks.csv`.
+
+use futuresdr::anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::backend::demod::{Mode, SquelchSettings};
+
+
+// Columns of the CHIRP CSV
+const CHIRP_HEADER: &str = "Location,Name,Frequency,Duplex,Offset,Tone,rToneFreq,cToneFreq,DtcsCode,DtcsPolarity,Mode,TStep,Skip,Comment,URCALL,RPT1CALL,RPT2CALL,DVCODE";
+// Tag of the gqrx bookmarks without tags
+const GQRX_UNTAGGED: &str = "Untagged";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub name: String,
+    pub frequency: u64,
+    /// Folder shown in the bookmark panel. Empty for the ungrouped bookmarks.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub group: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<Mode>,
+    /// Channel bandwidth in Hz
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bandwidth: Option<f32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub squelch: Option<SquelchSettings>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Chirp,
+    Gqrx,
+}
+
+impl Format {
+    pub const ALL: [Format; 2] = [Format::Chirp, Format::Gqrx];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Chirp => "CHIRP CSV",
+            Format::Gqrx => "gqrx",
+        }
+    }
+
+    /// Parse the bookmarks file. Imported bookmarks are put into `group`.
+    pub fn import(&self, text: &str, group: &str) -> Result<Vec<Bookmark>> {
+        match self {
+            Format::Chirp => import_chirp(text, group),
+            Format::Gqrx => import_gqrx(text, group),
+        }
+    }
+
+    pub fn export(&self, bookmarks: &[Bookmark]) -> String {
+        match self {
+            Format::Chirp => export_chirp(bookmarks),
+            Format::Gqrx => export_gqrx(bookmarks),
+        }
+    }
+}
+
+/// Names of the groups in the alphabetical order
+pub fn groups(bookmarks: &[Bookmark]) -> Vec<String> {
+    let mut groups: Vec<String> = bookmarks.iter().map(|b| b.group.clone()).collect();
+    groups.sort();
+    groups.dedup();
+    groups
+}
+
+fn import_chirp(text: &str, group: &str) -> Result<Vec<Bookmark>> {
+    let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+    let header = split_csv(lines.next().ok_or_else(|| anyhow!("Empty file"))?);
+    let column = |name: &str| header.iter().position(|h| h == name).ok_or_else(|| anyhow!("Missing column {name}"));
+    let (name_col, freq_col, mode_col) = (column("Name")?, column("Frequency")?, column("Mode")?);
+
+    lines
+        .map(|line| {
+            let fields = split_csv(line);
+            let field = |i: usize| fields.get(i).map(|f| f.as_str()).unwrap_or("");
+            let mhz: f64 = field(freq_col).parse().map_err(|_| anyhow!("Wrong frequency: {line}"))?;
+            let mode = match field(mode_col) {
+                "WFM" => Some(Mode::Wfm),
+                "FM" | "NFM" => Some(Mode::Nfm),
+                "AM" => Some(Mode::Am),
+                "USB" => Some(Mode::Usb),
+                "LSB" => Some(Mode::Lsb),
+                "CW" => Some(Mode::Cw),
+                _ => None,
+            };
+            let bandwidth = match field(mode_col) {
+                "FM" => Some(25_000.0),
+                "NFM" => Some(12_500.0),
+                _ => None,
+            };
+            Ok(Bookmark {
+                name: field(name_col).to_string(),
+                frequency: (mhz * 1e6).round() as u64,
+                group: group.to_string(),
+                mode,
+                bandwidth,
+                tags: vec![],
+                squelch: None,
+            })
+        })
+        .collect()
+}
+
+fn export_chirp(bookmarks: &[Bookmark]) -> String {
+    let mut text = format!("{CHIRP_HEADER}\n");
+    for (i, bookmark) in bookmarks.iter().enumerate() {
+        let mode = match bookmark.mode {
+            Some(Mode::Wfm) => "WFM",
+            Some(Mode::Nfm) if bookmark.bandwidth.is_some_and(|b| b < 20_000.0) => "NFM",
+            Some(Mode::Nfm) | None => "FM",
+            Some(Mode::Am) | Some(Mode::Sam) => "AM",
+            Some(Mode::Usb) => "USB",
+            Some(Mode::Lsb) => "LSB",
+            Some(Mode::Cw) => "CW",
+        };
+        text += &format!(
+            "{},{},{:.6},,0.000000,,88.5,88.5,023,NN,{},5.00,,,,,,\n",
+            i + 1,
+            quote_csv(&bookmark.name),
+            bookmark.frequency as f64 / 1e6,
+            mode,
+        );
+    }
+    text
+}
+
+fn import_gqrx(text: &str, group: &str) -> Result<Vec<Bookmark>> {
+    // Tag table comes first and the bookmarks follow the "# Frequency" header
+    let mut lines = text.lines().skip_while(|l| !l.trim_start().starts_with("# Frequency"));
+    lines.next().ok_or_else(|| anyhow!("Missing bookmarks table"))?;
+
+    lines
+        .filter(|l| !l.trim().is_empty() && !l.trim_start().starts_with('#'))
+        .map(|line| {
+            let fields: Vec<&str> = line.split(';').map(|f| f.trim()).collect();
+            if fields.len() < 5 {
+                return Err(anyhow!("Wrong bookmark: {line}"));
+            }
+            let frequency = fields[0].parse().map_err(|_| anyhow!("Wrong frequency: {line}"))?;
+            let mode = match fields[2] {
+                "Narrow FM" => Some(Mode::Nfm),
+                m if m.starts_with("WFM") => Some(Mode::Wfm),
+                "AM" => Some(Mode::Am),
+                "AM-Sync" => Some(Mode::Sam),
+                "LSB" => Some(Mode::Lsb),
+                "USB" => Some(Mode::Usb),
+                "CW-L" | "CW-U" => Some(Mode::Cw),
+                _ => None,
+            };
+            let tags = fields[4].split(',')
+                .map(|t| t.trim())
+                .filter(|t| !t.is_empty() && *t != GQRX_UNTAGGED)
+                .map(|t| t.to_string())
+                .collect();
+            Ok(Bookmark {
+                name: fields[1].to_string(),
+                frequency,
+                group: group.to_string(),
+                mode,
+                bandwidth: fields[3].parse().ok(),
+                tags,
+                squelch: None,
+            })
+        })
+        .collect()
+}
+
+fn export_gqrx(bookmarks: &[Bookmark]) -> String {
+    let mut tags: Vec<&str> = bookmarks.iter().flat_map(|b| b.tags.iter().map(|t| t.as_str())).collect();
+    tags.push(GQRX_UNTAGGED);
+    tags.sort();
+    tags.dedup();
+
+    let mut text = String::from("# Tag name          ;  color\n");
+    for tag in tags {
+        text += &format!("{:<20}; #c0c0c0\n", tag);
+    }
+    text += "\n# Frequency ; Name                     ; Modulation          ;  Bandwidth; Tags\n";
+    for bookmark in bookmarks {
+        let mode = match bookmark.mode {
+            Some(Mode::Wfm) => "WFM (stereo)",
+            Some(Mode::Nfm) => "Narrow FM",
+            Some(Mode::Am) => "AM",
+            Some(Mode::Sam) => "AM-Sync",
+            Some(Mode::Usb) => "USB",
+            Some(Mode::Lsb) => "LSB",
+            Some(Mode::Cw) => "CW-U",
+            None => "Raw I/Q",
+        };
+        let bandwidth = bookmark.bandwidth
+            .or(bookmark.mode.map(|m| m.bandwidth()))
+            .unwrap_or(0.0);
+        let tags = if bookmark.tags.is_empty() { GQRX_UNTAGGED.to_string() } else { bookmark.tags.join(",") };
+        // Separator can't be escaped in gqrx
+        let name = bookmark.name.replace(';', ",");
+        text += &format!("{:>12}; {:<25}; {:<20}; {:>10}; {}\n", bookmark.frequency, name, mode, bandwidth as u32, tags);
+    }
+    text
+}
+
+// Split the CSV line. Quoted fields can contain commas and the doubled quotes.
+fn split_csv(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.trim_end_matches('\r').chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            _ => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+fn quote_csv(field: &str) -> String {
+    if field.contains([',', '"']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bookmark(name: &str, frequency: u64, mode: Mode, bandwidth: f32, tags: &[&str]) -> Bookmark {
+        Bookmark {
+            name: name.to_string(),
+            frequency,
+            group: "Test".to_string(),
+            mode: Some(mode),
+            bandwidth: Some(bandwidth),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            squelch: None,
+        }
+    }
+
+    #[test]
+    fn test_import_chirp() {
+        let text = "Location,Name,Frequency,Duplex,Offset,Tone,rToneFreq,cToneFreq,DtcsCode,DtcsPolarity,Mode,TStep,Skip,Comment,URCALL,RPT1CALL,RPT2CALL,DVCODE\n\
+                    0,\"Call, 2m\",145.500000,,0.600000,,88.5,88.5,023,NN,FM,5.00,,,,,,\n\
+                    1,PMR 1,446.006250,,0.000000,,88.5,88.5,023,NN,NFM,6.25,,,,,,\n";
+        let bookmarks = Format::Chirp.import(text, "Radio").unwrap();
+        assert_eq!(bookmarks.len(), 2);
+        assert_eq!(bookmarks[0].name, "Call, 2m");
+        assert_eq!(bookmarks[0].frequency, 145_500_000);
+        assert_eq!(bookmarks[0].mode, Some(Mode::Nfm));
+        assert_eq!(bookmarks[1].frequency, 446_006_250);
+        assert_eq!(bookmarks[1].bandwidth, Some(12_500.0));
+        assert_eq!(bookmarks[1].group, "Radio");
+
+        assert!(Format::Chirp.import("Location,Name\n", "Radio").is_err());
+    }
+
+    #[test]
+    fn test_import_gqrx() {
+        let text = "# Tag name          ;  color\n\
+                    Untagged            ; #c0c0c0\n\
+                    Marine              ; #c0c0c0\n\
+                    \n\
+                    # Frequency ; Name                     ; Modulation          ;  Bandwidth; Tags\n\
+                    \x20  162400000; NOAA                     ; Narrow FM           ;      10000; Marine\n\
+                    \x20   95000000; Radio                    ; WFM (stereo)        ;     160000; Untagged\n";
+        let bookmarks = Format::Gqrx.import(text, "gqrx").unwrap();
+        assert_eq!(bookmarks.len(), 2);
+        assert_eq!(bookmarks[0], Bookmark { group: "gqrx".to_string(), ..bookmark("NOAA", 162_400_000, Mode::Nfm, 10_000.0, &["Marine"]) });
+        assert_eq!(bookmarks[1].mode, Some(Mode::Wfm));
+        assert!(bookmarks[1].tags.is_empty());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bookmarks = vec![
+            bookmark("Air, tower", 118_100_000, Mode::Am, 10_000.0, &["Air"]),
+            bookmark("FT8", 14_074_000, Mode::Usb, 2_400.0, &[]),
+            bookmark("Radio \"1\"", 92_000_000, Mode::Wfm, 200_000.0, &["Broadcast", "Music"]),
+        ];
+
+        let chirp = Format::Chirp.import(&Format::Chirp.export(&bookmarks), "Test").unwrap();
+        assert_eq!(chirp.len(), 3);
+        for (a, b) in chirp.iter().zip(&bookmarks) {
+            assert_eq!((&a.name, a.frequency, a.mode), (&b.name, b.frequency, b.mode));
+        }
+
+        let gqrx = Format::Gqrx.import(&Format::Gqrx.export(&bookmarks), "Test").unwrap();
+        assert_eq!(gqrx, bookmarks.iter().map(|b| Bookmark { name: b.name.replace(';', ","), ..b.clone() }).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_groups() {
+        let mut bookmarks = vec![bookmark("A", 1, Mode::Am, 1.0, &[]); 3];
+        bookmarks[0].group = "B".to_string();
+        bookmarks[2].group = String::new();
+        assert_eq!(groups(&bookmarks), vec!["", "B", "Test"]);
+    }
+}
@@ -0,0 +1,113 @@
+//! Channels demodulated next to the VFO, shown in the side panel
+
+use eframe::egui::*;
+
+use crate::backend::channels::Channel;
+use crate::backend::demod::SquelchMode;
+use crate::backend::radio::ChannelStatus;
+
+
+pub enum Action {
+    /// Add the channel at the VFO frequency
+    Add,
+    Remove(usize),
+    /// Apply the edited channel
+    Update(usize, Channel),
+    /// Channels were changed and should be saved
+    Save,
+    /// Tune the VFO to the channel
+    Tune(usize),
+    /// Start the audio recording of the channel
+    Record(usize),
+    StopRecord(usize),
+    MuteVfo(bool),
+}
+
+/// List of the channels with their squelch, mute, solo and recording.
+/// `status` has the state of every channel.
+pub fn show(ui: &mut Ui, channels: &[Channel], status: &[ChannelStatus], vfo_mute: bool, is_running: bool) -> Option<Action> {
+    let mut action = None;
+
+    ui.horizontal(|ui| {
+        if ui.button("Add").on_hover_text("Demodulate the VFO frequency as the channel. Recordings continue after a short gap.").clicked() {
+            action = Some(Action::Add);
+        }
+        let mut mute = vfo_mute;
+        if ui.checkbox(&mut mute, "Mute VFO").changed() {
+            action = Some(Action::MuteVfo(mute));
+        }
+    });
+
+    Grid::new("channels").num_columns(6).show(ui, |ui| {
+        for (i, (channel, status)) in channels.iter().zip(status).enumerate() {
+            let label = ui.link(format!("{:.4}", channel.frequency as f64 / 1e6))
+                .on_hover_text(format!("{} {} kHz, click to tune the VFO", channel.mode.name(), channel.bandwidth / 1000.0));
+            if label.clicked() {
+                action = Some(Action::Tune(i));
+            }
+
+            let mut edited = channel.clone();
+            ui.horizontal(|ui| {
+                match status.squelch {
+                    _ if !status.in_band => {
+                        ui.colored_label(ui.visuals().warn_fg_color, "⚠").on_hover_text("Outside of the received band");
+                    }
+                    Some(squelch) => {
+                        let color = if squelch.open { Color32::LIGHT_GREEN } else { Color32::GRAY };
+                        ui.colored_label(color, "●").on_hover_text(format!("{:.1} dB", squelch.level));
+                    }
+                    None => {
+                        ui.label("○").on_hover_text("Squelch is off");
+                    }
+                }
+                let threshold = match edited.squelch.mode {
+                    SquelchMode::Power => Some((&mut edited.squelch.power_threshold, -120.0..=0.0)),
+                    SquelchMode::Noise => Some((&mut edited.squelch.noise_threshold, -60.0..=0.0)),
+                    SquelchMode::Off => None,
+                };
+                if let Some((value, range)) = threshold {
+                    let response = ui.add(DragValue::new(value).clamp_range(range).speed(0.5).suffix(" dB"))
+                        .on_hover_text("Squelch threshold");
+                    if response.drag_released() || response.lost_focus() {
+                        action = Some(Action::Save);
+                    }
+                }
+            });
+
+            if ui.selectable_label(channel.mute, "M").on_hover_text("Mute").clicked() {
+                edited.mute = !edited.mute;
+            }
+            if ui.selectable_label(channel.solo, "S").on_hover_text("Solo").clicked() {
+                edited.solo = !edited.solo;
+            }
+            if edited != *channel {
+                action = Some(Action::Update(i, edited));
+            }
+
+            match &status.recording {
+                Some(recording) => {
+                    let text = match recording.path.as_ref().and_then(|path| path.file_name()) {
+                        Some(name) => format!("{}\n{:.1} s", name.to_string_lossy(), recording.seconds),
+                        None => "Waiting for squelch".to_string(),
+                    };
+                    if ui.selectable_label(true, "⏺").on_hover_text(text).clicked() {
+                        action = Some(Action::StopRecord(i));
+                    }
+                }
+                None => {
+                    let record = ui.add_enabled(is_running, SelectableLabel::new(false, "⏺"))
+                        .on_hover_text("Record the audio of the channel");
+                    if record.clicked() {
+                        action = Some(Action::Record(i));
+                    }
+                }
+            }
+            if ui.small_button("✖").on_hover_text("Remove").clicked() {
+                action = Some(Action::Remove(i));
+            }
+            ui.end_row();
+        }
+    });
+
+    action
+}
@@ -0,0 +1,166 @@
+//! Saving of the config file without losing the comments and the keys unknown to the app.
+//!
+//! The config is serialized and merged into the document read from the file. Values which the app
+//! didn't change keep their formatting and comments. Keys which the app stopped writing are removed.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use futuresdr::anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use toml_edit::{ArrayOfTables, Document, Item, Table};
+
+
+/// Write the config to the file, which is created when it doesn't exist
+pub fn save<T: Serialize + DeserializeOwned>(path: &Path, config: &T) -> Result<()> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    fs::write(path, update(&text, config)?)?;
+    Ok(())
+}
+
+/// Text of the config file `text` with the values of `config`
+pub fn update<T: Serialize + DeserializeOwned>(text: &str, config: &T) -> Result<String> {
+    let mut file: Document = text.parse().context("Config file is not valid TOML")?;
+    let new: Document = toml::to_string(config)?.parse()?;
+    // Values the app read from the file. Only the ones which differ from them are written.
+    // File which the app can't read would be overwritten by the new config, so it is kept.
+    let old: Document = if file.is_empty() {
+        Document::new()
+    } else {
+        let old: T = toml::from_str(text).context("Config file has invalid settings")?;
+        toml::to_string(&old)?.parse()?
+    };
+    merge(file.as_table_mut(), new.as_table(), old.as_table());
+    Ok(file.to_string())
+}
+
+fn merge(file: &mut Table, new: &Table, old: &Table) {
+    let removed: Vec<String> = old.iter()
+        .map(|(key, _)| key.to_string())
+        .filter(|key| !new.contains_key(key))
+        .collect();
+    for key in removed {
+        file.remove(&key);
+    }
+
+    let empty = Table::new();
+    for (key, item) in new.iter() {
+        let previous = old.get(key);
+        let changed = previous.map(|p| p.to_string()) != Some(item.to_string());
+        match (file.get_mut(key), item) {
+            (Some(Item::Table(file_table)), Item::Table(new_table)) => {
+                merge(file_table, new_table, previous.and_then(Item::as_table).unwrap_or(&empty));
+            }
+            (Some(Item::ArrayOfTables(file_array)), Item::ArrayOfTables(new_array)) if changed => {
+                let empty_array = ArrayOfTables::new();
+                merge_array(file_array, new_array, previous.and_then(Item::as_array_of_tables).unwrap_or(&empty_array));
+            }
+            (Some(Item::Value(file_value)), Item::Value(new_value)) if changed => {
+                // Comment after the value stays
+                let decor = file_value.decor().clone();
+                *file_value = new_value.clone();
+                *file_value.decor_mut() = decor;
+            }
+            (Some(file_item), _) if changed => *file_item = item.clone(),
+            (Some(_), _) => {}
+            (None, _) => {
+                file.insert(key, item.clone());
+            }
+        }
+    }
+}
+
+// Tables are matched by their position, so the comments above them are kept
+fn merge_array(file: &mut ArrayOfTables, new: &ArrayOfTables, old: &ArrayOfTables) {
+    let empty = Table::new();
+    while file.len() > new.len() {
+        file.remove(file.len() - 1);
+    }
+    for (i, new_table) in new.iter().enumerate() {
+        match file.get_mut(i) {
+            Some(file_table) => merge(file_table, new_table, old.get(i).unwrap_or(&empty)),
+            None => file.push(new_table.clone()),
+        }
+    }
+}
+
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize)]
+    struct Config {
+        source: Source,
+        #[serde(default)]
+        bookmarks: Vec<Bookmark>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Source {
+        frequency: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        antenna: Option<String>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Bookmark {
+        name: String,
+    }
+
+    const TEXT: &str = r#"# Device
+[source]
+frequency = 92_000_000 # Antyradio
+antenna = "RX"
+# Not known to the app
+buffer = 16
+
+# Saved by the app
+[[bookmarks]]
+name = "Antyradio"
+
+[[bookmarks]]
+name = "Radio Gdansk"
+"#;
+
+    #[test]
+    fn test_unchanged() {
+        let config: Config = toml::from_str(TEXT).unwrap();
+        assert_eq!(update(TEXT, &config).unwrap(), TEXT);
+    }
+
+    #[test]
+    fn test_changed() {
+        let mut config: Config = toml::from_str(TEXT).unwrap();
+        config.source.frequency = 103_700_000;
+        config.source.antenna = None;
+        config.bookmarks.remove(0);
+        let text = update(TEXT, &config).unwrap();
+
+        assert!(text.starts_with("# Device\n[source]\nfrequency = 103700000 # Antyradio\n"));
+        assert!(!text.contains("antenna"));
+        assert!(text.contains("# Not known to the app\nbuffer = 16\n"));
+        assert!(text.contains("# Saved by the app\n[[bookmarks]]\nname = \"Radio Gdansk\"\n"));
+        assert_eq!(text.matches("[[bookmarks]]").count(), 1);
+    }
+
+    #[test]
+    fn test_new_file() {
+        let config = Config { source: Source { frequency: 1, antenna: None }, bookmarks: vec![] };
+        let text = update("", &config).unwrap();
+        assert_eq!(toml::from_str::<Config>(&text).unwrap().source.frequency, 1);
+        assert!(update("[source", &config).is_err());
+        // Valid TOML with the wrong type isn't overwritten
+        assert!(update("[source]\nfrequency = \"92\"\n", &config).is_err());
+    }
+}
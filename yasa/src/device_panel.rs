@@ -0,0 +1,191 @@
+//! Window for choosing the SDR device and its sample rate and antenna
+
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use eframe::egui::*;
+
+use crate::backend::device::{self, Capabilities, DeviceInfo, DeviceSettings};
+use crate::backend::iq_file::FileArgs;
+
+
+pub struct DevicePanel {
+    pub open: bool,
+    devices: Vec<DeviceInfo>,
+    // Settings edited by the user, applied with the button
+    settings: DeviceSettings,
+    // Args of the edited device and its capabilities, when it isn't the one in use
+    capabilities: Option<(String, Option<Capabilities>)>,
+    // Device is opened by the thread, so the window doesn't wait for it
+    query: Option<(String, Receiver<Result<Capabilities, String>>)>,
+    error: Option<String>,
+}
+
+impl DevicePanel {
+    pub fn new(settings: DeviceSettings) -> Self {
+        Self { open: false, devices: vec![], settings, capabilities: None, query: None, error: None }
+    }
+
+    /// Open the window with the settings of the device in use
+    pub fn open(&mut self, settings: &DeviceSettings) {
+        self.open = true;
+        self.settings = settings.clone();
+        self.error = None;
+        self.refresh();
+    }
+
+    /// Show the window. `current` is the device in use and its capabilities.
+    /// Returns the settings to apply.
+    pub fn show(&mut self, ctx: &Context, current: (&DeviceSettings, Option<&Capabilities>)) -> Option<DeviceSettings> {
+        let mut apply = None;
+        let mut open = self.open;
+        self.receive_query();
+        if self.query.is_some() {
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
+        // Devices are queried when the args are chosen, not on every typed character
+        let mut query = false;
+        Window::new("Device").open(&mut open).resizable(false).show(ctx, |ui| {
+            Grid::new("device").num_columns(2).show(ui, |ui| {
+                ui.label("Device");
+                ui.horizontal(|ui| {
+                    let selected = self.devices.iter()
+                        .find(|d| d.args == self.settings.args)
+                        .map_or("Custom", |d| d.label.as_str())
+                        .to_string();
+                    ComboBox::from_id_source("device")
+                        .selected_text(selected)
+                        .show_ui(ui, |ui| {
+                            for device in &self.devices {
+                                query |= ui.selectable_value(&mut self.settings.args, device.args.clone(), &device.label).clicked();
+                            }
+                        });
+                    if ui.button("Refresh").clicked() {
+                        self.refresh();
+                        self.capabilities = None;
+                        query = true;
+                    }
+                    if self.query.is_some() {
+                        ui.spinner();
+                    }
+                });
+                ui.end_row();
+
+                ui.label("Args");
+                query |= ui.text_edit_singleline(&mut self.settings.args).lost_focus();
+                ui.end_row();
+
+                // Rate of the file is in the file or its args
+                let is_file = FileArgs::parse(&self.settings.args).is_some();
+                let capabilities = if is_file {
+                    None
+                } else if self.settings.args == current.0.args {
+                    current.1.cloned()
+                } else {
+                    self.capabilities.as_ref()
+                        .filter(|(args, _)| *args == self.settings.args)
+                        .and_then(|(_, capabilities)| capabilities.clone())
+                };
+                let capabilities = capabilities.as_ref();
+
+                ui.label("Sample rate");
+                match capabilities {
+                    None if is_file => {
+                        ui.label("From the file");
+                    }
+                    Some(capabilities) => {
+                        ComboBox::from_id_source("rate")
+                            .selected_text(rate_name(self.settings.rate))
+                            .show_ui(ui, |ui| {
+                                for &rate in &capabilities.sample_rates {
+                                    ui.selectable_value(&mut self.settings.rate, rate, rate_name(rate));
+                                }
+                            });
+                    }
+                    None => {
+                        ui.add(DragValue::new(&mut self.settings.rate).speed(1e4).clamp_range(1e5..=1e8).suffix(" S/s"));
+                    }
+                }
+                ui.end_row();
+
+                if let Some(capabilities) = capabilities.filter(|c| !c.antennas.is_empty()) {
+                    ui.label("Antenna");
+                    ComboBox::from_id_source("antenna")
+                        .selected_text(self.settings.antenna.as_deref().unwrap_or("Default"))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.settings.antenna, None, "Default");
+                            for antenna in &capabilities.antennas {
+                                ui.selectable_value(&mut self.settings.antenna, Some(antenna.clone()), antenna);
+                            }
+                        });
+                    ui.end_row();
+                }
+            });
+
+            ui.separator();
+            if ui.button("Apply").clicked() {
+                apply = Some(self.settings.clone());
+            }
+            if let Some(error) = &self.error {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            }
+        });
+        self.open = open;
+        // File and the device in use are not opened
+        if query && FileArgs::parse(&self.settings.args).is_none() && self.settings.args != current.0.args {
+            self.start_query();
+        }
+        apply
+    }
+
+    /// Show the error of applying the settings
+    pub fn set_error(&mut self, error: Option<String>) {
+        self.error = error;
+    }
+
+    fn refresh(&mut self) {
+        match device::enumerate() {
+            Ok(devices) => self.devices = devices,
+            Err(e) => self.error = Some(format!("Can't list devices: {e}")),
+        }
+    }
+
+    // Ask for the capabilities of the edited device, once for every args
+    fn start_query(&mut self) {
+        let args = self.settings.args.clone();
+        let known = self.capabilities.as_ref().map(|(a, _)| a);
+        let running = self.query.as_ref().map(|(a, _)| a);
+        if known == Some(&args) || running == Some(&args) {
+            return;
+        }
+        let (tx, rx) = mpsc::channel();
+        let thread_args = args.clone();
+        thread::spawn(move || {
+            let _ = tx.send(Capabilities::query(&thread_args).map_err(|e| e.to_string()));
+        });
+        // Result of the previous args is dropped
+        self.query = Some((args, rx));
+    }
+
+    fn receive_query(&mut self) {
+        let Some((args, rx)) = &self.query else {
+            return;
+        };
+        let capabilities = match rx.try_recv() {
+            Ok(Ok(capabilities)) => Some(capabilities),
+            Ok(Err(e)) => {
+                self.error = Some(format!("Can't open device: {e}"));
+                None
+            }
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => None,
+        };
+        self.capabilities = Some((args.clone(), capabilities));
+        self.query = None;
+    }
+}
+
+fn rate_name(rate: f64) -> String {
+    format!("{} MS/s", rate / 1e6)
+}
@@ -0,0 +1,187 @@
+//! Frequency display with the digit-wise scroll editing, typed input and tuning history.
+
+use eframe::egui::*;
+
+
+// Number of the digits in the display, up to 9.999999999 GHz
+const DIGITS: u32 = 10;
+// Number of the frequencies kept in the history
+const HISTORY: usize = 50;
+
+pub struct FrequencyEntry {
+    // Text typed by the user while editing
+    text: Option<String>,
+}
+
+/// Frequency set by the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Entry {
+    /// Digit was scrolled. The small steps aren't kept in the history.
+    Step(u64),
+    /// Frequency was typed
+    Jump(u64),
+}
+
+/// Frequencies visited by the jumps, for going back and forward
+pub struct History {
+    back: Vec<u64>,
+    forward: Vec<u64>,
+}
+
+impl FrequencyEntry {
+    pub fn new() -> Self {
+        Self { text: None }
+    }
+
+    /// Show the frequency. Scroll over the digit changes it and click starts typing.
+    /// Returns the new frequency set by the user.
+    pub fn show(&mut self, ui: &mut Ui, frequency: u64) -> Option<Entry> {
+        match &mut self.text {
+            Some(text) => {
+                let response = ui.add(TextEdit::singleline(text).desired_width(120.0).font(TextStyle::Monospace));
+                if response.lost_focus() {
+                    let entered = ui.input(|i| i.key_pressed(Key::Enter));
+                    let new_freq = if entered { parse_frequency(text) } else { None };
+                    self.text = None;
+                    return new_freq.map(Entry::Jump);
+                }
+                if !response.has_focus() {
+                    response.request_focus();
+                }
+                None
+            }
+            None => self.show_digits(ui, frequency).map(Entry::Step),
+        }
+    }
+
+    fn show_digits(&mut self, ui: &mut Ui, frequency: u64) -> Option<u64> {
+        let mut new_freq = None;
+        ui.horizontal(|ui| {
+            ui.spacing_mut().item_spacing.x = 0.0;
+            // Leading zeros are dimmed
+            let first = frequency.max(1).ilog10();
+            for pos in (0..DIGITS).rev() {
+                let digit = frequency / 10u64.pow(pos) % 10;
+                let color = if pos > first { ui.visuals().weak_text_color() } else { ui.visuals().strong_text_color() };
+                let text = RichText::new(digit.to_string()).monospace().size(18.0).color(color);
+                let response = ui.add(Label::new(text).sense(Sense::click()));
+                if response.clicked() {
+                    self.text = Some(String::new());
+                }
+                if response.hovered() {
+                    let scroll = ui.input(|i| i.scroll_delta.y);
+                    if scroll > 0.0 {
+                        new_freq = Some(frequency.saturating_add(10u64.pow(pos)));
+                    } else if scroll < 0.0 {
+                        new_freq = Some(frequency.saturating_sub(10u64.pow(pos)));
+                    }
+                }
+                if pos > 0 && pos % 3 == 0 {
+                    ui.label(RichText::new(".").monospace().size(18.0).color(ui.visuals().weak_text_color()));
+                }
+            }
+        });
+        new_freq
+    }
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self { back: vec![], forward: vec![] }
+    }
+
+    /// Remember the frequency we jump away from
+    pub fn push(&mut self, frequency: u64) {
+        if self.back.last() != Some(&frequency) {
+            self.back.push(frequency);
+        }
+        if self.back.len() > HISTORY {
+            self.back.remove(0);
+        }
+        self.forward.clear();
+    }
+
+    /// Frequency where going back leads
+    pub fn previous(&self) -> Option<u64> {
+        self.back.last().copied()
+    }
+
+    /// Frequency where going forward leads
+    pub fn next(&self) -> Option<u64> {
+        self.forward.last().copied()
+    }
+
+    /// Go back once the previous frequency is tuned. `current` is kept for going forward.
+    pub fn back(&mut self, current: u64) {
+        if self.back.pop().is_some() {
+            self.forward.push(current);
+        }
+    }
+
+    /// Go forward once the next frequency is tuned
+    pub fn forward(&mut self, current: u64) {
+        if self.forward.pop().is_some() {
+            self.back.push(current);
+        }
+    }
+}
+
+/// Parse the frequency in Hz with the optional unit suffix: "145.5M", "7074 k", "1.2G" or "100000"
+pub fn parse_frequency(text: &str) -> Option<u64> {
+    let text: String = text.chars().filter(|c| !c.is_whitespace() && *c != '_').collect();
+    let text = text.strip_suffix("Hz").or_else(|| text.strip_suffix("hz")).unwrap_or(&text);
+    let (number, multiplier) = match text.chars().last()? {
+        'k' | 'K' => (&text[..text.len() - 1], 1e3),
+        'm' | 'M' => (&text[..text.len() - 1], 1e6),
+        'g' | 'G' => (&text[..text.len() - 1], 1e9),
+        _ => (text, 1.0),
+    };
+    let frequency = number.parse::<f64>().ok()? * multiplier;
+    (frequency.is_finite() && frequency >= 0.0).then(|| frequency.round() as u64)
+}
+
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_frequency() {
+        assert_eq!(parse_frequency("100000"), Some(100_000));
+        assert_eq!(parse_frequency("145.5M"), Some(145_500_000));
+        assert_eq!(parse_frequency("7 074 kHz"), Some(7_074_000));
+        assert_eq!(parse_frequency("1.2g"), Some(1_200_000_000));
+        assert_eq!(parse_frequency("12.5k"), Some(12_500));
+        assert_eq!(parse_frequency("100_000_000 Hz"), Some(100_000_000));
+        assert_eq!(parse_frequency("M"), None);
+        assert_eq!(parse_frequency("-5k"), None);
+        assert_eq!(parse_frequency("abc"), None);
+        assert_eq!(parse_frequency(""), None);
+    }
+
+    #[test]
+    fn test_history() {
+        let mut history = History::new();
+        assert_eq!(history.previous(), None);
+        history.push(100);
+        history.push(200);
+        assert_eq!(history.previous(), Some(200));
+        history.back(300);
+        assert_eq!(history.previous(), Some(100));
+        history.back(200);
+        assert_eq!(history.previous(), None);
+        assert_eq!(history.next(), Some(200));
+        history.forward(100);
+        assert_eq!(history.next(), Some(300));
+        history.forward(200);
+        assert_eq!(history.next(), None);
+
+        // New jump drops the forward history
+        history.back(300);
+        history.push(200);
+        assert_eq!(history.next(), None);
+    }
+}
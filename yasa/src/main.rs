@@ -1,45 +1,134 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
 mod backend;
+mod bookmark_panel;
+mod bookmarks;
+mod channel_panel;
+mod config_file;
+mod device_panel;
+mod frequency;
+mod scanner_panel;
+mod waterfall;
 
+use std::collections::BTreeMap;
 use std::fs;
+use std::path::Path;
+use std::time::Duration;
 
 use eframe::{egui::{self, Key}, CreationContext};
-use backend::radio::FMRadio;
-use serde::Deserialize;
+use backend::audio_file::AudioFormat;
+use backend::channels::Channel;
+use backend::demod::{pty_name, AgcPreset, Mode, Region, SquelchMode, SquelchSettings};
+use backend::device::DeviceSettings;
+use backend::dsp::AgcTimes;
+use backend::radio::{ChannelStatus, Radio, RecordSource};
+use backend::scanner::ScanSettings;
+use backend::sigmf::SampleFormat;
+use bookmark_panel::{Action, BookmarkPanel};
+use channel_panel::Action as ChannelAction;
+use bookmarks::Bookmark;
+use device_panel::DevicePanel;
+use futuresdr::log;
+use frequency::{Entry, FrequencyEntry, History};
+use scanner_panel::ScannerPanel;
+use serde::{Deserialize, Serialize};
+use waterfall::Waterfall;
 
 
-#[derive(Deserialize)]
+// Path of the config file, which also keeps the bookmarks
+const CONFIG_PATH: &str = "config.toml";
+
+#[derive(Serialize, Deserialize)]
 struct Config {
    source: Source,
+   #[serde(default)]
+   audio: Audio,
+   #[serde(default)]
+   vfo: Vfo,
+   #[serde(default)]
+   recording: Recording,
+   #[serde(default)]
+   scanner: ScanSettings,
+   /// Channels demodulated with the VFO
+   #[serde(default, skip_serializing_if = "Vec::is_empty")]
+   channels: Vec<Channel>,
+   /// Frequency correction in ppm of every device by its args
+   #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+   ppm: BTreeMap<String, f64>,
+   #[serde(default)]
    bookmarks: Vec<Bookmark>,
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize)]
 struct Source {
     frequency: u64,
     gain: f64,
     rate: f64,
     args: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    antenna: Option<String>,
+    #[serde(default)]
+    agc: bool,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    gain_elements: BTreeMap<String, f64>,
 }
 
-#[derive(Deserialize)]
-struct Bookmark {
-    name: String,
-    frequency: u64,
+#[derive(Serialize, Deserialize, Default)]
+struct Audio {
+    region: Region,
+    /// Times of the custom AGC, the defaults are used when they aren't set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    custom_agc: Option<AgcTimes>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Vfo {
+    step: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Recording {
+    /// Directory of the IQ and audio recordings
+    directory: String,
 }
 
 struct YasaApp<'a> {
-    radio: FMRadio<'a>,
+    radio: Radio<'a>,
     is_running: bool,
     current_freq: u64,
     config: Config,
+    waterfall: Waterfall,
+    // Tuning step of the panadapter and the arrow keys in Hz
+    step: u64,
+    frequency_entry: FrequencyEntry,
+    history: History,
+    bookmark_panel: BookmarkPanel,
+    device_panel: DevicePanel,
+    scanner_panel: ScannerPanel,
+    record_format: SampleFormat,
+    record_source: RecordSource,
+    audio_format: AudioFormat,
+    // Audio is recorded only while the squelch is open
+    squelch_triggered: bool,
+    // Last error shown to the user
+    error: Option<String>,
+    // Config file which couldn't be read. It isn't saved, so the user's settings aren't lost.
+    config_error: Option<String>,
 }
 
+// Tuning steps offered in the toolbar
+const STEPS: [u64; 9] = [100, 1_000, 5_000, 6_250, 9_000, 10_000, 12_500, 25_000, 100_000];
+
 impl Default for Config {
     fn default() -> Self {
         Self { 
             source: Default::default(), 
+            audio: Default::default(),
+            vfo: Default::default(),
+            recording: Default::default(),
+            scanner: Default::default(),
+            channels: Vec::default(),
+            ppm: BTreeMap::new(),
             bookmarks: Vec::default() 
         }
     }
@@ -52,12 +141,27 @@ impl Default for Source {
             gain: 30.0, 
             rate: 1_000_000.0, 
             args: String::default(), 
+            antenna: None,
+            agc: false,
+            gain_elements: BTreeMap::new(),
         }
     }
 }
 
+impl Default for Vfo {
+    fn default() -> Self {
+        Self { step: 1_000 }
+    }
+}
+
+impl Default for Recording {
+    fn default() -> Self {
+        Self { directory: ".".to_string() }
+    }
+}
+
 impl<'a> YasaApp<'a> {
-    fn new(cc: &CreationContext<'_>, radio: FMRadio<'a>, config: Config) -> Self {
+    fn new(cc: &CreationContext<'_>, radio: Radio<'a>, config: Config, config_error: Option<String>) -> Self {
         // init GUI
         cc.egui_ctx.set_zoom_factor(1.5);
 
@@ -65,7 +169,20 @@ impl<'a> YasaApp<'a> {
             radio,
             is_running: false,
             current_freq: config.source.frequency,
+            step: config.vfo.step,
+            frequency_entry: FrequencyEntry::new(),
+            history: History::new(),
+            bookmark_panel: BookmarkPanel::new(),
+            device_panel: DevicePanel::new(radio_device(&config)),
+            scanner_panel: ScannerPanel::new(),
+            record_format: SampleFormat::Cf32,
+            record_source: RecordSource::Raw,
+            audio_format: AudioFormat::Wav,
+            squelch_triggered: false,
+            error: None,
+            config_error,
             config,
+            waterfall: Waterfall::new(),
         }
     }
     
@@ -81,55 +198,645 @@ impl<'a> YasaApp<'a> {
                     if run_btn.clicked() {
                         self.play_stop_action()
                     }
+                    if ui.button("Device").clicked() {
+                        self.device_panel.open(self.radio.device());
+                    }
+
+                    if ui.button("⏴").on_hover_text("Back (Alt+Left)").clicked() {
+                        self.back_action();
+                    }
+                    if ui.button("⏵").on_hover_text("Forward (Alt+Right)").clicked() {
+                        self.forward_action();
+                    }
+                    match self.frequency_entry.show(ui, self.current_freq) {
+                        Some(Entry::Step(freq)) => { self.tune_action(freq); }
+                        Some(Entry::Jump(freq)) => self.jump_action(freq),
+                        None => (),
+                    }
+
+                    let mut mode = self.radio.mode();
+                    egui::ComboBox::from_id_source("mode")
+                        .selected_text(mode.name())
+                        .show_ui(ui, |ui| {
+                            for m in Mode::ALL {
+                                ui.selectable_value(&mut mode, m, m.name());
+                            }
+                        });
+                    if mode != self.radio.mode() {
+                        self.radio.set_mode(mode);
+                    }
+
+                    egui::ComboBox::from_id_source("step")
+                        .selected_text(step_name(self.step))
+                        .show_ui(ui, |ui| {
+                            for step in STEPS {
+                                ui.selectable_value(&mut self.step, step, step_name(step));
+                            }
+                        });
+
+                    self.demod_settings_ui(ui);
                 });
             });
 
         egui::SidePanel::left("source_panel")
             .resizable(true)
-            .default_width(150.0)
-            .width_range(80.0..=200.0)
+            .default_width(200.0)
+            .width_range(120.0..=320.0)
             .show(ctx, |ui| {
-                let mut new_freq = 0;
-                for bookmark in &self.config.bookmarks {
-                    if ui.link(&bookmark.name).clicked() {
-                        new_freq = bookmark.frequency;
-                    }
+                egui::CollapsingHeader::new("Receiver").default_open(true).show(ui, |ui| {
+                    self.receiver_ui(ui);
+                });
+                egui::CollapsingHeader::new("Recording").default_open(false).show(ui, |ui| {
+                    self.recording_ui(ui);
+                });
+                egui::CollapsingHeader::new("Channels").default_open(false).show(ui, |ui| {
+                    self.channels_ui(ui);
+                });
+                egui::CollapsingHeader::new("Scanner").default_open(false).show(ui, |ui| {
+                    self.scanner_ui(ui);
+                });
+                ui.separator();
+
+                let snapshot = self.bookmark_snapshot();
+                match self.bookmark_panel.show(ui, &mut self.config.bookmarks, snapshot) {
+                    Some(Action::Tune(bookmark)) => self.bookmark_action(&bookmark),
+                    Some(Action::Save) => self.save_config(),
+                    None => {}
                 }
-                if new_freq > 0 { self.tune_action(new_freq); }
             });
 
         // Needs to be last
         egui::CentralPanel::default()
             .show(ctx, |ui| {
-                ui.label(format!("f =  {}", self.current_freq));
+                if let Some(error) = &self.config_error {
+                    ui.colored_label(ui.visuals().error_fg_color, format!("Settings are not saved. {error}"));
+                }
+                if let Some(error) = &self.error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+                if let Some(squelch) = self.radio.squelch() {
+                    let state = if squelch.open { "open" } else { "closed" };
+                    ui.label(format!("Squelch: {state} ({:.1} dB)", squelch.level));
+                }
+                if self.radio.mode() == Mode::Wfm && self.is_running {
+                    ui.label(if self.radio.is_stereo() { "Stereo" } else { "Mono" });
+                    if let Some(rds) = self.radio.rds() {
+                        ui.heading(&rds.ps);
+                        ui.label(&rds.radiotext);
+                        if let Some(pi) = rds.pi {
+                            ui.label(format!("PI: {pi:04X}"));
+                        }
+                        if let Some(pty) = rds.pty {
+                            ui.label(format!("PTY: {}", pty_name(pty)));
+                        }
+                        if let Some(time) = rds.time {
+                            ui.label(format!("Time: {time}"));
+                        }
+                    }
+                }
+
+                for spectrum in self.radio.spectrum() {
+                    self.waterfall.push(spectrum);
+                }
+                let vfo = self.current_freq as f64;
+                let center = self.radio.center_freq().unwrap_or(vfo);
+                let channels: Vec<f64> = self.radio.channels().iter().map(|c| c.frequency as f64).collect();
+                if let Some(freq) = self.waterfall.show(ui, center, self.radio.rate(), vfo, &channels, self.step as f64) {
+                    if freq as u64 != self.current_freq {
+                        self.tune_action(freq as u64);
+                    }
+                }
+            });
+    }
+
+    // Filter settings of the current mode
+    fn demod_settings_ui(&mut self, ui: &mut egui::Ui) {
+        let mode = self.radio.mode();
+        let mut settings = self.radio.settings();
+
+        let bandwidths = mode.bandwidths();
+        if bandwidths.len() > 1 {
+            egui::ComboBox::from_id_source("bandwidth")
+                .selected_text(format!("{} kHz", settings.bandwidth / 1000.0))
+                .show_ui(ui, |ui| {
+                    for &bw in bandwidths {
+                        ui.selectable_value(&mut settings.bandwidth, bw, format!("{} kHz", bw / 1000.0));
+                    }
+                });
+        }
+        if mode == Mode::Usb || mode == Mode::Lsb {
+            let (low, high) = &mut settings.passband;
+            ui.label("Passband");
+            ui.add(egui::DragValue::new(low).clamp_range(0.0..=*high - 100.0).speed(10.0).suffix(" Hz"));
+            ui.add(egui::DragValue::new(high).clamp_range(*low + 100.0..=4_000.0).speed(10.0).suffix(" Hz"));
+        }
+        if mode == Mode::Nfm {
+            ui.checkbox(&mut settings.nfm_deemphasis, "De-emphasis");
+        }
+        if mode == Mode::Cw {
+            ui.label("Pitch");
+            ui.add(egui::DragValue::new(&mut settings.cw_pitch).clamp_range(300.0..=1_200.0).speed(10.0).suffix(" Hz"));
+        }
+
+        Self::squelch_ui(ui, mode, &mut settings.squelch);
+
+        ui.label("AGC");
+        egui::ComboBox::from_id_source("agc")
+            .selected_text(settings.agc.name())
+            .show_ui(ui, |ui| {
+                for agc in AgcPreset::ALL {
+                    ui.selectable_value(&mut settings.agc, agc, agc.name());
+                }
+            });
+        if settings.agc == AgcPreset::Custom {
+            let times = &mut settings.custom_agc;
+            ui.label("Attack");
+            ui.add(egui::DragValue::new(&mut times.attack).clamp_range(0.0001..=1.0).speed(0.001).suffix(" s"));
+            ui.label("Decay");
+            ui.add(egui::DragValue::new(&mut times.decay).clamp_range(0.01..=10.0).speed(0.01).suffix(" s"));
+            ui.label("Hang");
+            ui.add(egui::DragValue::new(&mut times.hang).clamp_range(0.0..=10.0).speed(0.01).suffix(" s"));
+        }
+
+        if settings != self.radio.settings() {
+            // Custom AGC is kept for the next start
+            if settings.custom_agc != self.radio.settings().custom_agc {
+                self.config.audio.custom_agc = Some(settings.custom_agc);
+                self.save_config();
+            }
+            self.radio.set_settings(settings);
+        }
+    }
+
+    fn squelch_ui(ui: &mut egui::Ui, mode: Mode, squelch: &mut SquelchSettings) {
+        // Noise squelch works only for FM
+        let is_fm = mode == Mode::Wfm || mode == Mode::Nfm;
+        ui.label("Squelch");
+        egui::ComboBox::from_id_source("squelch")
+            .selected_text(squelch.mode.name())
+            .show_ui(ui, |ui| {
+                for m in SquelchMode::ALL {
+                    if m != SquelchMode::Noise || is_fm {
+                        ui.selectable_value(&mut squelch.mode, m, m.name());
+                    }
+                }
             });
+        match squelch.mode {
+            SquelchMode::Power => {
+                ui.add(egui::DragValue::new(&mut squelch.power_threshold).clamp_range(-120.0..=0.0).speed(0.5).suffix(" dBFS"));
+            }
+            SquelchMode::Noise if is_fm => {
+                ui.add(egui::DragValue::new(&mut squelch.noise_threshold).clamp_range(-60.0..=0.0).speed(0.5).suffix(" dB"));
+            }
+            _ => (),
+        }
     }
 
     // Application wide shortcuts
     fn handle_shortcuts(&mut self, ctx: &egui::Context) {
+        // Keys go to the text field while typing
+        if ctx.wants_keyboard_input() {
+            return;
+        }
         if ctx.input(|i| i.key_pressed(Key::P))  { 
             self.play_stop_action() 
         }
+        let alt = ctx.input(|i| i.modifiers.alt);
+        if ctx.input(|i| i.key_pressed(Key::ArrowLeft)) {
+            if alt { self.back_action() } else { self.tune_action(self.current_freq.saturating_sub(self.step)); }
+        }
+        if ctx.input(|i| i.key_pressed(Key::ArrowRight)) {
+            if alt { self.forward_action() } else { self.tune_action(self.current_freq + self.step); }
+        }
+        if ctx.input(|i| i.key_pressed(Key::ArrowUp)) {
+            self.tune_action(self.current_freq + self.step);
+        }
+        if ctx.input(|i| i.key_pressed(Key::ArrowDown)) {
+            self.tune_action(self.current_freq.saturating_sub(self.step));
+        }
     }
 
     // Start/Stop radio action
     fn play_stop_action(&mut self) {
         if self.is_running {
-            self.radio.stop().expect("Can't stop radio");
-            self.is_running = false;
+            let result = self.radio.stop();
+            self.show_result("Can't stop radio", result);
+            self.waterfall.clear();
         } else {
-            if self.radio.start(self.current_freq as f64).is_ok() {
-                self.is_running = true;
+            let result = self.radio.start(self.current_freq as f64);
+            self.show_result("Can't start radio", result);
+        }
+        self.is_running = self.radio.is_running();
+        self.sync_frequency();
+    }
+
+    // Played file may not contain the frequency, so the radio tunes to other one
+    fn sync_frequency(&mut self) {
+        if let Some(frequency) = self.radio.frequency() {
+            self.current_freq = frequency.round() as u64;
+        }
+    }
+
+    // Apply the settings from the device panel and save them in the config
+    fn device_action(&mut self, mut device: DeviceSettings) {
+        // Gain and correction are set by the live controls
+        let current = self.radio.device();
+        device.gain = current.gain;
+        device.agc = current.agc;
+        if device.args == current.args {
+            device.gain_elements = current.gain_elements.clone();
+            device.ppm = current.ppm;
+        } else {
+            device.gain_elements.clear();
+            device.ppm = self.config.ppm.get(&device.args).copied().unwrap_or(0.0);
+        }
+
+        let result = self.radio.set_device(device);
+        self.is_running = self.radio.is_running();
+        self.sync_frequency();
+        self.waterfall.clear();
+        match result {
+            Ok(_) => {
+                self.device_panel.set_error(None);
+                self.error = None;
+                self.save_device();
+            }
+            Err(e) => {
+                log::error!("Can't apply device settings: {e}");
+                self.device_panel.set_error(Some(e.to_string()));
             }
         }
     }
 
-    // tune to given frequency
-    fn tune_action(&mut self, new_freq: u64) {
-        if new_freq > 10 && new_freq < 1_500_000_000 {
-            self.radio.tune_to(new_freq as f64).expect("Tune error"); 
+    // Gain, AGC and the frequency correction of the running device
+    fn receiver_ui(&mut self, ui: &mut egui::Ui) {
+        let device = self.radio.device().clone();
+        let capabilities = self.radio.capabilities().cloned();
+        let mut save = false;
+
+        if let Some((mut position, duration)) = self.radio.playback() {
+            let response = ui.add(egui::Slider::new(&mut position, 0.0..=duration).text("Position").suffix(" s"));
+            if response.changed() {
+                let result = self.radio.seek(position);
+                self.show_result("Can't seek", result);
+            }
+        }
+
+        let (min_gain, max_gain) = capabilities.as_ref().map_or((0.0, 50.0), |c| c.gain);
+        let mut gain = device.gain;
+        let response = ui.add(egui::Slider::new(&mut gain, min_gain..=max_gain).text("Gain"));
+        if response.changed() {
+            let result = self.radio.set_gain(gain);
+            self.show_result("Can't set gain", result);
+        }
+        save |= response.drag_released();
+
+        // Stages are shown only when the device has more than one
+        let elements = capabilities.as_ref().map(|c| c.gain_elements.clone()).unwrap_or_default();
+        if elements.len() > 1 {
+            for (name, (min, max)) in &elements {
+                let mut value = self.radio.gain_element(name).unwrap_or(*min);
+                let response = ui.add(egui::Slider::new(&mut value, *min..=*max).text(name));
+                if response.changed() {
+                    let result = self.radio.set_gain_element(name, value);
+                    self.show_result("Can't set gain", result);
+                }
+                save |= response.drag_released();
+            }
+        }
+
+        if capabilities.as_ref().is_some_and(|c| c.supports_agc) {
+            let mut agc = device.agc;
+            if ui.checkbox(&mut agc, "Hardware AGC").changed() {
+                let result = self.radio.set_agc(agc);
+                self.show_result("Can't set AGC", result);
+                save = true;
+            }
+        }
+
+        let mut ppm = device.ppm;
+        let response = ui.add(egui::DragValue::new(&mut ppm).speed(0.1).clamp_range(-200.0..=200.0).prefix("Correction: ").suffix(" ppm"));
+        if response.changed() {
+            let result = self.radio.set_ppm(ppm);
+            self.show_result("Can't set correction", result);
+        }
+        save |= response.drag_released() || response.lost_focus();
+
+        if save {
+            self.save_device();
+        }
+    }
+
+    // IQ recording to SigMF files and the audio recording
+    fn recording_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Directory");
+            if ui.text_edit_singleline(&mut self.config.recording.directory).lost_focus() {
+                self.save_config();
+            }
+        });
+        ui.separator();
+
+        let status = self.radio.recording();
+        ui.add_enabled_ui(status.is_none(), |ui| {
+            egui::Grid::new("recording").num_columns(2).show(ui, |ui| {
+                ui.label("Signal");
+                egui::ComboBox::from_id_source("record_source")
+                    .selected_text(record_source_name(self.record_source))
+                    .show_ui(ui, |ui| {
+                        for source in [RecordSource::Raw, RecordSource::Channel] {
+                            ui.selectable_value(&mut self.record_source, source, record_source_name(source));
+                        }
+                    });
+                ui.end_row();
+                ui.label("Format");
+                egui::ComboBox::from_id_source("record_format")
+                    .selected_text(self.record_format.name())
+                    .show_ui(ui, |ui| {
+                        for format in SampleFormat::ALL {
+                            ui.selectable_value(&mut self.record_format, format, format.name());
+                        }
+                    });
+                ui.end_row();
+            });
+        });
+
+        match status {
+            Some(status) => {
+                if ui.button("Stop recording").clicked() {
+                    let result = self.radio.stop_recording();
+                    self.show_result("Can't finish recording", result);
+                }
+                let name = status.path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                ui.label(format!("{name}\n{:.1} s, {:.1} MB", status.seconds, status.size as f64 / 1e6));
+            }
+            None => {
+                let record = ui.add_enabled(self.is_running, egui::Button::new("Record"));
+                if record.clicked() {
+                    let directory = Path::new(&self.config.recording.directory);
+                    let result = self.radio.start_recording(directory, self.record_format, self.record_source);
+                    self.show_result("Can't start recording", result.map(|_| ()));
+                }
+            }
+        }
+        ui.separator();
+
+        let audio_status = self.radio.audio_recording();
+        ui.add_enabled_ui(audio_status.is_none(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Audio");
+                egui::ComboBox::from_id_source("audio_format")
+                    .selected_text(self.audio_format.name())
+                    .show_ui(ui, |ui| {
+                        for format in AudioFormat::ALL {
+                            ui.selectable_value(&mut self.audio_format, format, format.name());
+                        }
+                    });
+            });
+            ui.checkbox(&mut self.squelch_triggered, "Only while squelch is open")
+                .on_hover_text("Every transmission is saved to its own file");
+        });
+
+        match audio_status {
+            Some(status) => {
+                if ui.button("Stop audio").clicked() {
+                    let result = self.radio.stop_audio_recording();
+                    self.show_result("Can't finish audio recording", result);
+                }
+                let current = match status.path.as_ref().and_then(|path| path.file_name()) {
+                    Some(name) => format!("{}\n{:.1} s", name.to_string_lossy(), status.seconds),
+                    None => "Waiting for squelch".to_string(),
+                };
+                ui.label(format!("{current}\n{} files", status.files));
+            }
+            None => {
+                let record = ui.add_enabled(self.is_running, egui::Button::new("Record audio"));
+                if record.clicked() {
+                    let directory = Path::new(&self.config.recording.directory);
+                    let result = self.radio.start_audio_recording(directory, self.audio_format, self.squelch_triggered);
+                    self.show_result("Can't start audio recording", result);
+                }
+            }
+        }
+    }
+
+    // Channels demodulated next to the VFO. Their audio is recorded like the audio of the VFO.
+    fn channels_ui(&mut self, ui: &mut egui::Ui) {
+        let status: Vec<ChannelStatus> = (0..self.radio.channels().len()).map(|i| self.radio.channel_status(i)).collect();
+        let action = channel_panel::show(ui, self.radio.channels(), &status, self.radio.vfo_mute(), self.is_running);
+        match action {
+            Some(ChannelAction::Add) => {
+                let settings = self.radio.settings();
+                let mut squelch = settings.squelch;
+                // Channel without the squelch would only add noise to the mix
+                if squelch.mode == SquelchMode::Off {
+                    squelch.mode = SquelchMode::Power;
+                }
+                let channel = Channel {
+                    frequency: self.current_freq,
+                    mode: self.radio.mode(),
+                    bandwidth: settings.bandwidth,
+                    squelch,
+                    mute: false,
+                    solo: false,
+                };
+                let result = self.radio.add_channel(channel);
+                self.is_running = self.radio.is_running();
+                self.show_result("Can't add channel", result);
+                self.save_channels();
+            }
+            Some(ChannelAction::Remove(i)) => {
+                let result = self.radio.remove_channel(i);
+                self.is_running = self.radio.is_running();
+                self.show_result("Can't remove channel", result);
+                self.save_channels();
+            }
+            Some(ChannelAction::Update(i, channel)) => {
+                let result = self.radio.set_channel(i, channel);
+                self.show_result("Can't change channel", result);
+            }
+            Some(ChannelAction::Save) => self.save_channels(),
+            Some(ChannelAction::Tune(i)) => {
+                let frequency = self.radio.channels()[i].frequency;
+                self.jump_action(frequency);
+            }
+            Some(ChannelAction::Record(i)) => {
+                let directory = Path::new(&self.config.recording.directory);
+                let result = self.radio.start_channel_recording(i, directory, self.audio_format, self.squelch_triggered);
+                self.show_result("Can't start audio recording", result);
+            }
+            Some(ChannelAction::StopRecord(i)) => {
+                let result = self.radio.stop_channel_recording(i);
+                self.show_result("Can't finish audio recording", result);
+            }
+            Some(ChannelAction::MuteVfo(mute)) => self.radio.set_vfo_mute(mute),
+            None => {}
+        }
+    }
+
+    fn save_channels(&mut self) {
+        self.config.channels = self.radio.channels().to_vec();
+        self.save_config();
+    }
+
+    // Scan settings are saved with the lockouts and priorities
+    fn scanner_ui(&mut self, ui: &mut egui::Ui) {
+        let has_squelch = self.radio.squelch().is_some();
+        let config = &mut self.config;
+        if self.scanner_panel.show(ui, &mut config.scanner, &config.bookmarks, self.is_running, has_squelch) {
+            self.save_config();
+        }
+    }
+
+    // Store the device settings in the config. Correction is kept for every device.
+    fn save_device(&mut self) {
+        let device = self.radio.device();
+        let source = &mut self.config.source;
+        source.args = device.args.clone();
+        source.rate = device.rate;
+        source.gain = device.gain;
+        source.gain_elements = device.gain_elements.clone();
+        source.agc = device.agc;
+        source.antenna = device.antenna.clone();
+        self.config.ppm.insert(device.args.clone(), device.ppm);
+        self.save_config();
+    }
+
+    // Errors are shown in the central panel
+    fn show_result(&mut self, context: &str, result: futuresdr::anyhow::Result<()>) {
+        match result {
+            Ok(_) => self.error = None,
+            Err(e) => {
+                log::error!("{context}: {e}");
+                self.error = Some(format!("{context}: {e}"));
+            }
+        }
+    }
+
+    // tune to given frequency. Returns false if the device can't receive it.
+    fn tune_action(&mut self, new_freq: u64) -> bool {
+        if !self.radio.is_valid_frequency(new_freq as f64) {
+            log::warn!("Frequency {new_freq} out of the device range");
+            return false;
+        }
+        let result = self.radio.tune_to(new_freq as f64);
+        let tuned = result.is_ok();
+        self.show_result("Tune error", result);
+        if tuned {
             self.current_freq = new_freq;
         }
+        tuned
+    }
+
+    // Tune to the entered or bookmarked frequency and remember where we came from
+    fn jump_action(&mut self, new_freq: u64) {
+        let old_freq = self.current_freq;
+        if new_freq != old_freq && self.tune_action(new_freq) {
+            self.history.push(old_freq);
+        }
+    }
+
+    // Tune to the bookmark and restore its demodulator settings
+    fn bookmark_action(&mut self, bookmark: &Bookmark) {
+        self.jump_action(bookmark.frequency);
+        if let Some(mode) = bookmark.mode {
+            if mode != self.radio.mode() {
+                self.radio.set_mode(mode);
+            }
+        }
+        let mut settings = self.radio.settings();
+        if let Some(bandwidth) = bookmark.bandwidth {
+            settings.bandwidth = bandwidth;
+        }
+        if let Some(squelch) = bookmark.squelch {
+            settings.squelch = squelch;
+        }
+        if settings != self.radio.settings() {
+            self.radio.set_settings(settings);
+        }
+    }
+
+    // Bookmark of the current frequency and settings
+    fn bookmark_snapshot(&self) -> Bookmark {
+        let settings = self.radio.settings();
+        Bookmark {
+            name: format!("{:.3} MHz", self.current_freq as f64 / 1e6),
+            frequency: self.current_freq,
+            group: String::new(),
+            mode: Some(self.radio.mode()),
+            bandwidth: Some(settings.bandwidth),
+            tags: vec![],
+            squelch: Some(settings.squelch),
+        }
+    }
+
+    // Comments and the keys unknown to the app stay in the file
+    fn save_config(&self) {
+        if self.config_error.is_some() {
+            return;
+        }
+        if let Err(e) = config_file::save(Path::new(CONFIG_PATH), &self.config) {
+            log::error!("Can't save config: {e}");
+        }
+    }
+
+    // Let the scanner tune by the squelch of the current channel
+    fn scan(&mut self, now: f64) {
+        if !self.is_running {
+            self.scanner_panel.stop();
+            return;
+        }
+        let squelch_open = self.radio.squelch().is_some_and(|s| s.open);
+        if let Some(frequency) = self.scanner_panel.update(now, squelch_open) {
+            if !self.tune_action(frequency) {
+                self.scanner_panel.skip();
+            }
+        }
+    }
+
+    // History moves only when the tune succeeds, so the entry isn't lost
+    fn back_action(&mut self) {
+        let old_freq = self.current_freq;
+        if self.history.previous().is_some_and(|freq| self.tune_action(freq)) {
+            self.history.back(old_freq);
+        }
+    }
+
+    fn forward_action(&mut self) {
+        let old_freq = self.current_freq;
+        if self.history.next().is_some_and(|freq| self.tune_action(freq)) {
+            self.history.forward(old_freq);
+        }
+    }
+}
+
+// Device settings from the config
+fn radio_device(config: &Config) -> DeviceSettings {
+    let source = &config.source;
+    DeviceSettings {
+        args: source.args.clone(),
+        rate: source.rate,
+        gain: source.gain,
+        gain_elements: source.gain_elements.clone(),
+        agc: source.agc,
+        ppm: config.ppm.get(&source.args).copied().unwrap_or(0.0),
+        antenna: source.antenna.clone(),
+    }
+}
+
+// Label of the signal written to the IQ recording
+fn record_source_name(source: RecordSource) -> &'static str {
+    match source {
+        RecordSource::Raw => "Raw IQ",
+        RecordSource::Channel => "Channel",
+    }
+}
+
+fn step_name(step: u64) -> String {
+    if step < 1_000 {
+        format!("{step} Hz")
+    } else {
+        format!("{} kHz", step as f64 / 1_000.0)
     }
 }
 
@@ -137,7 +844,25 @@ impl<'a> YasaApp<'a> {
 impl eframe::App for YasaApp<'_> {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         self.draw_ui(ctx);
+        let current = (self.radio.device(), self.radio.capabilities());
+        if let Some(device) = self.device_panel.show(ctx, current) {
+            self.device_action(device);
+        }
         self.handle_shortcuts(ctx);
+        self.scan(ctx.input(|i| i.time));
+
+        // Flowgraph ends by itself when the device fails or the file was played
+        if self.is_running {
+            if let Err(e) = self.radio.check() {
+                self.show_result("Radio stopped", Err(e));
+            }
+            self.is_running = self.radio.is_running();
+        }
+
+        // Keep the spectrum and the receiver state fresh
+        if self.is_running {
+            ctx.request_repaint_after(Duration::from_millis(40));
+        }
     }
 
 }
@@ -145,13 +870,31 @@ impl eframe::App for YasaApp<'_> {
 
 fn main() -> Result<(), eframe::Error> {
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
-    let config = match fs::read_to_string("config.toml") {
-        Ok(c) => toml::from_str(&c).unwrap(),
-        Err(_) => Config::default(),
+    let (config, config_error) = match fs::read_to_string(CONFIG_PATH) {
+        Ok(c) => match toml::from_str(&c) {
+            Ok(config) => (config, None),
+            Err(e) => {
+                log::error!("Can't read {CONFIG_PATH}, using the defaults: {e}");
+                (Config::default(), Some(format!("Can't read {CONFIG_PATH}: {e}")))
+            }
+        },
+        Err(_) => (Config::default(), None),
     };
 
     // Init backend
-    let radio = FMRadio::init(config.source.gain, config.source.rate, &config.source.args);
+    let mut radio = Radio::init(radio_device(&config));
+    let mut settings = radio.settings();
+    settings.region = config.audio.region;
+    if let Some(times) = config.audio.custom_agc {
+        settings.custom_agc = times;
+    }
+    radio.set_settings(settings);
+    for channel in &config.channels {
+        // Radio isn't running yet, so the channel is only added to the list
+        if let Err(e) = radio.add_channel(channel.clone()) {
+            log::warn!("Skipping channel {}: {e}", channel.frequency);
+        }
+    }
     
     // Init GUI
     let options = eframe::NativeOptions {
@@ -162,6 +905,6 @@ fn main() -> Result<(), eframe::Error> {
     eframe::run_native(
         "Yet Another SDR App",
         options,
-        Box::new(|cc| Box::new(YasaApp::new(cc, radio, config))),
+        Box::new(|cc| Box::new(YasaApp::new(cc, radio, config, config_error))),
     )
 }
@@ -0,0 +1,205 @@
+//! Scanner controls shown in the side panel
+
+use eframe::egui::*;
+
+use crate::backend::scanner::{self, Channel, ScanSettings, Scanner};
+use crate::bookmarks::{groups, Bookmark};
+
+
+// Bookmarks with this tag are the priority channels
+const PRIORITY_TAG: &str = "priority";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScanList {
+    Bookmarks,
+    Range,
+}
+
+pub struct ScannerPanel {
+    list: ScanList,
+    // Bookmark group to scan
+    group: String,
+    // Range in MHz and the step in kHz
+    start: f64,
+    stop: f64,
+    step: f64,
+    scanner: Option<Scanner>,
+    error: Option<String>,
+}
+
+impl ScannerPanel {
+    pub fn new() -> Self {
+        Self {
+            list: ScanList::Bookmarks,
+            group: String::new(),
+            start: 144.0,
+            stop: 146.0,
+            step: 12.5,
+            scanner: None,
+            error: None,
+        }
+    }
+
+    /// Show the channel list, the times and the controls of the running scan.
+    /// Returns true when the settings were changed and should be saved.
+    pub fn show(&mut self, ui: &mut Ui, settings: &mut ScanSettings, bookmarks: &[Bookmark], is_running: bool, has_squelch: bool) -> bool {
+        let mut save = false;
+        let old_settings = settings.clone();
+
+        ui.add_enabled_ui(self.scanner.is_none(), |ui| {
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut self.list, ScanList::Bookmarks, "Bookmarks");
+                ui.radio_value(&mut self.list, ScanList::Range, "Range");
+            });
+            match self.list {
+                ScanList::Bookmarks => {
+                    let groups = groups(bookmarks);
+                    if !groups.contains(&self.group) {
+                        self.group = groups.first().cloned().unwrap_or_default();
+                    }
+                    let group_name = |group: &str| if group.is_empty() { "Ungrouped".to_string() } else { group.to_string() };
+                    ComboBox::from_id_source("scan_group")
+                        .selected_text(group_name(&self.group))
+                        .show_ui(ui, |ui| {
+                            for group in groups {
+                                let name = group_name(&group);
+                                ui.selectable_value(&mut self.group, group, name);
+                            }
+                        });
+                }
+                ScanList::Range => {
+                    Grid::new("scan_range").num_columns(2).show(ui, |ui| {
+                        ui.label("Start");
+                        ui.add(DragValue::new(&mut self.start).speed(0.01).max_decimals(4).clamp_range(0.0..=f64::MAX).suffix(" MHz"));
+                        ui.end_row();
+                        ui.label("Stop");
+                        ui.add(DragValue::new(&mut self.stop).speed(0.01).max_decimals(4).clamp_range(0.0..=f64::MAX).suffix(" MHz"));
+                        ui.end_row();
+                        ui.label("Step");
+                        ui.add(DragValue::new(&mut self.step).speed(0.1).max_decimals(3).clamp_range(0.001..=10_000.0).suffix(" kHz"));
+                        ui.end_row();
+                    });
+                }
+            }
+        });
+
+        // Times are saved when the editing is finished
+        let done = |response: Response| response.drag_released() || response.lost_focus();
+        Grid::new("scan_times").num_columns(2).show(ui, |ui| {
+            ui.label("Dwell");
+            save |= done(ui.add(DragValue::new(&mut settings.dwell).speed(0.01).clamp_range(0.1..=10.0).suffix(" s"))
+                .on_hover_text("Time on every channel without a signal"));
+            ui.end_row();
+            ui.label("Hang");
+            save |= done(ui.add(DragValue::new(&mut settings.hang).speed(0.1).clamp_range(0.0..=60.0).suffix(" s"))
+                .on_hover_text("Time to stay after the signal is gone"));
+            ui.end_row();
+            ui.label("Priority");
+            save |= done(ui.add(DragValue::new(&mut settings.priority_interval).speed(0.1).clamp_range(0.0..=60.0).prefix("every ").suffix(" s"))
+                .on_hover_text(format!("Priority channels are checked between the other ones. Bookmarks tagged \"{PRIORITY_TAG}\" are priority channels.")));
+            ui.end_row();
+        });
+
+        if self.scanner.is_some() {
+            if ui.button("Stop scan").clicked() {
+                self.scanner = None;
+            }
+        } else if ui.add_enabled(is_running, Button::new("Scan")).clicked() {
+            self.start(settings, bookmarks);
+        }
+        if let Some(scanner) = &mut self.scanner {
+            let current = scanner.current();
+            ui.label(format!("{}, {} channels", scanner.state().name(), scanner.channels().len()));
+            if let Some(frequency) = current {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{:.4} MHz", frequency as f64 / 1e6));
+                    if ui.button("Skip").on_hover_text("Go to the next channel").clicked() {
+                        scanner.skip();
+                    }
+                    if ui.button("Lock out").on_hover_text("Skip the channel from now on").clicked() {
+                        scanner.lockout(frequency);
+                    }
+                    let mut priority = scanner.is_priority(frequency);
+                    if ui.checkbox(&mut priority, "Priority").changed() {
+                        scanner.set_priority(frequency, priority);
+                    }
+                });
+            }
+            if !has_squelch {
+                ui.label("Enable the squelch to stop on signals");
+            }
+            // Lockouts and priorities are kept in the config
+            if scanner.settings().lockout != settings.lockout || scanner.settings().priority != settings.priority {
+                settings.lockout = scanner.settings().lockout.clone();
+                settings.priority = scanner.settings().priority.clone();
+                save = true;
+            }
+        }
+
+        if !settings.lockout.is_empty() {
+            CollapsingHeader::new(format!("Locked out ({})", settings.lockout.len())).default_open(false).show(ui, |ui| {
+                let mut unlocked = None;
+                for &frequency in &settings.lockout {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("{:.4} MHz", frequency as f64 / 1e6));
+                        if ui.small_button("Unlock").clicked() {
+                            unlocked = Some(frequency);
+                        }
+                    });
+                }
+                if let Some(frequency) = unlocked {
+                    settings.lockout.retain(|&f| f != frequency);
+                    save = true;
+                }
+            });
+        }
+
+        if let Some(error) = &self.error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+
+        if *settings != old_settings {
+            if let Some(scanner) = &mut self.scanner {
+                scanner.set_settings(settings.clone());
+            }
+        }
+        save
+    }
+
+    /// Advance the running scan to the time `now` in seconds. Returns the frequency to tune to.
+    pub fn update(&mut self, now: f64, squelch_open: bool) -> Option<u64> {
+        self.scanner.as_mut().and_then(|scanner| scanner.update(now, squelch_open))
+    }
+
+    /// Leave the channel which couldn't be tuned
+    pub fn skip(&mut self) {
+        if let Some(scanner) = &mut self.scanner {
+            scanner.skip();
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.scanner = None;
+    }
+
+    fn start(&mut self, settings: &ScanSettings, bookmarks: &[Bookmark]) {
+        let channels = match self.list {
+            ScanList::Bookmarks => Ok(bookmarks.iter()
+                .filter(|b| b.group == self.group)
+                .map(|b| Channel { frequency: b.frequency, priority: b.tags.iter().any(|t| t.eq_ignore_ascii_case(PRIORITY_TAG)) })
+                .collect()),
+            ScanList::Range => {
+                let hz = |value: f64, unit: f64| (value * unit).round() as u64;
+                scanner::range(hz(self.start, 1e6), hz(self.stop, 1e6), hz(self.step, 1e3))
+            }
+        };
+        match channels {
+            Ok(channels) if channels.is_empty() => self.error = Some("No channels to scan".to_string()),
+            Ok(channels) => {
+                self.error = None;
+                self.scanner = Some(Scanner::new(channels, settings.clone()));
+            }
+            Err(e) => self.error = Some(e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! Panadapter: spectrum line and the scrolling waterfall with the frequency axis.
+
+use std::collections::VecDeque;
+
+use eframe::egui::*;
+
+
+// Number of the spectra kept in the waterfall
+const HISTORY: usize = 300;
+const AXIS_HEIGHT: f32 = 20.0;
+
+pub struct Waterfall {
+    // Newest first
+    rows: VecDeque<Vec<f32>>,
+    texture: Option<TextureHandle>,
+    dirty: bool,
+    /// Range of the power in dBFS mapped to the colors and the spectrum height
+    pub min_db: f32,
+    pub max_db: f32,
+}
+
+impl Waterfall {
+    pub fn new() -> Self {
+        Self { rows: VecDeque::new(), texture: None, dirty: false, min_db: -110.0, max_db: -20.0 }
+    }
+
+    pub fn push(&mut self, spectrum: Vec<f32>) {
+        if self.rows.front().is_some_and(|r| r.len() != spectrum.len()) {
+            self.rows.clear();
+        }
+        self.rows.push_front(spectrum);
+        self.rows.truncate(HISTORY);
+        self.dirty = true;
+    }
+
+    pub fn clear(&mut self) {
+        self.rows.clear();
+        self.dirty = true;
+    }
+
+    /// Draw spectrum in the upper third, frequency axis and the waterfall below.
+    /// `center`, `span`, the tuned frequency `vfo` and the extra `channels` are in Hz.
+    ///
+    /// Click or drag tunes to the pointer and the scroll wheel moves the VFO by `step`.
+    /// Returns the new VFO frequency.
+    pub fn show(&mut self, ui: &mut Ui, center: f64, span: f64, vfo: f64, channels: &[f64], step: f64) -> Option<f64> {
+        let (rect, response) = ui.allocate_exact_size(ui.available_size(), Sense::click_and_drag());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, Color32::BLACK);
+
+        let spectrum_rect = Rect::from_min_max(rect.min, pos2(rect.max.x, rect.min.y + rect.height() / 3.0));
+        let axis_rect = Rect::from_min_size(spectrum_rect.left_bottom(), vec2(rect.width(), AXIS_HEIGHT));
+        let waterfall_rect = Rect::from_min_max(axis_rect.left_bottom(), rect.max);
+
+        self.draw_spectrum(&painter, spectrum_rect);
+        draw_axis(&painter, axis_rect, center, span);
+        self.draw_waterfall(ui, &painter, waterfall_rect);
+
+        // Tuned frequency and the channels demodulated with it
+        let marker = |freq: f64, color: Color32| {
+            let x = rect.center().x + ((freq - center) / span) as f32 * rect.width();
+            painter.line_segment([pos2(x, rect.min.y), pos2(x, rect.max.y)], Stroke::new(1.0, color));
+        };
+        for &channel in channels {
+            marker(channel, Color32::from_rgba_unmultiplied(255, 255, 0, 96));
+        }
+        marker(vfo, Color32::from_rgba_unmultiplied(255, 0, 0, 128));
+
+        let snap = |freq: f64| (freq / step).round() * step;
+        if response.clicked() || response.dragged() {
+            if let Some(pos) = response.interact_pointer_pos() {
+                let freq = center + ((pos.x - rect.center().x) / rect.width()) as f64 * span;
+                return Some(snap(freq));
+            }
+        }
+        if response.hovered() {
+            let scroll = ui.input(|i| i.scroll_delta.y);
+            if scroll != 0.0 {
+                return Some(snap(vfo) + step * scroll.signum() as f64);
+            }
+        }
+        None
+    }
+
+    fn draw_spectrum(&self, painter: &Painter, rect: Rect) {
+        let Some(spectrum) = self.rows.front() else {
+            return;
+        };
+        let to_screen = emath::RectTransform::from_to(
+            Rect::from_x_y_ranges(0.0..=spectrum.len() as f32, self.max_db..=self.min_db),
+            rect,
+        );
+        let points: Vec<Pos2> = spectrum.iter()
+            .enumerate()
+            .map(|(i, &v)| to_screen * pos2(i as f32, v.clamp(self.min_db, self.max_db)))
+            .collect();
+        painter.add(Shape::line(points, Stroke::new(1.0, Color32::LIGHT_GREEN)));
+    }
+
+    fn draw_waterfall(&mut self, ui: &Ui, painter: &Painter, rect: Rect) {
+        if self.dirty {
+            self.dirty = false;
+            let width = self.rows.front().map_or(1, |r| r.len());
+            let mut image = ColorImage::new([width, HISTORY], Color32::BLACK);
+            for (y, row) in self.rows.iter().enumerate() {
+                for (x, &v) in row.iter().enumerate() {
+                    image.pixels[y * width + x] = color_map((v - self.min_db) / (self.max_db - self.min_db));
+                }
+            }
+            match &mut self.texture {
+                Some(texture) => texture.set(image, TextureOptions::LINEAR),
+                None => self.texture = Some(ui.ctx().load_texture("waterfall", image, TextureOptions::LINEAR)),
+            }
+        }
+        if let Some(texture) = &self.texture {
+            let uv = Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0));
+            painter.image(texture.id(), rect, uv, Color32::WHITE);
+        }
+    }
+}
+
+// Ticks at the round frequencies with the labels in MHz
+fn draw_axis(painter: &Painter, rect: Rect, center: f64, span: f64) {
+    let step = tick_step(span / 8.0);
+    let low = center - span / 2.0;
+    let high = center + span / 2.0;
+    let decimals = (6.0 - step.log10().floor()).max(0.0) as usize;
+    let mut freq = (low / step).ceil() * step;
+    while freq <= high {
+        let x = rect.min.x + ((freq - low) / span) as f32 * rect.width();
+        painter.line_segment([pos2(x, rect.min.y), pos2(x, rect.min.y + 4.0)], Stroke::new(1.0, Color32::GRAY));
+        painter.text(
+            pos2(x, rect.min.y + 4.0),
+            Align2::CENTER_TOP,
+            format!("{:.*}", decimals, freq / 1e6),
+            FontId::proportional(10.0),
+            Color32::GRAY,
+        );
+        freq += step;
+    }
+}
+
+// Smallest 1, 2 or 5 times the power of 10, which is at least `min_step`
+fn tick_step(min_step: f64) -> f64 {
+    let magnitude = 10f64.powf(min_step.log10().floor());
+    [1.0, 2.0, 5.0, 10.0].iter()
+        .map(|m| m * magnitude)
+        .find(|&s| s >= min_step)
+        .unwrap_or(10.0 * magnitude)
+}
+
+// Black, blue, cyan, yellow, red for the power from 0.0 to 1.0
+fn color_map(level: f32) -> Color32 {
+    const COLORS: [(f32, f32, f32); 5] = [(0.0, 0.0, 0.0), (0.0, 0.0, 1.0), (0.0, 1.0, 1.0), (1.0, 1.0, 0.0), (1.0, 0.0, 0.0)];
+    let pos = level.clamp(0.0, 1.0) * (COLORS.len() - 1) as f32;
+    let i = (pos as usize).min(COLORS.len() - 2);
+    let t = pos - i as f32;
+    let (r0, g0, b0) = COLORS[i];
+    let (r1, g1, b1) = COLORS[i + 1];
+    let mix = |a: f32, b: f32| ((a + t * (b - a)) * 255.0) as u8;
+    Color32::from_rgb(mix(r0, r1), mix(g0, g1), mix(b0, b1))
+}